@@ -0,0 +1,53 @@
+;; Example roadii plugin, for `--plugin` or `plugins` in the configuration
+;; file, the same as `etc/roadii/scripts/hammer-on.rhai`. Plugins are usually
+;; compiled to .wasm from another language, but roadii reads the text format
+;; too.
+;;
+;; It lets a fret pressed while another is held down play the note without
+;; strumming, like a hammer-on, by strumming for it.
+(module
+  (import "roadii" "emit" (func $emit (param i32 i32 i32 i32)))
+  (import "roadii" "domain" (func $domain (param i32 i32) (result i32)))
+
+  (memory (export "memory") 1)
+  (data (i32.const 0) "guitar")
+
+  ;; The number of the guitar's domain, and how many frets are held down
+  (global $guitar (mut i32) (i32.const -1))
+  (global $held (mut i32) (i32.const 0))
+
+  (func (export "roadii_abi_version") (result i32)
+    (i32.const 1))
+
+  (func (export "init")
+    (global.set $guitar (call $domain (i32.const 0) (i32.const 6))))
+
+  (func (export "on_event")
+    (param $type i32) (param $code i32) (param $value i32) (param $previous i32)
+    (param $domain i32)
+
+    ;; Every event is passed on as it is
+    (call $emit (local.get $type) (local.get $code) (local.get $value)
+      (local.get $domain))
+
+    ;; Only the guitar's frets, EV_KEY events from BTN_1 to BTN_5
+    (if (i32.ne (local.get $domain) (global.get $guitar)) (then (return)))
+    (if (i32.ne (local.get $type) (i32.const 1)) (then (return)))
+    (if (i32.or
+          (i32.lt_u (local.get $code) (i32.const 0x101))
+          (i32.gt_u (local.get $code) (i32.const 0x105)))
+      (then (return)))
+
+    (if (i32.eqz (local.get $value))
+      (then
+        (if (i32.gt_s (global.get $held) (i32.const 0))
+          (then (global.set $held (i32.sub (global.get $held) (i32.const 1)))))
+        (return)))
+
+    (global.set $held (i32.add (global.get $held) (i32.const 1)))
+
+    ;; Strum down, BTN_DPAD_DOWN, and let go again
+    (if (i32.gt_s (global.get $held) (i32.const 1))
+      (then
+        (call $emit (i32.const 1) (i32.const 0x221) (i32.const 1) (global.get $guitar))
+        (call $emit (i32.const 1) (i32.const 0x221) (i32.const 0) (global.get $guitar))))))
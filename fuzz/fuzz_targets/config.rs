@@ -0,0 +1,14 @@
+//! Feeds anything at all to the configuration file parser, and then works
+//! out the settings for a Wiimote from whatever it makes of it
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use roadii::config::Config;
+
+fuzz_target!(|contents: &str| {
+    if let Ok(config) = Config::parse(contents) {
+        let _ = config.settings_for(None);
+        let _ = config.settings_for(Some("00:11:22:33:44:55"));
+    }
+});
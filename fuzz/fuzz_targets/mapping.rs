@@ -0,0 +1,49 @@
+//! Feeds anything at all to each of the parsers for the mapping syntax, and
+//! checks whatever parses and can be written back out parses the same again
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use roadii::calibration::{Area, Span};
+use roadii::key::{Key, Range, Value};
+use roadii::pipeline::{AxisRange, AxisTransform};
+use roadii::profile::{
+    AxisButton, ButtonAxis, Chord, ChordBinding, EventMap, KeyBinding, LongPressBinding,
+    MacroBinding, Merge,
+};
+use std::fmt::{Debug, Display};
+use std::str::FromStr;
+
+/// Checks that if `s` parses as a `T`, writing it back out parses to the
+/// same `T`
+fn round_trip<T: FromStr + Display + PartialEq + Debug>(s: &str) {
+    let Ok(parsed) = s.parse::<T>() else {
+        return;
+    };
+
+    let written = parsed.to_string();
+    match written.parse::<T>() {
+        Ok(again) => assert_eq!(again, parsed, "{:?} was written out as {:?}", s, written),
+        Err(_) => panic!("{:?} was written out as {:?}, which doesn't parse", s, written),
+    }
+}
+
+fuzz_target!(|s: &str| {
+    round_trip::<Key>(s);
+    round_trip::<Value>(s);
+    round_trip::<Range>(s);
+    round_trip::<Span>(s);
+    round_trip::<Area>(s);
+
+    let _ = AxisRange::from_str(s);
+    let _ = AxisTransform::from_str(s);
+    let _ = KeyBinding::from_str(s);
+    let _ = Chord::from_str(s);
+    let _ = ChordBinding::from_str(s);
+    let _ = LongPressBinding::from_str(s);
+    let _ = MacroBinding::from_str(s);
+    let _ = AxisButton::from_str(s);
+    let _ = ButtonAxis::from_str(s);
+    let _ = EventMap::from_str(s);
+    let _ = Merge::from_str(s);
+});
@@ -0,0 +1,14 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+
+/// What actually does the remapping once everything has been worked out
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// Hand everything over to `evsieve`
+    #[default]
+    Evsieve,
+
+    /// Remap events ourselves, which supports a few things `evsieve` can't
+    Native,
+}
@@ -0,0 +1,71 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use tracing::warn;
+use udev::{Device, Enumerator, Udev};
+
+use crate::notify::Notifier;
+
+/// How often the battery is looked at while remapping
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The charge below which the battery counts as low, if not configured
+pub const DEFAULT_LOW: u8 = 20;
+
+/// Finds the sysfs path of the battery belonging to the Wiimote `hid`
+pub fn find(udev: Udev, hid: &Device) -> Result<Option<PathBuf>> {
+    let mut enumerator =
+        Enumerator::with_udev(udev).context("couldn't start a device enumerator")?;
+    enumerator
+        .match_parent(hid)
+        .context("couldn't set wiimote as parent device matcher")?;
+    enumerator
+        .match_subsystem("power_supply")
+        .context("couldn't set power_supply as device subsystem matcher")?;
+
+    Ok(enumerator
+        .scan_devices()
+        .context("couldn't scan the Wiimote's power supplies")?
+        .next()
+        .map(|battery| battery.syspath().to_path_buf()))
+}
+
+/// How charged the battery at `path` is, as a percentage. This reads sysfs
+/// directly, since udev holds on to the first value it sees.
+pub fn level(path: &Path) -> Result<u8> {
+    let capacity = path.join("capacity");
+
+    fs::read_to_string(&capacity)
+        .with_context(|| format!("couldn't read {:?}", capacity))?
+        .trim()
+        .parse()
+        .with_context(|| format!("couldn't make sense of {:?}", capacity))
+}
+
+/// Keeps an eye on the battery at `path` in the background, warning with
+/// `notifier` too when its charge drops below `low` percent, and again if it
+/// does so after being charged back up
+pub fn watch(path: PathBuf, low: u8, notifier: Notifier) {
+    thread::spawn(move || {
+        let mut warned = false;
+
+        loop {
+            match level(&path) {
+                Ok(level) if level < low && !warned => {
+                    warn!("The Wiimote's battery is down to {}%", level);
+                    notifier.send(&format!("Guitar battery low, down to {}%", level));
+                    warned = true;
+                }
+                Ok(level) if level >= low => warned = false,
+                Ok(_) => {}
+                // It's gone away along with the Wiimote, which is someone
+                // else's problem
+                Err(_) => return,
+            }
+
+            thread::sleep(CHECK_INTERVAL);
+        }
+    });
+}
@@ -0,0 +1,334 @@
+use anyhow::{anyhow, bail, Context, Result};
+use evdev::uinput::VirtualDevice;
+use evdev::{
+    AbsInfo, AbsoluteAxisCode, AttributeSet, BusType, EventType, InputEvent, InputId, KeyCode,
+    UinputAbsSetup,
+};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::backend::Backend;
+use crate::config::Settings;
+use crate::evsieve;
+use crate::key::Key;
+use crate::mapping;
+use crate::native;
+use crate::notify::Notifier;
+use crate::permissions;
+use crate::pipeline::Step;
+use crate::profile::Control;
+
+/// How long to wait for the backend to create its virtual devices
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for a press to come out the other end before giving up
+/// on it
+const PRESS_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long to leave between presses, so they don't queue up behind each
+/// other
+const PRESS_GAP: Duration = Duration::from_millis(20);
+
+/// Measures how long the backend `settings` picks takes to pass a press of
+/// the green fret through the mapping, over `presses` presses. Stands in
+/// for the Wiimote with virtual devices, so none needs to be connected.
+pub fn run(settings: &Settings, evsieve_path: Option<PathBuf>, presses: usize) -> Result<()> {
+    if presses == 0 {
+        bail!("the benchmark needs at least one press");
+    }
+
+    let mut wiimote = fake_device(
+        "wiimote",
+        &[
+            KeyCode::KEY_LEFT,
+            KeyCode::KEY_RIGHT,
+            KeyCode::KEY_UP,
+            KeyCode::KEY_DOWN,
+            KeyCode::KEY_NEXT,
+            KeyCode::KEY_PREVIOUS,
+            KeyCode::BTN_1,
+            KeyCode::BTN_2,
+            KeyCode::BTN_SOUTH,
+            KeyCode::BTN_EAST,
+            KeyCode::BTN_MODE,
+        ],
+        &[],
+    )?;
+    let guitar_axes = [
+        (AbsoluteAxisCode::ABS_X, -32, 31),
+        (AbsoluteAxisCode::ABS_Y, -32, 31),
+        (AbsoluteAxisCode::ABS_HAT0X, 0, 31),
+        (AbsoluteAxisCode::ABS_HAT1X, 0, 15),
+    ];
+    let accel_axes = [
+        (AbsoluteAxisCode::ABS_RX, -500, 500),
+        (AbsoluteAxisCode::ABS_RY, -500, 500),
+        (AbsoluteAxisCode::ABS_RZ, -500, 500),
+    ];
+
+    let mut guitar = fake_device(
+        "guitar",
+        &[
+            KeyCode::BTN_1,
+            KeyCode::BTN_2,
+            KeyCode::BTN_3,
+            KeyCode::BTN_4,
+            KeyCode::BTN_5,
+            KeyCode::BTN_DPAD_UP,
+            KeyCode::BTN_DPAD_DOWN,
+            KeyCode::BTN_START,
+            KeyCode::BTN_SELECT,
+        ],
+        &guitar_axes,
+    )?;
+    let mut accel = fake_device("accelerometer", &[], &accel_axes)?;
+
+    // The pipeline is built before it's pointed at the fake devices, so it
+    // needs telling what their axes span
+    let ranges = [("guitar", &guitar_axes[..]), ("accel", &accel_axes[..])]
+        .into_iter()
+        .flat_map(|(domain, axes)| {
+            axes.iter()
+                .map(move |&(axis, min, max)| ((domain.to_owned(), axis.0), (min, max)))
+        })
+        .collect();
+    let mut pipeline = mapping::replay_pipeline(settings, &ranges)?;
+
+    for step in &mut pipeline.steps {
+        match step {
+            Step::Input { path, domain } => {
+                *path = match domain.as_str() {
+                    "wiimote" => devnode(&mut wiimote)?,
+                    "guitar" => devnode(&mut guitar)?,
+                    "accel" => devnode(&mut accel)?,
+                    _ => bail!(
+                        "the benchmark can only stand in for the Wiimote, guitar and accelerometer, not @{}",
+                        domain
+                    ),
+                };
+            }
+            // Keep out of the way of anything already remapping
+            Step::Output { name, .. } => name.push_str(" (benchmark)"),
+            _ => {}
+        }
+    }
+
+    let green = Control::Green
+        .button()
+        .expect("green is a button")
+        .parse::<Key>()?;
+    let expected = native::expected(&pipeline, &green)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| {
+            anyhow!("green doesn't do anything on its own, so there's nothing to time")
+        })?;
+
+    // The output the press ends up on, to watch
+    let domain = expected
+        .rsplit_once('@')
+        .map(|(_, domain)| domain.to_owned())
+        .unwrap_or_default();
+    let name = pipeline
+        .steps
+        .iter()
+        .find_map(|step| match step {
+            Step::Output { keys, name, .. }
+                if keys.first().and_then(|key| key.domain.as_deref()) == Some(&domain) =>
+            {
+                Some(name.clone())
+            }
+            _ => None,
+        })
+        .ok_or_else(|| anyhow!("couldn't work out where {} comes out", expected))?;
+
+    let backend = settings.backend.unwrap_or_default();
+    let (mut evsieve, native) = match backend {
+        Backend::Evsieve => {
+            let (path, _) = evsieve::find(evsieve_path)?;
+
+            (
+                Some(
+                    Command::new(path)
+                        .args(evsieve::arguments(&pipeline)?)
+                        .spawn()
+                        .context("couldn't start evsieve")?,
+                ),
+                None,
+            )
+        }
+        Backend::Native => {
+            let pipeline = pipeline.clone();
+            let native = thread::spawn(move || {
+                native::run(
+                    &pipeline,
+                    Notifier::silent(),
+                    None,
+                    None,
+                    None,
+                    false,
+                    |_, _| {},
+                )
+            });
+            (None, Some(native))
+        }
+    };
+
+    let result = measure(&mut guitar, &green, &name, &domain, &expected, presses);
+
+    if let Some(evsieve) = &mut evsieve {
+        let _ = evsieve.kill();
+        let _ = evsieve.wait();
+    }
+
+    // If the backend fell over, that's more interesting than it not working
+    if let Some(native) = native.filter(|native| result.is_err() && native.is_finished()) {
+        native
+            .join()
+            .map_err(|_| anyhow!("the native backend panicked"))??;
+    }
+
+    let mut latencies = result?;
+    latencies.sort();
+
+    let percentile = |fraction: f64| {
+        let index = ((latencies.len() - 1) as f64 * fraction).round() as usize;
+        latencies[index].as_secs_f64() * 1000.0
+    };
+
+    println!(
+        "{} presses through {:?}, in milliseconds:",
+        latencies.len(),
+        backend
+    );
+    println!(
+        "  min {:.3}  p50 {:.3}  p90 {:.3}  p99 {:.3}  max {:.3}",
+        percentile(0.0),
+        percentile(0.5),
+        percentile(0.9),
+        percentile(0.99),
+        percentile(1.0)
+    );
+
+    Ok(())
+}
+
+/// Presses `green` on `guitar` `presses` times, timing how long until the
+/// output called `name` sends `expected` each time
+fn measure(
+    guitar: &mut VirtualDevice,
+    green: &Key,
+    name: &str,
+    domain: &str,
+    expected: &str,
+    presses: usize,
+) -> Result<Vec<Duration>> {
+    let deadline = Instant::now() + STARTUP_TIMEOUT;
+    let (path, mut output) = loop {
+        let found = evdev::enumerate().find(|(_, device)| device.name() == Some(name));
+
+        match found {
+            Some(found) => break found,
+            None if Instant::now() >= deadline => {
+                bail!("the backend didn't create {:?} in time", name)
+            }
+            None => thread::sleep(Duration::from_millis(50)),
+        }
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || loop {
+        match output.fetch_events() {
+            Ok(events) => {
+                for event in events {
+                    if sender.send(event).is_err() {
+                        return;
+                    }
+                }
+            }
+            Err(_) => return,
+        }
+    });
+
+    let code = green.code_number().expect("green has a code");
+    let mut latencies = Vec::new();
+
+    for _ in 0..presses {
+        // Anything left over from the last press would be taken for this one
+        while receiver.try_recv().is_ok() {}
+
+        let sent = SystemTime::now();
+        guitar
+            .emit(&[InputEvent::new(EventType::KEY.0, code, 1)])
+            .context("couldn't press the fake guitar's green fret")?;
+
+        let deadline = Instant::now() + PRESS_TIMEOUT;
+        loop {
+            let event =
+                match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => {
+                        bail!("{} never came out of {:?}", expected, path)
+                    }
+                    Err(RecvTimeoutError::Disconnected) => bail!("{:?} went away", path),
+                };
+
+            // The kernel stamps the event as it arrives, which is more
+            // accurate than when we get around to reading it
+            if Key::from_event(&event, domain).is_some_and(|key| key.to_string() == expected) {
+                latencies.push(event.timestamp().duration_since(sent).unwrap_or_default());
+                break;
+            }
+        }
+
+        guitar
+            .emit(&[InputEvent::new(EventType::KEY.0, code, 0)])
+            .context("couldn't let go of the fake guitar's green fret")?;
+        thread::sleep(PRESS_GAP);
+    }
+
+    Ok(latencies)
+}
+
+/// Creates a virtual device standing in for a part of the Wiimote, with
+/// `keys` and `axes` given as their codes and ranges
+fn fake_device(
+    part: &str,
+    keys: &[KeyCode],
+    axes: &[(AbsoluteAxisCode, i32, i32)],
+) -> Result<VirtualDevice> {
+    let name = format!("roadii benchmark {}", part);
+
+    let mut builder =
+        permissions::uinput()?
+            .name(&name)
+            .input_id(InputId::new(BusType::BUS_VIRTUAL, 0, 0, 0));
+
+    if !keys.is_empty() {
+        builder = builder.with_keys(&keys.iter().copied().collect::<AttributeSet<_>>())?;
+    }
+
+    for &(axis, min, max) in axes {
+        builder = builder.with_absolute_axis(&UinputAbsSetup::new(
+            axis,
+            AbsInfo::new(0, min, max, 0, 0, 0),
+        ))?;
+    }
+
+    builder
+        .build()
+        .with_context(|| format!("couldn't create {:?}", name))
+}
+
+/// Where a fake device can be read from
+fn devnode(device: &mut VirtualDevice) -> Result<PathBuf> {
+    device
+        .enumerate_dev_nodes_blocking()
+        .context("couldn't find a fake device's node")?
+        .next()
+        .ok_or_else(|| anyhow!("a fake device has no node"))?
+        .context("couldn't find a fake device's node")
+}
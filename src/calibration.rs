@@ -0,0 +1,393 @@
+use anyhow::{anyhow, bail, Context, Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use crate::config::Settings;
+use crate::key::Key;
+use crate::mapping;
+use crate::permissions;
+use crate::profile::WhammySource;
+use crate::wiitar::Wiitar;
+
+/// The IR camera's axes for where it sees the sensor bar
+const ABS_HAT0X: u16 = 0x10;
+const ABS_HAT0Y: u16 = 0x11;
+
+/// Where calibrations are kept if no other path is supplied
+pub const DEFAULT_PATH: &str = "/var/lib/roadii/calibration.toml";
+
+/// The values an axis reaches at rest and fully pressed, given as
+/// `rest:full`. Some axes fall as they're pressed, so `full` can be the
+/// lower of the two.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Span {
+    pub rest: i32,
+    pub full: i32,
+}
+
+impl Span {
+    /// How far `value` is from rest towards fully pressed, from 0 to 1
+    pub fn travel(&self, value: i32) -> f64 {
+        ((value - self.rest) as f64 / (self.full - self.rest) as f64).clamp(0.0, 1.0)
+    }
+
+    /// The value `travel` of the way from rest towards fully pressed
+    pub fn value(&self, travel: f64) -> i32 {
+        (self.rest as f64 + (self.full - self.rest) as f64 * travel).round() as i32
+    }
+
+    /// Whether the axis falls as it's pressed
+    pub fn falls(&self) -> bool {
+        self.full < self.rest
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.rest, self.full)
+    }
+}
+
+impl FromStr for Span {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (rest, full) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("{:?} should look like `rest:full`", s))?;
+
+        let span = Span {
+            rest: rest
+                .parse()
+                .with_context(|| format!("{:?} is not a valid value at rest", rest))?,
+            full: full
+                .parse()
+                .with_context(|| format!("{:?} is not a valid value fully pressed", full))?,
+        };
+
+        if span.rest == span.full {
+            bail!("{:?} doesn't go anywhere from rest to fully pressed", s);
+        }
+
+        Ok(span)
+    }
+}
+
+impl TryFrom<String> for Span {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<Span> for String {
+    fn from(span: Span) -> Self {
+        span.to_string()
+    }
+}
+
+/// The part of the IR camera's view the screen takes up, given as where the
+/// camera sees the sensor bar when the Wiimote points at the screen's top
+/// left corner and then its bottom right corner, like `900:650:120:110`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Area {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+impl fmt::Display for Area {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}:{}",
+            self.left, self.top, self.right, self.bottom
+        )
+    }
+}
+
+impl FromStr for Area {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let values = s
+            .split(':')
+            .map(|value| {
+                value
+                    .parse()
+                    .with_context(|| format!("{:?} is not a valid position", value))
+            })
+            .collect::<Result<Vec<i32>>>()?;
+
+        let [left, top, right, bottom] = values[..] else {
+            bail!("{:?} should look like `left:top:right:bottom`", s);
+        };
+
+        if left == right || top == bottom {
+            bail!("{:?} doesn't cover any of the screen", s);
+        }
+
+        Ok(Area {
+            left,
+            top,
+            right,
+            bottom,
+        })
+    }
+}
+
+impl TryFrom<String> for Area {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<Area> for String {
+    fn from(area: Area) -> Self {
+        area.to_string()
+    }
+}
+
+/// What has been measured for one Wiimote and its guitar
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct DeviceCalibration {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub whammy: Option<Span>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pointer: Option<Area>,
+}
+
+/// The contents of the calibration file, which Roadii writes itself
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Calibration {
+    /// Calibrations for specific Wiimotes, keyed by their Bluetooth address
+    pub devices: BTreeMap<String, DeviceCalibration>,
+}
+
+impl Calibration {
+    /// Reads the calibrations from `path`. It's fine for the file not to
+    /// exist yet, as nothing may have been calibrated.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Default::default()),
+            Err(error) => {
+                return Err(error).with_context(|| format!("couldn't read {:?}", path));
+            }
+        };
+
+        toml::from_str(&contents).with_context(|| format!("couldn't parse {:?}", path))
+    }
+
+    /// Writes the calibrations to `path`, creating its directory if need be
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("couldn't create {:?}", parent))?;
+        }
+
+        let contents = toml::to_string_pretty(self).context("couldn't serialise calibrations")?;
+
+        fs::write(path, contents).with_context(|| format!("couldn't write {:?}", path))
+    }
+
+    /// The settings the calibration for the Wiimote at `address` implies
+    pub fn settings_for(&self, address: Option<&str>) -> Settings {
+        let device = address.and_then(|address| {
+            self.devices
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(address))
+                .map(|(_, device)| device)
+        });
+
+        Settings {
+            whammy_range: device.and_then(|device| device.whammy),
+            pointer_area: device.and_then(|device| device.pointer),
+            ..Default::default()
+        }
+    }
+}
+
+/// The path to the calibration file, given `path` if one was supplied
+pub fn path(path: Option<PathBuf>) -> PathBuf {
+    path.unwrap_or_else(|| PathBuf::from(DEFAULT_PATH))
+}
+
+/// Walks the user through measuring how far the whammy on `parts`
+/// actually travels, from wherever `source` says it comes from, and saves
+/// it to the calibration file at `path`
+pub fn whammy(parts: Wiitar, source: WhammySource, path: &Path) -> Result<()> {
+    let address = parts.address.clone().ok_or_else(|| {
+        anyhow!("couldn't tell which Wiimote this is, so there's nowhere to save its calibration")
+    })?;
+
+    let (devnode, prompts) = match source {
+        WhammySource::Bar => (
+            mapping::devnode(parts.guitar.as_ref(), "wiimote guitar")?,
+            [
+                "Leave the whammy bar at rest",
+                "Now hold the whammy bar all the way down",
+            ],
+        ),
+        _ => (
+            mapping::devnode(parts.accel.as_ref(), "wiimote accelerometer")?,
+            [
+                "Hold the guitar the way you do while playing",
+                "Now hold it the way you want a full whammy to be",
+            ],
+        ),
+    };
+
+    let device = permissions::open(&devnode)?;
+
+    let code = source
+        .axis()
+        .parse::<Key>()?
+        .code_number()
+        .context("the whammy's axis doesn't have a number")?;
+
+    let rest = sample(&device, code, prompts[0])?;
+    let full = sample(&device, code, prompts[1])?;
+
+    // Wobbling about at rest shouldn't count as pressing the whammy, and a
+    // wobbly hand shouldn't stop it reaching all the way, so each end is
+    // taken as whatever came closest to the other
+    let span = if full.0 > rest.1 {
+        Span {
+            rest: rest.1,
+            full: full.0,
+        }
+    } else if full.1 < rest.0 {
+        Span {
+            rest: rest.0,
+            full: full.1,
+        }
+    } else {
+        bail!(
+            "the whammy didn't move far enough to tell (it went from {}~{} to {}~{})",
+            rest.0,
+            rest.1,
+            full.0,
+            full.1
+        );
+    };
+
+    let mut calibration = Calibration::load(path)?;
+    calibration.devices.entry(address).or_default().whammy = Some(span);
+    calibration.save(path)?;
+
+    println!(
+        "The whammy goes from {} to {}, saved to {:?}. Reconnect the guitar to use it.",
+        span.rest, span.full, path
+    );
+
+    Ok(())
+}
+
+/// Walks the user through pointing the Wiimote in `parts` at the corners of
+/// the screen, and saves where the screen is to the calibration file at
+/// `path`
+pub fn pointer(parts: Wiitar, path: &Path) -> Result<()> {
+    let address = parts.address.clone().ok_or_else(|| {
+        anyhow!("couldn't tell which Wiimote this is, so there's nowhere to save its calibration")
+    })?;
+
+    let devnode = mapping::devnode(parts.ir.as_ref(), "wiimote IR camera")?;
+    let device = permissions::open(&devnode)?;
+
+    let (left, top) = aim(
+        &device,
+        "Point the Wiimote at the top left corner of the screen",
+    )?;
+    let (right, bottom) = aim(
+        &device,
+        "Now point it at the bottom right corner of the screen",
+    )?;
+
+    let area: Area = format!("{}:{}:{}:{}", left, top, right, bottom)
+        .parse()
+        .context("the sensor bar didn't move far enough to tell where the screen is")?;
+
+    let mut calibration = Calibration::load(path)?;
+    calibration.devices.entry(address).or_default().pointer = Some(area);
+    calibration.save(path)?;
+
+    println!(
+        "The screen is at {} as far as the camera's concerned, saved to {:?}. Reconnect the guitar to use it.",
+        area, path
+    );
+
+    Ok(())
+}
+
+/// Asks the user to do `prompt`, then watches where the IR camera on
+/// `device` sees the sensor bar for a second, returning where it was on
+/// average
+fn aim(device: &evdev::Device, prompt: &str) -> Result<(i32, i32)> {
+    println!("{}, then press Enter.", prompt);
+    io::stdin()
+        .read_line(&mut String::new())
+        .context("couldn't read from the terminal")?;
+
+    let (mut x, mut y) = (0, 0);
+
+    for _ in 0..50 {
+        let axes = device.get_abs_state().context("couldn't read the axes")?;
+        let (seen_x, seen_y) = (
+            axes[ABS_HAT0X as usize].value,
+            axes[ABS_HAT0Y as usize].value,
+        );
+
+        // This is what the camera says when it can't see anything
+        if seen_x >= 1023 || seen_y >= 768 {
+            bail!(
+                "the Wiimote couldn't see the sensor bar, try again a bit further from the screen"
+            );
+        }
+
+        x += seen_x;
+        y += seen_y;
+
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    Ok((x / 50, y / 50))
+}
+
+/// Asks the user to do `prompt`, then watches axis `code` for a second,
+/// returning the lowest and highest values it reached
+fn sample(device: &evdev::Device, code: u16, prompt: &str) -> Result<(i32, i32)> {
+    println!("{}, then press Enter.", prompt);
+    io::stdin()
+        .read_line(&mut String::new())
+        .context("couldn't read from the terminal")?;
+
+    let mut lowest = i32::MAX;
+    let mut highest = i32::MIN;
+
+    for _ in 0..50 {
+        let value = device.get_abs_state().context("couldn't read the axes")?[code as usize].value;
+
+        lowest = lowest.min(value);
+        highest = highest.max(value);
+
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    Ok((lowest, highest))
+}
@@ -0,0 +1,78 @@
+use clap::ValueEnum;
+use std::env;
+use std::fmt::Display;
+use std::io::{self, IsTerminal};
+use std::sync::OnceLock;
+
+/// When to colour what roadii writes out
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum When {
+    /// When writing to a terminal, unless `NO_COLOR` is set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+static WHEN: OnceLock<When> = OnceLock::new();
+
+const GREEN: &str = "32";
+const YELLOW: &str = "33";
+const RED: &str = "31";
+const BOLD: &str = "1";
+const BOLD_RED: &str = "1;31";
+
+/// Sets when to colour for the rest of the run
+pub fn init(when: When) {
+    WHEN.get_or_init(|| when);
+}
+
+/// Whether what's written to standard output should be coloured
+pub fn stdout() -> bool {
+    enabled(io::stdout().is_terminal())
+}
+
+/// Whether what's written to standard error should be coloured
+pub fn stderr() -> bool {
+    enabled(io::stderr().is_terminal())
+}
+
+fn enabled(terminal: bool) -> bool {
+    match WHEN.get().copied().unwrap_or_default() {
+        When::Always => true,
+        When::Never => false,
+        When::Auto => terminal && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()),
+    }
+}
+
+/// Something that went well, like a check passing, for standard output
+pub fn good(text: impl Display) -> String {
+    paint(GREEN, text, stdout())
+}
+
+/// Something worth a second look, like a low battery, for standard output
+pub fn warning(text: impl Display) -> String {
+    paint(YELLOW, text, stdout())
+}
+
+/// Something that went wrong, like a check failing, for standard output
+pub fn bad(text: impl Display) -> String {
+    paint(RED, text, stdout())
+}
+
+/// Something to pick out, like a device's name, for standard output
+pub fn strong(text: impl Display) -> String {
+    paint(BOLD, text, stdout())
+}
+
+/// What roadii stopped because of, for standard error
+pub fn error(text: impl Display) -> String {
+    paint(BOLD_RED, text, stderr())
+}
+
+fn paint(code: &str, text: impl Display, enabled: bool) -> String {
+    match enabled {
+        true => format!("\x1b[{}m{}\x1b[0m", code, text),
+        false => text.to_string(),
+    }
+}
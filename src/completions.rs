@@ -0,0 +1,100 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use clap_complete::env::{Bash, EnvCompleter, Fish, Zsh};
+use clap_complete::CompletionCandidate;
+use std::ffi::OsStr;
+use std::io;
+use udev::{Device, Udev};
+
+use crate::wiitar::Wiitar;
+
+/// The environment variable the completion scripts set when they call back
+/// into roadii for what to complete
+pub const VARIABLE: &str = "COMPLETE";
+
+/// The shells completions can be written for
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Prints the script which sets up `shell` to complete roadii's arguments.
+/// The script asks roadii what to complete as it goes, so it always matches
+/// the roadii being run, and can offer the kernel names and addresses of
+/// whichever Wiimotes are connected at the time.
+pub fn run(shell: Shell) -> Result<()> {
+    let completer: &dyn EnvCompleter = match shell {
+        Shell::Bash => &Bash,
+        Shell::Zsh => &Zsh,
+        Shell::Fish => &Fish,
+    };
+
+    completer
+        .write_registration(VARIABLE, "roadii", "roadii", "roadii", &mut io::stdout())
+        .context("couldn't write out the completion script")
+}
+
+/// The kernel names of the connected Wiimotes' guitars starting with
+/// `current`, with their Bluetooth addresses as help
+pub fn kernel_names(current: &OsStr) -> Vec<CompletionCandidate> {
+    guitars()
+        .into_iter()
+        .filter(|guitar| starts_with(guitar.sysname(), current))
+        .map(|guitar| {
+            let mut candidate = CompletionCandidate::new(guitar.sysname());
+            if let Some(address) = address(&guitar) {
+                candidate = candidate.help(Some(address.into()));
+            }
+            candidate
+        })
+        .collect()
+}
+
+/// The Bluetooth addresses of the connected Wiimotes with a guitar
+/// attached starting with `current`, with their guitars' kernel names as
+/// help
+pub fn addresses(current: &OsStr) -> Vec<CompletionCandidate> {
+    guitars()
+        .into_iter()
+        .filter_map(|guitar| {
+            let address = address(&guitar)?;
+            starts_with(OsStr::new(&address), current).then(|| {
+                CompletionCandidate::new(address)
+                    .help(Some(guitar.sysname().to_string_lossy().into_owned().into()))
+            })
+        })
+        .collect()
+}
+
+/// The kernel names and Bluetooth addresses of the connected Wiimotes with
+/// a guitar attached starting with `current`, for anything which takes
+/// either
+pub fn selectors(current: &OsStr) -> Vec<CompletionCandidate> {
+    let mut candidates = kernel_names(current);
+    candidates.extend(addresses(current));
+    candidates
+}
+
+/// Every connected Wiimote's guitar, or none if udev can't be asked, as
+/// there's nowhere to say what went wrong while completing
+fn guitars() -> Vec<Device> {
+    Udev::new()
+        .map_err(Into::into)
+        .and_then(Wiitar::guitars)
+        .unwrap_or_default()
+}
+
+fn address(guitar: &Device) -> Option<String> {
+    guitar
+        .attribute_value("uniq")
+        .map(|uniq| uniq.to_string_lossy().into_owned())
+        .filter(|uniq| !uniq.is_empty())
+}
+
+fn starts_with(value: &OsStr, current: &OsStr) -> bool {
+    value
+        .as_encoded_bytes()
+        .starts_with(current.as_encoded_bytes())
+}
@@ -0,0 +1,711 @@
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use crate::backend::Backend;
+use crate::calibration::{Area, Calibration, Span};
+use crate::identity::Identity;
+use crate::outputs::Existing;
+use crate::pipeline::{AxisRange, AxisTransform};
+use crate::preset::Preset;
+use crate::profile::{
+    AxisButton, ButtonAxis, Chord, ChordBinding, Control, Curve, EventMap, KeyBinding,
+    LongPressBinding, MacroBinding, Merge, Pair, PointerMode, Profile, TiltMode, TiltSource,
+    WhammySource, WiimoteOrientation,
+};
+
+/// Where the configuration file is read from if no other path is supplied
+pub const DEFAULT_PATH: &str = "/etc/roadii/config.toml";
+
+/// Settings which can be given on the command line, in the configuration
+/// file, or in the configuration file for one specific Wiimote. Anything in
+/// the file which isn't one of them is refused, so a typo doesn't go
+/// unnoticed.
+#[derive(Args, Clone, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Settings {
+    /// What does the remapping; some options only work with the native
+    /// backend.
+    ///
+    /// If not supplied, `evsieve` is used.
+    #[arg(short, long, value_enum)]
+    pub backend: Option<Backend>,
+
+    /// The set of mappings to use, which decides what virtual devices are
+    /// created.
+    ///
+    /// If not supplied, the `gamepad` profile is used.
+    #[arg(short, long, value_enum)]
+    pub profile: Option<Profile>,
+
+    /// Picks the profile, identity, tilt mode and axis ranges which suit a
+    /// game, for any of them which aren't set otherwise.
+    #[arg(long, value_enum)]
+    pub preset: Option<Preset>,
+
+    /// Switches to the next of the profiles in `--switch-profile` when these
+    /// controls are held down together for two seconds, like
+    /// `select+start+green`. Needs the native backend.
+    #[arg(long, value_name = "CONTROL+CONTROL")]
+    pub switch_chord: Option<Chord>,
+
+    /// A profile to switch between with `--switch-chord`, given once for
+    /// each, in order.
+    ///
+    /// If not supplied, it switches between `gamepad` and `keyboard`.
+    #[arg(long = "switch-profile", value_name = "PROFILE", value_enum)]
+    pub switch_profiles: Vec<Profile>,
+
+    /// Stops remapping and lets go of the Wiimote when these controls are
+    /// held down together for three seconds, like `home`. Needs the native
+    /// backend.
+    #[arg(long, value_name = "CONTROL+CONTROL")]
+    pub exit_chord: Option<Chord>,
+
+    /// Pauses remapping when these controls are held down together for two
+    /// seconds, letting go of the Wiimote so other programs can use it, and
+    /// resumes when they're held again. Needs the native backend.
+    #[arg(long, value_name = "CONTROL+CONTROL")]
+    pub pause_chord: Option<Chord>,
+
+    /// The identity the virtual device presents to games.
+    ///
+    /// The button layout is the same for every identity, and
+    /// matches a PlayStation 3 guitar as closely as possible.
+    ///
+    /// If not supplied, the `wiitar` identity is used.
+    #[arg(short, long, value_enum)]
+    pub identity: Option<Identity>,
+
+    /// The name of the virtual device, useful for telling several apart.
+    ///
+    /// If not supplied, the name is chosen by the identity.
+    #[arg(short, long)]
+    pub output_name: Option<String>,
+
+    /// What to do if there are already virtual devices with the names ours
+    /// would have, most likely from another roadii, which games could pick
+    /// instead. `replace` stops whichever roadii or evsieve made them.
+    ///
+    /// If not supplied, roadii refuses to start.
+    #[arg(long, value_enum)]
+    pub existing_outputs: Option<Existing>,
+
+    /// Reads the Wiimote's devices without grabbing them, so other programs
+    /// like Dolphin still see the real Wiimote alongside the virtual
+    /// devices. Anything reading both sees every press twice.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub no_grab: Option<bool>,
+
+    /// Binds a control on the guitar to a key on the keyboard device, like
+    /// `green=f1`. Controls which aren't bound keep their default keys.
+    #[arg(long = "key", value_name = "CONTROL=KEY")]
+    pub keys: Vec<KeyBinding>,
+
+    /// Makes Home the Guide button, which Steam uses to open its overlay,
+    /// and A the button Home would otherwise be.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub steam: Option<bool>,
+
+    /// What raising the guitar's neck does on the gamepad. The keyboard
+    /// presses a key unless this is `off`.
+    ///
+    /// If not supplied, it presses Select.
+    #[arg(long, value_enum)]
+    pub tilt_mode: Option<TiltMode>,
+
+    /// Which of the accelerometer's axes tilt is read from.
+    ///
+    /// If not supplied, RY is used.
+    #[arg(long, value_enum)]
+    pub tilt_source: Option<TiltSource>,
+
+    /// Raising the neck the other way triggers star power instead, for
+    /// left-handed players or guitars worn upside down.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub tilt_invert: Option<bool>,
+
+    /// How far the guitar's neck needs to be raised for star power, as the
+    /// value of the tilt source, which falls as the neck rises (other than
+    /// for `magnitude`, which rises).
+    ///
+    /// If not supplied, -60 is used, or 60 for `magnitude` (flipped around
+    /// when inverted).
+    #[arg(long, allow_hyphen_values = true)]
+    pub tilt_threshold: Option<i32>,
+
+    /// How far back past the threshold the neck needs to be lowered before
+    /// star power is let go, so it doesn't flicker on and off at the edge.
+    ///
+    /// If not supplied, 1 is used.
+    #[arg(long)]
+    pub tilt_hysteresis: Option<i32>,
+
+    /// How much the tilt source is smoothed out, from 0 (not at all) to 1
+    /// (so much it never moves), so strumming hard doesn't trigger or let
+    /// go of star power. Needs the native backend.
+    ///
+    /// If not supplied, tilt isn't smoothed at all.
+    #[arg(long)]
+    pub tilt_smoothing: Option<f64>,
+
+    /// Also triggers star power while these controls are held down
+    /// together, like `select+green`, alongside or instead of tilting.
+    #[arg(long, value_name = "CONTROL+CONTROL")]
+    pub star_power_chord: Option<Chord>,
+
+    /// How the Wiimote is held, so its d-pad points the right way when it's
+    /// used outside the guitar.
+    ///
+    /// If not supplied, it's assumed to be plugged into the guitar.
+    #[arg(long, value_enum)]
+    pub wiimote_orientation: Option<WiimoteOrientation>,
+
+    /// Sends anything which isn't mapped to something else to the gamepad as
+    /// it is, or the keyboard or pointer if there's no gamepad, which helps
+    /// with finding what's missing from a mapping.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub passthrough_unmapped: Option<bool>,
+
+    /// Presses a key or button on the gamepad, or the keyboard or pointer if
+    /// there's no gamepad, while some controls are held down together, like
+    /// `green+red=key:f6`. The controls still do their usual jobs.
+    #[arg(long = "chord", value_name = "CONTROL+CONTROL=EVENT")]
+    pub chords: Vec<ChordBinding>,
+
+    /// Presses a key or button on the gamepad, or the keyboard or pointer if
+    /// there's no gamepad, when a control is held down for a while rather
+    /// than tapped, like `select=btn:mode`. Tapping it still does its usual
+    /// job, once it's let go of. Needs the native backend.
+    #[arg(long = "long-press", value_name = "CONTROL=EVENT")]
+    pub long_presses: Vec<LongPressBinding>,
+
+    /// How long a control has to be held down for to count as a long press.
+    ///
+    /// If not supplied, it's 500 milliseconds.
+    #[arg(long, value_name = "MILLISECONDS")]
+    pub long_press_time: Option<u64>,
+
+    /// Plays a short sequence of keys or buttons on the gamepad, or the
+    /// keyboard or pointer if there's no gamepad, when some controls are
+    /// held down together, like `select+orange=btn:start,500,btn:south`.
+    /// Each step is a key or button to tap, or press or let go of with a
+    /// value of 1 or 0, or a number of milliseconds to wait. Needs the native
+    /// backend.
+    #[arg(long = "macro", value_name = "CONTROL+CONTROL=STEP,STEP")]
+    pub macros: Vec<MacroBinding>,
+
+    /// Presses a key or button on the gamepad, or the keyboard or pointer if
+    /// there's no gamepad, while an axis is past a threshold, given as the
+    /// axis, the key or button, the value to press it at and optionally the
+    /// value to let go of it at, like `abs:hat1x@guitar=btn:tl2,8,6`. The
+    /// axis still does its usual job.
+    #[arg(long = "axis-button", value_name = "AXIS=EVENT,PRESS,RELEASE")]
+    pub axis_buttons: Vec<AxisButton>,
+
+    /// Sets an axis on the gamepad, or the keyboard or pointer if there's no
+    /// gamepad, to one value while a key or button is held down and another
+    /// once it's let go of, like `btn:5@guitar=abs:z,255,0`. The button
+    /// still does its usual job.
+    #[arg(long = "button-axis", value_name = "EVENT=AXIS,PRESSED,RELEASED")]
+    pub button_axes: Vec<ButtonAxis>,
+
+    /// Reads another input device alongside the Wiimote, like a foot pedal,
+    /// tagging its events with a name for `--map` to use, like
+    /// `pedal=/dev/input/by-id/usb-footswitch-event-kbd`. It's grabbed like
+    /// the Wiimote's own devices.
+    #[arg(long = "merge", value_name = "NAME=PATH")]
+    pub merges: Vec<Merge>,
+
+    /// Replaces an event with another on the gamepad, or the keyboard or
+    /// pointer if there's no gamepad, unless it says otherwise, like
+    /// `key:b@pedal=btn:select`. The event replaced doesn't do its usual job.
+    #[arg(long = "map", value_name = "EVENT=EVENT")]
+    pub maps: Vec<EventMap>,
+
+    /// Runs every event through the `on_event(event, state)` function of a
+    /// Rhai script, for mappings the other options can't describe, passing
+    /// on whatever it returns instead. It sees the events before anything
+    /// else, other than the exit and pause chords. Needs the native backend.
+    #[arg(long, value_name = "PATH")]
+    pub script: Option<PathBuf>,
+
+    /// Runs every event through a WebAssembly plugin, passing on whatever
+    /// it emits instead, after `--script` and any plugins before it. Needs
+    /// the native backend.
+    #[arg(long = "plugin", value_name = "PATH")]
+    pub plugins: Vec<PathBuf>,
+
+    /// Makes a control latch, so pressing it once holds it down until it's
+    /// pressed again, like `select` for games where star power has to be
+    /// held. Needs the native backend.
+    #[arg(long = "toggle", value_name = "CONTROL")]
+    pub toggles: Vec<Control>,
+
+    /// Swaps strumming up and down, for guitars played left-handed or games
+    /// which see strumming the other way around
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub swap_strum: Option<bool>,
+
+    /// Turns everything around for a guitar flipped over to be played
+    /// left-handed, whichever profile is active: strumming, the Wiimote's
+    /// d-pad while it's plugged into the guitar, the stick and tilt. Swapping
+    /// strumming or inverting tilt as well turns them back again.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub lefty: Option<bool>,
+
+    /// Reverses the order of the frets, so orange is green and green is
+    /// orange, for left-handed players who'd rather have green nearest the
+    /// body
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub reverse_frets: Option<bool>,
+
+    /// Keeps strumming this many times a second for as long as the strum
+    /// bar is held, for players who can't strum quickly. Needs the native
+    /// backend.
+    ///
+    /// If not supplied, holding the strum bar strums once.
+    #[arg(long, value_name = "STRUMS")]
+    pub auto_strum: Option<f64>,
+
+    /// Ignores strums within this many milliseconds of the last one in the
+    /// same direction, so a worn strum bar can't register one strum twice.
+    /// Needs the native backend.
+    ///
+    /// If not supplied, every strum counts.
+    #[arg(long, value_name = "MILLISECONDS")]
+    pub strum_debounce: Option<u64>,
+
+    /// Also republish the raw accelerometer axes on a separate virtual
+    /// device, for games and emulators which want real motion data.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub motion: Option<bool>,
+
+    /// Also publish the Motion Plus gyroscope's axes on a separate virtual
+    /// device, for Wiimotes with one plugged in or built in. Its axes can be
+    /// used elsewhere as `abs:rx@gyro` and so on.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub gyro: Option<bool>,
+
+    /// Also turn pointing the Wiimote at the sensor bar into a pointer
+    /// device, where B is the left mouse button.
+    ///
+    /// The `pointer` profile uses `relative` if this is not supplied.
+    #[arg(long, value_enum)]
+    pub pointer: Option<PointerMode>,
+
+    /// Also turn pointing the Wiimote at the screen into a position on a
+    /// separate virtual device, like a lightgun, with B as its trigger and A
+    /// as its other button, whatever `--pointer` is doing
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub lightgun: Option<bool>,
+
+    /// Where the IR camera sees the sensor bar when the Wiimote points at the
+    /// top left and bottom right corners of the screen, like
+    /// `900:650:120:110`, which `roadii calibrate pointer` measures. Absolute
+    /// pointers and the lightgun are stretched to fit it.
+    ///
+    /// If not supplied, the camera's whole view is used.
+    #[arg(long, value_name = "LEFT:TOP:RIGHT:BOTTOM")]
+    pub pointer_area: Option<Area>,
+
+    /// How far the pointer moves for a given movement of the Wiimote, in
+    /// relative mode.
+    ///
+    /// If not supplied, 2 is used.
+    #[arg(long)]
+    pub pointer_sensitivity: Option<f64>,
+
+    /// Lights the Wiimote's LED for this player, from 1 to 4, rather than
+    /// leaving whichever the Bluetooth stack chose
+    #[arg(long, value_name = "PLAYER")]
+    pub player: Option<u8>,
+
+    /// Stops remapping and disconnects the Wiimote once nothing has been
+    /// pressed for this many minutes, so a forgotten one doesn't run its
+    /// battery down. Needs the native backend.
+    ///
+    /// If not supplied, or 0, it keeps going for as long as it's connected.
+    #[arg(long, value_name = "MINUTES")]
+    pub idle_timeout: Option<u64>,
+
+    /// Once nothing at all has come from the Wiimote for this many
+    /// seconds, even from the accelerometer, opens its devices again, and
+    /// if that doesn't get it going, disconnects it so it can reconnect
+    /// afresh. Needs the native backend.
+    ///
+    /// If not supplied, or 0, it waits for as long as it takes.
+    #[arg(long, value_name = "SECONDS")]
+    pub stall_timeout: Option<u64>,
+
+    /// Streams what the guitar is doing as JSON over a WebSocket at this
+    /// address, like `127.0.0.1:9753`, for stream overlays to show. Needs
+    /// the native backend.
+    ///
+    /// If not supplied, nothing is streamed.
+    #[arg(long, value_name = "ADDRESS")]
+    pub websocket: Option<SocketAddr>,
+
+    /// Sends what the guitar is doing as OSC messages over UDP to this
+    /// address, like `127.0.0.1:57120`, for music software to play along
+    /// with. Needs the native backend.
+    ///
+    /// If not supplied, no OSC messages are sent.
+    #[arg(long, value_name = "ADDRESS")]
+    pub osc: Option<SocketAddr>,
+
+    /// When started as root, carries on as this user once the Wiimote's
+    /// devices and the virtual devices are open, so a bug can't do as much
+    /// harm. Needs the native backend, as evsieve opens the devices itself.
+    ///
+    /// If not supplied, it stays as whoever started it.
+    #[arg(long)]
+    pub user: Option<String>,
+
+    /// The group to carry on as with `--user`.
+    ///
+    /// If not supplied, the user's own group is used.
+    #[arg(long)]
+    pub group: Option<String>,
+
+    /// Once the devices are open, stops roadii making any system call
+    /// remapping doesn't need, so a bug in handling what the Wiimote sends
+    /// can't do much. Needs the native backend, and can't be used with
+    /// `--websocket`, `--idle-timeout` or `--stall-timeout`.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub sandbox: Option<bool>,
+
+    /// Runs the event loop with realtime priority, so other busy programs
+    /// can't hold up what the guitar sends. Needs the native backend, and
+    /// root or an rtprio limit, without which it carries on as normal.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub realtime: Option<bool>,
+
+    /// Runs the event loop with this nice value, from -20 to 19, lower
+    /// being sooner, when it doesn't have realtime priority. Needs the native
+    /// backend, and root or a nice limit to go below 0, without which it
+    /// carries on as normal.
+    #[arg(long, value_name = "NICE", allow_hyphen_values = true)]
+    pub nice: Option<i32>,
+
+    /// Keeps roadii's memory in RAM, so it never has to wait for any of it
+    /// to be brought back in while the guitar's being played. Needs the
+    /// native backend, and root or an unlimited memlock limit, without which
+    /// it carries on as normal.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub mlock: Option<bool>,
+
+    /// Doesn't show desktop notifications when the Wiitar connects or
+    /// disconnects, or its battery runs low
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub no_notify: Option<bool>,
+
+    /// Warns when the Wiimote's battery drops below this percentage. It's
+    /// only checked when starting, unless using the native backend.
+    ///
+    /// If not supplied, 20 is used.
+    #[arg(long, value_name = "PERCENT")]
+    pub low_battery: Option<u8>,
+
+    /// Passes rumble games send to the gamepad on to the Wiimote. Needs the
+    /// native backend.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub rumble: Option<bool>,
+
+    /// Moves the pointer like a mouse with the guitar's stick, with green,
+    /// red and yellow as the left, right and middle mouse buttons. Needs the
+    /// native backend.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub stick_mouse: Option<bool>,
+
+    /// How many pixels a second the stick moves the pointer when it's pushed
+    /// all the way, with `--stick-mouse`.
+    ///
+    /// If not supplied, 800 is used.
+    #[arg(long, value_name = "PIXELS")]
+    pub stick_mouse_speed: Option<f64>,
+
+    /// How much the pointer's movement is smoothed out, from 0 (not at all)
+    /// to 1 (so much it never moves). Needs the native backend.
+    ///
+    /// If not supplied, the pointer isn't smoothed at all.
+    #[arg(long)]
+    pub pointer_smoothing: Option<f64>,
+
+    /// Where the whammy comes from; one of the accelerometer's axes can
+    /// stand in for a worn out whammy bar.
+    ///
+    /// If not supplied, the whammy bar is used.
+    #[arg(long, value_enum)]
+    pub whammy_source: Option<WhammySource>,
+
+    /// The values the whammy actually reaches at rest and fully pressed,
+    /// like `0:12`, so it can be stretched to its full range.
+    ///
+    /// If not supplied, the calibration saved by `roadii calibrate whammy`
+    /// is used, or the range the guitar declares if there isn't one.
+    #[arg(long, value_name = "REST:FULL", allow_hyphen_values = true)]
+    pub whammy_range: Option<Span>,
+
+    /// Snaps the whammy to this many evenly spaced positions, from rest to
+    /// fully pressed, for games which treat it as a switch.
+    ///
+    /// If not supplied, the whammy moves freely.
+    #[arg(long)]
+    pub whammy_positions: Option<u32>,
+
+    /// How much the whammy is smoothed out, from 0 (not at all) to 1 (so
+    /// much it never moves), mostly for when it comes from the
+    /// accelerometer. Needs the native backend.
+    ///
+    /// If not supplied, the whammy isn't smoothed at all.
+    #[arg(long)]
+    pub whammy_smoothing: Option<f64>,
+
+    /// How much of the whammy bar's travel near rest is ignored, from 0
+    /// (none of it) to 1 (all of it), so resting a hand on it does nothing.
+    ///
+    /// If not supplied, none of it is ignored.
+    #[arg(long)]
+    pub whammy_deadzone: Option<f64>,
+
+    /// How the whammy responds as the bar is pressed.
+    ///
+    /// If not supplied, it's linear.
+    #[arg(long, value_enum)]
+    pub whammy_curve: Option<Curve>,
+
+    /// How much of the stick's travel around the centre is ignored, from 0
+    /// (none of it) to 1 (all of it), so a drifting stick stays put. Given
+    /// as `x:y`, or one amount for both.
+    ///
+    /// If not supplied, none of it is ignored.
+    #[arg(long, value_name = "X:Y")]
+    pub stick_deadzone: Option<Pair<f64>>,
+
+    /// Where the stick actually rests, as `x:y`, for sticks which don't
+    /// quite centre themselves any more.
+    ///
+    /// If not supplied, it's assumed to rest at 0:0.
+    #[arg(long, value_name = "X:Y", allow_hyphen_values = true)]
+    pub stick_center: Option<Pair<i32>>,
+
+    /// Scales, offsets or inverts an absolute axis on the virtual device,
+    /// like `rx=scale:2,offset:-10,invert`, after whatever is mapped to it
+    /// already. Inverting flips it around the middle of its range, and
+    /// anything in `--axis-range` applies afterwards.
+    #[arg(long = "axis-transform", value_name = "AXIS=OPTION,OPTION")]
+    pub axis_transforms: Vec<AxisTransform>,
+
+    /// Overrides the range of an absolute axis on the virtual device, like
+    /// `rx=-32768:32767`, rescaling whatever is mapped to it to fit. A third
+    /// number, like `rx=-32768:32767:4096`, sets its flat too, which needs
+    /// the native backend.
+    ///
+    /// Otherwise the range is derived from the source device's axis.
+    #[arg(long = "axis-range", value_name = "AXIS=MIN:MAX[:FLAT]")]
+    pub axis_ranges: Vec<AxisRange>,
+}
+
+impl Settings {
+    /// Fills in anything not set here with the value from `other`
+    pub fn or(self, other: Settings) -> Settings {
+        Settings {
+            backend: self.backend.or(other.backend),
+            profile: self.profile.or(other.profile),
+            preset: self.preset.or(other.preset),
+            switch_chord: self.switch_chord.or(other.switch_chord),
+            // Unlike keys, profiles given here replace the whole list
+            switch_profiles: match self.switch_profiles.is_empty() {
+                true => other.switch_profiles,
+                false => self.switch_profiles,
+            },
+            exit_chord: self.exit_chord.or(other.exit_chord),
+            pause_chord: self.pause_chord.or(other.pause_chord),
+            identity: self.identity.or(other.identity),
+            output_name: self.output_name.or(other.output_name),
+            existing_outputs: self.existing_outputs.or(other.existing_outputs),
+            no_grab: self.no_grab.or(other.no_grab),
+            keys: other.keys.into_iter().chain(self.keys).collect(),
+            steam: self.steam.or(other.steam),
+            tilt_mode: self.tilt_mode.or(other.tilt_mode),
+            tilt_source: self.tilt_source.or(other.tilt_source),
+            tilt_invert: self.tilt_invert.or(other.tilt_invert),
+            tilt_threshold: self.tilt_threshold.or(other.tilt_threshold),
+            tilt_hysteresis: self.tilt_hysteresis.or(other.tilt_hysteresis),
+            tilt_smoothing: self.tilt_smoothing.or(other.tilt_smoothing),
+            star_power_chord: self.star_power_chord.or(other.star_power_chord),
+            chords: other.chords.into_iter().chain(self.chords).collect(),
+            long_presses: other
+                .long_presses
+                .into_iter()
+                .chain(self.long_presses)
+                .collect(),
+            long_press_time: self.long_press_time.or(other.long_press_time),
+            macros: other.macros.into_iter().chain(self.macros).collect(),
+            axis_buttons: other
+                .axis_buttons
+                .into_iter()
+                .chain(self.axis_buttons)
+                .collect(),
+            button_axes: other
+                .button_axes
+                .into_iter()
+                .chain(self.button_axes)
+                .collect(),
+            merges: other.merges.into_iter().chain(self.merges).collect(),
+            maps: other.maps.into_iter().chain(self.maps).collect(),
+            script: self.script.or(other.script),
+            plugins: other.plugins.into_iter().chain(self.plugins).collect(),
+            toggles: other.toggles.into_iter().chain(self.toggles).collect(),
+            wiimote_orientation: self.wiimote_orientation.or(other.wiimote_orientation),
+            passthrough_unmapped: self.passthrough_unmapped.or(other.passthrough_unmapped),
+            swap_strum: self.swap_strum.or(other.swap_strum),
+            lefty: self.lefty.or(other.lefty),
+            reverse_frets: self.reverse_frets.or(other.reverse_frets),
+            strum_debounce: self.strum_debounce.or(other.strum_debounce),
+            auto_strum: self.auto_strum.or(other.auto_strum),
+            motion: self.motion.or(other.motion),
+            gyro: self.gyro.or(other.gyro),
+            pointer: self.pointer.or(other.pointer),
+            pointer_sensitivity: self.pointer_sensitivity.or(other.pointer_sensitivity),
+            pointer_smoothing: self.pointer_smoothing.or(other.pointer_smoothing),
+            lightgun: self.lightgun.or(other.lightgun),
+            pointer_area: self.pointer_area.or(other.pointer_area),
+            player: self.player.or(other.player),
+            idle_timeout: self.idle_timeout.or(other.idle_timeout),
+            stall_timeout: self.stall_timeout.or(other.stall_timeout),
+            websocket: self.websocket.or(other.websocket),
+            osc: self.osc.or(other.osc),
+            user: self.user.or(other.user),
+            group: self.group.or(other.group),
+            sandbox: self.sandbox.or(other.sandbox),
+            realtime: self.realtime.or(other.realtime),
+            nice: self.nice.or(other.nice),
+            mlock: self.mlock.or(other.mlock),
+            no_notify: self.no_notify.or(other.no_notify),
+            low_battery: self.low_battery.or(other.low_battery),
+            rumble: self.rumble.or(other.rumble),
+            stick_mouse: self.stick_mouse.or(other.stick_mouse),
+            stick_mouse_speed: self.stick_mouse_speed.or(other.stick_mouse_speed),
+            whammy_source: self.whammy_source.or(other.whammy_source),
+            whammy_range: self.whammy_range.or(other.whammy_range),
+            whammy_positions: self.whammy_positions.or(other.whammy_positions),
+            whammy_smoothing: self.whammy_smoothing.or(other.whammy_smoothing),
+            whammy_deadzone: self.whammy_deadzone.or(other.whammy_deadzone),
+            whammy_curve: self.whammy_curve.or(other.whammy_curve),
+            stick_deadzone: self.stick_deadzone.or(other.stick_deadzone),
+            stick_center: self.stick_center.or(other.stick_center),
+            axis_transforms: other
+                .axis_transforms
+                .into_iter()
+                .chain(self.axis_transforms)
+                .collect(),
+            // Later ranges for the same axis win, so ours go last
+            axis_ranges: other
+                .axis_ranges
+                .into_iter()
+                .chain(self.axis_ranges)
+                .collect(),
+        }
+    }
+
+    /// The settings for the Wiimote at `address`: these, with anything not
+    /// set filled in from `config`, then from `calibration`, then from the
+    /// preset
+    pub fn resolve(
+        self,
+        config: &Config,
+        calibration: &Calibration,
+        address: Option<&str>,
+    ) -> Settings {
+        self.or(config.settings_for(address))
+            .or(calibration.settings_for(address))
+            .with_preset()
+    }
+
+    /// Fills in anything still not set with the value from the preset, if
+    /// one was picked
+    pub fn with_preset(self) -> Settings {
+        match self.preset {
+            Some(preset) => self.or(preset.settings()),
+            None => self,
+        }
+    }
+}
+
+/// The contents of the configuration file
+#[derive(Debug, Default)]
+pub struct Config {
+    /// Settings which apply to every Wiimote
+    pub settings: Settings,
+
+    /// Settings for specific Wiimotes, keyed by their Bluetooth address
+    pub devices: HashMap<String, Settings>,
+}
+
+impl Config {
+    /// Reads the configuration from `path`, or the default path if none is
+    /// given. It's fine for the default configuration file not to exist.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => (PathBuf::from(DEFAULT_PATH), false),
+        };
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == ErrorKind::NotFound && !required => {
+                return Ok(Default::default())
+            }
+            Err(error) => {
+                return Err(error).with_context(|| format!("couldn't read {:?}", path));
+            }
+        };
+
+        Self::parse(&contents).with_context(|| format!("couldn't parse {:?}", path))
+    }
+
+    /// Reads the configuration from the contents of a configuration file
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut table: toml::Table = toml::from_str(contents)?;
+
+        // Everything but the devices is settings, which flattening them in
+        // would take on trust, so they're split apart here instead
+        let devices = match table.remove("devices") {
+            Some(toml::Value::Table(devices)) => devices
+                .into_iter()
+                .map(|(address, settings)| {
+                    let settings = settings
+                        .try_into()
+                        .with_context(|| format!("couldn't read the settings for {}", address))?;
+                    Ok((address, settings))
+                })
+                .collect::<Result<_>>()?,
+            Some(_) => bail!("devices should be a table of settings by Bluetooth address"),
+            None => HashMap::new(),
+        };
+
+        Ok(Config {
+            settings: table.try_into()?,
+            devices,
+        })
+    }
+
+    /// The settings for the Wiimote at `address`, if we know its address
+    pub fn settings_for(&self, address: Option<&str>) -> Settings {
+        let device = address.and_then(|address| {
+            self.devices
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(address))
+                .map(|(_, settings)| settings.clone())
+        });
+
+        device.unwrap_or_default().or(self.settings.clone())
+    }
+}
@@ -0,0 +1,261 @@
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use udev::Udev;
+
+use crate::color;
+use crate::evsieve;
+use crate::permissions::in_group;
+use crate::wiitar::Wiitar;
+
+/// Where udev rules and systemd units get installed, in the order udev and
+/// systemd look at them
+const RULES_DIRECTORIES: &[&str] = &["/etc/udev/rules.d", "/usr/lib/udev/rules.d"];
+const UNIT_DIRECTORIES: &[&str] = &["/etc/systemd/system", "/usr/lib/systemd/system"];
+
+/// How one of the checks went
+#[derive(Debug, Serialize)]
+struct Check {
+    name: &'static str,
+    passed: bool,
+
+    /// What was found, either way
+    detail: String,
+
+    /// What to do about it, if it failed
+    fix: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Check {
+            name,
+            passed: true,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Check {
+            name,
+            passed: false,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// Checks everything roadii needs to remap a guitar, printing whether each
+/// passed and how to fix any that didn't, or as JSON if `json`. Fails if
+/// any of them did.
+pub fn run(evsieve_path: Option<PathBuf>, json: bool) -> Result<()> {
+    let checks = checks(evsieve_path);
+
+    match json {
+        true => println!("{}", serde_json::to_string_pretty(&checks)?),
+        false => print!("{}", text(&checks, true)),
+    }
+
+    let failed = checks.iter().filter(|check| !check.passed).count();
+    if failed > 0 {
+        bail!("{} of {} checks failed", failed, checks.len());
+    }
+
+    Ok(())
+}
+
+/// Runs the checks, giving what `roadii doctor` would print
+pub fn summary(evsieve_path: Option<PathBuf>) -> String {
+    text(&checks(evsieve_path), false)
+}
+
+fn checks(evsieve_path: Option<PathBuf>) -> Vec<Check> {
+    vec![
+        evsieve(evsieve_path),
+        uinput(),
+        hid_wiimote(),
+        udev_rules(),
+        systemd_unit(),
+        input_group(),
+        wiimotes(),
+    ]
+}
+
+/// Whether each check passed, and how to fix any that didn't, a line each,
+/// coloured if `colored` and standard output wants it
+fn text(checks: &[Check], colored: bool) -> String {
+    let mut text = String::new();
+
+    for check in checks {
+        let (status, name) = match (check.passed, colored) {
+            (true, true) => (color::good("  ok"), color::strong(check.name)),
+            (false, true) => (color::bad("FAIL"), color::strong(check.name)),
+            (true, false) => ("  ok".to_owned(), check.name.to_owned()),
+            (false, false) => ("FAIL".to_owned(), check.name.to_owned()),
+        };
+        text.push_str(&format!("[{}] {}: {}\n", status, name, check.detail));
+
+        if let Some(fix) = &check.fix {
+            text.push_str(&format!("       {}\n", fix));
+        }
+    }
+
+    text
+}
+
+fn evsieve(evsieve_path: Option<PathBuf>) -> Check {
+    const NAME: &str = "evsieve";
+
+    match evsieve::find(evsieve_path) {
+        Ok((path, version)) => Check::pass(NAME, format!("evsieve {} at {}", version, path.display())),
+        Err(error) => Check::fail(
+            NAME,
+            format!("{:#}", error),
+            format!(
+                "Install evsieve {} or newer, or point --evsieve-path at it. Only --backend native works without it.",
+                evsieve::MINIMUM_VERSION
+            ),
+        ),
+    }
+}
+
+fn uinput() -> Check {
+    const NAME: &str = "uinput";
+    let path = Path::new("/dev/uinput");
+
+    if !path.exists() {
+        return Check::fail(
+            NAME,
+            "/dev/uinput doesn't exist",
+            "Load the uinput module with `modprobe uinput`.",
+        );
+    }
+
+    match writable(path) {
+        true => Check::pass(NAME, "/dev/uinput can be written to"),
+        false => Check::fail(
+            NAME,
+            "/dev/uinput can't be written to, so the virtual devices can't be created",
+            "Run roadii as root, as the example systemd service does.",
+        ),
+    }
+}
+
+fn hid_wiimote() -> Check {
+    const NAME: &str = "hid-wiimote";
+
+    // Built in or loaded as a module, it's here either way
+    match Path::new("/sys/bus/hid/drivers/wiimote").exists() {
+        true => Check::pass(NAME, "the hid-wiimote driver is loaded"),
+        false => Check::fail(
+            NAME,
+            "the hid-wiimote driver isn't loaded",
+            "Load it with `modprobe hid-wiimote`, or connect the Wiimote, which usually loads it.",
+        ),
+    }
+}
+
+fn udev_rules() -> Check {
+    const NAME: &str = "udev rules";
+
+    match find_mentioning(RULES_DIRECTORIES, "rules", "roadii") {
+        Some(path) => Check::pass(NAME, format!("found in {}", path.display())),
+        None => Check::fail(
+            NAME,
+            "no udev rule starts roadii when a guitar connects",
+            "Copy etc/udev/rules.d/99-roadii.rules to /etc/udev/rules.d, then run `udevadm control --reload`.",
+        ),
+    }
+}
+
+fn systemd_unit() -> Check {
+    const NAME: &str = "systemd service";
+
+    let found = UNIT_DIRECTORIES
+        .iter()
+        .map(|directory| Path::new(directory).join("roadii@.service"))
+        .find(|path| path.exists());
+
+    match found {
+        Some(path) => Check::pass(NAME, format!("found at {}", path.display())),
+        None => Check::fail(
+            NAME,
+            "roadii@.service isn't installed, so the udev rule has nothing to start",
+            "Copy etc/systemd/system/roadii@.service to /etc/systemd/system, then run `systemctl daemon-reload`.",
+        ),
+    }
+}
+
+fn input_group() -> Check {
+    const NAME: &str = "permissions";
+
+    // SAFETY: these only read the process's IDs
+    if unsafe { libc::geteuid() } == 0 {
+        return Check::pass(NAME, "running as root");
+    }
+
+    match in_group("input") {
+        true => Check::pass(NAME, "in the input group, so the Wiimote can be read"),
+        false => Check::fail(
+            NAME,
+            "not root or in the input group, so the Wiimote can't be read",
+            "Run roadii as root, as the example systemd service does, or add yourself to the input group.",
+        ),
+    }
+}
+
+fn wiimotes() -> Check {
+    const NAME: &str = "Wiimotes";
+
+    let guitars = Udev::new()
+        .context("couldn't get access to Udev")
+        .and_then(Wiitar::guitars);
+
+    match guitars {
+        Ok(guitars) if guitars.is_empty() => Check::fail(
+            NAME,
+            "no Wiimote with a guitar attached is connected",
+            "Connect one over Bluetooth, with the guitar plugged in before pressing 1 and 2.",
+        ),
+        Ok(guitars) => Check::pass(
+            NAME,
+            format!(
+                "{} connected with a guitar attached: {}",
+                guitars.len(),
+                guitars
+                    .iter()
+                    .map(|guitar| guitar.sysname().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        ),
+        Err(error) => Check::fail(NAME, format!("{:#}", error), "Check udev is running."),
+    }
+}
+
+/// The first file ending in `extension` in `directories` which mentions
+/// `needle`
+fn find_mentioning(directories: &[&str], extension: &str, needle: &str) -> Option<PathBuf> {
+    directories
+        .iter()
+        .filter_map(|directory| fs::read_dir(directory).ok())
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|found| found == extension))
+        .find(|path| fs::read_to_string(path).is_ok_and(|contents| contents.contains(needle)))
+}
+
+/// Whether we're allowed to write to `path`
+fn writable(path: &Path) -> bool {
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+
+    // SAFETY: the path is a valid C string for the length of the call
+    unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+}
@@ -0,0 +1,184 @@
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::ffi::OsString;
+use udev::{Device, Enumerator, Udev};
+
+use crate::failure::Failure;
+use crate::wiitar::{Part, Wiitar};
+
+/// Prints the device with `kernel_name`, or every Wiimote's guitar if not
+/// given, along with its parents and everything else under the Wiimote,
+/// showing the attributes discovery looks at and what it makes of each
+/// device
+pub fn run(kernel_name: Option<OsString>) -> Result<()> {
+    let udev = Udev::new().context("couldn't get access to Udev")?;
+
+    let devices = match kernel_name {
+        Some(kernel_name) => {
+            let mut enumerator = Enumerator::with_udev(udev.clone())
+                .context("couldn't start a device enumerator")?;
+            enumerator
+                .match_sysname(&kernel_name)
+                .with_context(|| format!("couldn't set {:?} as device matcher", kernel_name))?;
+
+            let devices: Vec<Device> = enumerator
+                .scan_devices()
+                .context("couldn't scan devices")?
+                .collect();
+            if devices.is_empty() {
+                return Err(Failure::NotFound
+                    .error(format!("couldn't find a device called {:?}", kernel_name)));
+            }
+
+            devices
+        }
+        None => {
+            let guitars = Wiitar::guitars(udev.clone())?;
+            if guitars.is_empty() {
+                return Err(Failure::NotFound.error("couldn't find a Wiimote with a guitar attached, pick a device with --kernel-name"));
+            }
+
+            guitars
+        }
+    };
+
+    for (index, device) in devices.iter().enumerate() {
+        if index > 0 {
+            println!();
+        }
+
+        dump(&udev, device)?;
+    }
+
+    Ok(())
+}
+
+fn dump(udev: &Udev, device: &Device) -> Result<()> {
+    println!("{}", line(device));
+
+    let name = attribute(device, "name");
+    match &name {
+        Some(name) if name.contains("Wii") && name.ends_with("Guitar") => {
+            println!("  looks like a Wii guitar")
+        }
+        Some(_) => println!("  rejected, its name doesn't look like a Wii guitar's"),
+        None => println!("  rejected, it has no name"),
+    }
+
+    let Some(wiimote) = device.parent() else {
+        println!("  rejected, it has no parent");
+        return Ok(());
+    };
+
+    println!();
+    println!("Parents:");
+    let mut parent = Some(wiimote.clone());
+    let mut depth = 1;
+    while let Some(device) = parent {
+        println!("{}{}", "  ".repeat(depth), line(&device));
+        parent = device.parent();
+        depth += 1;
+    }
+
+    if wiimote.subsystem() != Some("hid".as_ref()) {
+        println!("  rejected, its parent isn't a HID device");
+        return Ok(());
+    }
+    if wiimote.driver() != Some("wiimote".as_ref()) {
+        println!("  rejected, its parent isn't driven by hid-wiimote");
+        return Ok(());
+    }
+
+    let mut enumerator =
+        Enumerator::with_udev(udev.clone()).context("couldn't start a device enumerator")?;
+    enumerator
+        .match_parent(&wiimote)
+        .context("couldn't set the Wiimote as parent device matcher")?;
+    let mut descendants: Vec<Device> = enumerator
+        .scan_devices()
+        .context("couldn't scan the Wiimote's devices")?
+        .filter(|descendant| descendant.syspath() != wiimote.syspath())
+        .collect();
+    descendants.sort_by_key(|descendant| descendant.sysname().to_owned());
+
+    println!();
+    println!("Everything under {}:", wiimote.sysname().to_string_lossy());
+
+    let mut found = HashSet::new();
+    children(&wiimote, &descendants, device, 1, &mut found);
+
+    Ok(())
+}
+
+/// Prints the children of `parent` from `descendants` at `depth`, and their
+/// children under them, saying what discovery makes of each input device
+/// directly under the Wiimote. `found` is the parts seen so far, since only
+/// the first of each is used.
+fn children(
+    parent: &Device,
+    descendants: &[Device],
+    asked: &Device,
+    depth: usize,
+    found: &mut HashSet<Part>,
+) {
+    let indent = "  ".repeat(depth);
+
+    for child in descendants.iter().filter(|child| {
+        child
+            .parent()
+            .is_some_and(|p| p.syspath() == parent.syspath())
+    }) {
+        let mut line = format!("{}{}", indent, line(child));
+        if child.syspath() == asked.syspath() {
+            line.push_str(" (this one)");
+        }
+        println!("{}", line);
+
+        // Only the Wiimote's own input devices are considered
+        if depth == 1 && child.subsystem() == Some("input".as_ref()) {
+            let verdict = match attribute(child, "name").as_deref().map(Part::from_name) {
+                None => "skipped, it has no name".to_owned(),
+                Some(None) => "skipped, that name isn't part of a Wiitar".to_owned(),
+                Some(Some(part)) if !found.insert(part) => {
+                    format!("skipped, there's already a {}", part.description())
+                }
+                Some(Some(part)) => format!("used as the {}", part.description()),
+            };
+            println!("{}  {}", indent, verdict);
+        }
+
+        children(child, descendants, asked, depth + 1, found);
+    }
+}
+
+/// A device's kernel name and subsystem, with whichever of the attributes
+/// discovery cares about it has
+fn line(device: &Device) -> String {
+    let mut line = device.sysname().to_string_lossy().into_owned();
+
+    if let Some(subsystem) = device.subsystem() {
+        line.push_str(&format!(" [{}]", subsystem.to_string_lossy()));
+    }
+    if let Some(driver) = device.driver() {
+        line.push_str(&format!(" driver={}", driver.to_string_lossy()));
+    }
+    if let Some(name) = attribute(device, "name") {
+        line.push_str(&format!(" name={:?}", name));
+    }
+    if let Some(uniq) = attribute(device, "uniq").filter(|uniq| !uniq.is_empty()) {
+        line.push_str(&format!(" uniq={}", uniq));
+    }
+    if let Some(devnode) = device.devnode() {
+        line.push_str(&format!(" devnode={}", devnode.display()));
+    }
+
+    line.push_str(&format!(" ({})", device.syspath().display()));
+
+    line
+}
+
+fn attribute(device: &Device, name: &str) -> Option<String> {
+    device
+        .attribute_value(name)
+        .map(|value| value.to_string_lossy().into_owned())
+}
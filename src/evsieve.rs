@@ -0,0 +1,250 @@
+use anyhow::{bail, Context, Result};
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::failure::Failure;
+use crate::pipeline::{Pipeline, Step};
+
+/// The oldest `evsieve` with everything we rely on: `persist=exit` on
+/// inputs, `send-key` on hooks and `device-id` on outputs
+pub const MINIMUM_VERSION: Version = Version(1, 3, 0);
+
+/// A version of `evsieve`, as its major, minor and patch numbers
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version(pub u32, pub u32, pub u32);
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+/// Builds the `evsieve` invocation which runs `pipeline`, followed by
+/// `extra` arguments as they are, using the `evsieve` `find` finds
+pub fn command(
+    evsieve_path: Option<PathBuf>,
+    pipeline: &Pipeline,
+    extra: &[OsString],
+) -> Result<exec::Command> {
+    let (path, _) = find(evsieve_path)?;
+
+    let mut evsieve = exec::Command::new(path);
+    evsieve.args(&arguments(pipeline)?);
+    evsieve.args(extra);
+
+    Ok(evsieve)
+}
+
+/// Finds the `evsieve` at `evsieve_path`, or in `PATH` if that's not given
+/// or just a name, and checks it really is `evsieve`, and no older than
+/// `MINIMUM_VERSION`. Gives where it is and which version it is.
+pub fn find(evsieve_path: Option<PathBuf>) -> Result<(PathBuf, Version)> {
+    let path = resolve(&evsieve_path.unwrap_or("evsieve".into()))?;
+
+    let output = Command::new(&path)
+        .arg("--version")
+        .output()
+        .with_context(|| format!("couldn't run {}", path.display()))?;
+    let said = String::from_utf8_lossy(&output.stdout);
+
+    // It says something like `evsieve 1.4.0`
+    if !said.trim_start().to_lowercase().starts_with("evsieve") {
+        return Err(Failure::Evsieve.error(format!(
+                "{} doesn't seem to be evsieve, as it didn't say so when asked its version, point --evsieve-path at the right one",
+                path.display()
+            )));
+    }
+
+    let version = said
+        .split_whitespace()
+        .find_map(parse_version)
+        .filter(|_| output.status.success())
+        .ok_or_else(|| {
+            Failure::Evsieve.error(format!(
+                "{} didn't say which version it is, roadii needs evsieve {} or newer",
+                path.display(),
+                MINIMUM_VERSION
+            ))
+        })?;
+
+    if version < MINIMUM_VERSION {
+        return Err(Failure::Evsieve.error(format!(
+                "{} is evsieve {}, but roadii needs {} or newer for persist=exit, send-key and device-id",
+                path.display(),
+                version,
+                MINIMUM_VERSION
+            )));
+    }
+
+    Ok((path, version))
+}
+
+/// Where `path` is, looking through each of the directories in `PATH` if
+/// it's just a name, the same way running it would. Fails if it isn't there
+/// or can't be run, saying where it looked.
+fn resolve(path: &Path) -> Result<PathBuf> {
+    if path.components().count() > 1 {
+        if !path.exists() {
+            return Err(Failure::Evsieve.error(format!(
+                "{} doesn't exist, point --evsieve-path at evsieve",
+                path.display()
+            )));
+        }
+        if !executable(path) {
+            return Err(Failure::Evsieve.error(format!(
+                "{} can't be run, as it isn't an executable file",
+                path.display()
+            )));
+        }
+
+        return Ok(path.to_owned());
+    }
+
+    let search = env::var_os("PATH").unwrap_or_default();
+    let directories: Vec<PathBuf> = env::split_paths(&search).collect();
+
+    directories
+        .iter()
+        .map(|directory| directory.join(path))
+        .find(|candidate| executable(candidate))
+        .ok_or_else(|| {
+            let searched = directories
+                .iter()
+                .map(|directory| directory.display().to_string())
+                .collect::<Vec<_>>();
+
+            Failure::Evsieve.error(format!(
+                "couldn't find {} in any of {}, install evsieve or point --evsieve-path at it",
+                path.display(),
+                match searched.is_empty() {
+                    true => "PATH, which is empty".to_owned(),
+                    false => searched.join(", "),
+                }
+            ))
+        })
+}
+
+/// Whether `path` is a file which can be run
+fn executable(path: &Path) -> bool {
+    fs::metadata(path)
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+/// Reads a version like `1.4.0`, or `1.4` meaning `1.4.0`
+fn parse_version(version: &str) -> Option<Version> {
+    let mut numbers = version.split('.').map(|number| number.parse::<u32>().ok());
+
+    let major = numbers.next()??;
+    let minor = numbers.next()??;
+    let patch = numbers.next().unwrap_or(Some(0))?;
+
+    match numbers.next() {
+        None => Some(Version(major, minor, patch)),
+        Some(_) => None,
+    }
+}
+
+/// The arguments which make `evsieve` run `pipeline`
+pub fn arguments(pipeline: &Pipeline) -> Result<Vec<OsString>> {
+    if !pipeline.flats.is_empty() {
+        bail!("evsieve can't set the flat of an axis, try --backend native");
+    }
+
+    let mut arguments = Vec::new();
+
+    for step in &pipeline.steps {
+        arguments.extend(args(step, pipeline.mirror)?);
+    }
+
+    Ok(arguments)
+}
+
+/// The `evsieve` arguments for a single step, with inputs left ungrabbed
+/// if `mirror`
+fn args(step: &Step, mirror: bool) -> Result<Vec<OsString>> {
+    Ok(match step {
+        Step::Input { path, domain } => {
+            let mut args = vec![
+                "--input".into(),
+                path.into(),
+                format!("domain={}", domain).into(),
+            ];
+            if !mirror {
+                args.push("grab".into());
+            }
+            args.push("persist=exit".into());
+            args
+        }
+        Step::Map { source, targets } => ["--map".into(), source.to_string().into()]
+            .into_iter()
+            .chain(targets.iter().map(|target| target.to_string().into()))
+            .collect(),
+        Step::Copy { source, targets } => ["--copy".into(), source.to_string().into()]
+            .into_iter()
+            .chain(targets.iter().map(|target| target.to_string().into()))
+            .collect(),
+        Step::Block { keys } => std::iter::once("--block".into())
+            .chain(keys.iter().map(|key| key.to_string().into()))
+            .collect(),
+        Step::Chord { keys, target } => std::iter::once("--hook".into())
+            .chain(keys.iter().map(|key| key.to_string().into()))
+            .chain(std::iter::once(format!("send-key={}", target).into()))
+            .collect(),
+        Step::Output {
+            keys,
+            name,
+            device_id,
+        } => {
+            let mut args: Vec<OsString> = vec!["--output".into(), format!("name={}", name).into()];
+
+            if let Some((vendor, product)) = device_id {
+                args.push(format!("device-id={:04x}:{:04x}", vendor, product).into());
+            }
+
+            args.extend(keys.iter().map(|key| key.to_string().into()));
+
+            args
+        }
+        Step::Smooth { key, .. } => {
+            bail!("evsieve can't smooth out {}, try --backend native", key)
+        }
+        Step::Debounce { key, .. } => {
+            bail!("evsieve can't debounce {}, try --backend native", key)
+        }
+        Step::Toggle { key } => {
+            bail!("evsieve can't toggle {}, try --backend native", key)
+        }
+        Step::Switch { .. } => {
+            bail!("evsieve can't switch between profiles, try --backend native")
+        }
+        Step::Exit { .. } => {
+            bail!("evsieve can't stop on an exit chord, try --backend native")
+        }
+        Step::Pause { .. } => {
+            bail!("evsieve can't pause on a pause chord, try --backend native")
+        }
+        Step::Repeat { key, .. } => {
+            bail!("evsieve can't repeat {}, try --backend native", key)
+        }
+        Step::Sustain { key, .. } => {
+            bail!("evsieve can't keep {} going, try --backend native", key)
+        }
+        Step::LongPress { key, .. } => {
+            bail!(
+                "evsieve can't tell long presses of {} from taps, try --backend native",
+                key
+            )
+        }
+        Step::Macro { .. } => bail!("evsieve can't play macros, try --backend native"),
+        Step::Rumble { .. } => bail!("evsieve can't pass on rumble, try --backend native"),
+        Step::Script { .. } => bail!("evsieve can't run scripts, try --backend native"),
+        Step::Plugin { .. } => bail!("evsieve can't run plugins, try --backend native"),
+        Step::Magnitude { target, .. } => {
+            bail!("evsieve can't work out {}, try --backend native", target)
+        }
+    })
+}
@@ -0,0 +1,85 @@
+use anyhow::Error;
+use std::fmt;
+use std::io::{self, ErrorKind};
+
+/// The ways roadii can fail which scripts might want to tell apart, by
+/// the code it exits with. Anything else exits with 1.
+///
+/// Errors which should exit with one are made with `Failure::error`, like
+/// `Failure::NotFound.error("couldn't find input19")`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Failure {
+    /// 2: the command line needs to say more, like which guitar to use.
+    /// clap exits with this too when it can't make sense of the arguments.
+    Usage = 2,
+
+    /// 3: there's no device with the kernel name given, or no Wiimote with a
+    /// guitar attached at all
+    NotFound = 3,
+
+    /// 4: the device is there, but it isn't a guitar attached to a Wiimote
+    NotAGuitar = 4,
+
+    /// 5: the Wiimote is missing one of the devices roadii needs, like its
+    /// accelerometer
+    MissingPart = 5,
+
+    /// 6: evsieve isn't there, can't be run, or is too old
+    Evsieve = 6,
+
+    /// 7: something couldn't be opened for lack of permission, like one of
+    /// the Wiimote's devices or uinput
+    Permission = 7,
+
+    /// 8: another program has already grabbed one of the Wiimote's devices,
+    /// so roadii can't have it to itself
+    Grabbed = 8,
+
+    /// 9: there are already virtual devices with the names roadii would give
+    /// its own, most likely because it's already running
+    Duplicate = 9,
+}
+
+impl Failure {
+    /// An error saying `message`, which exits with this failure's code
+    pub fn error(self, message: impl fmt::Display) -> Error {
+        Error::new(Tagged {
+            failure: self,
+            message: message.to_string(),
+        })
+    }
+}
+
+/// An error made by `Failure::error`, which says what went wrong as usual
+/// and carries the failure along for `code` to find
+#[derive(Debug)]
+struct Tagged {
+    failure: Failure,
+    message: String,
+}
+
+impl fmt::Display for Tagged {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Tagged {}
+
+/// The code to exit with for `error`, from the first `Failure` found in its
+/// chain, or 1 if there isn't one
+pub fn code(error: &Error) -> u8 {
+    error
+        .chain()
+        .find_map(|cause| {
+            if let Some(tagged) = cause.downcast_ref::<Tagged>() {
+                return Some(tagged.failure);
+            }
+
+            cause
+                .downcast_ref::<io::Error>()
+                .filter(|error| error.kind() == ErrorKind::PermissionDenied)
+                .map(|_| Failure::Permission)
+        })
+        .map_or(1, |failure| failure as u8)
+}
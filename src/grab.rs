@@ -0,0 +1,179 @@
+use anyhow::{Context, Result};
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use crate::failure::Failure;
+use crate::outputs;
+use crate::permissions;
+use crate::pipeline::{Pipeline, Step};
+
+/// Makes sure nothing else has already grabbed the devices `pipeline` reads
+/// from, by grabbing each of them for a moment, and says which programs
+/// have one open if something has, since whatever grabbed it will be one of
+/// them.
+///
+/// When mirroring, even grabbing them for a moment would take them away from
+/// whatever we're sharing them with, so it goes on what the programs which
+/// have them open were started with instead.
+pub fn check(pipeline: &Pipeline) -> Result<()> {
+    for step in &pipeline.steps {
+        let Step::Input { path, .. } = step else {
+            continue;
+        };
+
+        if pipeline.mirror {
+            let grabbing: Vec<(u32, String)> = holders(path)
+                .into_iter()
+                .filter(|(pid, name)| grabs(*pid, name, path))
+                .collect();
+
+            if !grabbing.is_empty() {
+                return Err(Failure::Grabbed.error(format!(
+                    "{} is grabbed by {}, so none of its events would reach roadii to mirror. Stop it, or have it leave the guitar ungrabbed, and try again.",
+                    path.display(),
+                    list(&grabbing)
+                )));
+            }
+
+            continue;
+        }
+
+        let mut device = permissions::open(path)?;
+
+        match device.grab() {
+            Ok(()) => device
+                .ungrab()
+                .with_context(|| format!("couldn't let go of {}", path.display()))?,
+            Err(error) if error.raw_os_error() == Some(libc::EBUSY) => {
+                return Err(Failure::Grabbed.error(grabbed(path)));
+            }
+            Err(error) => {
+                return Err(error).with_context(|| format!("couldn't grab {}", path.display()))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Says `path` is grabbed, which programs have it open if we can tell,
+/// and what to do about it
+fn grabbed(path: &Path) -> String {
+    let holders = holders(path);
+
+    let mut message = format!(
+        "{} is already grabbed by another program, so roadii can't have it to itself",
+        path.display()
+    );
+
+    if holders.is_empty() {
+        message.push_str(". Stop whatever else might be using it, like Dolphin or another remapper, or run as root to see which program it is. If it can share the guitar instead, have it leave it ungrabbed and run roadii with --no-grab to share it.");
+        return message;
+    }
+
+    message.push_str(&format!(". It's open in {}", list(&holders)));
+
+    match holders
+        .iter()
+        .any(|(_, name)| name == "roadii" || name == "evsieve")
+    {
+        true => message.push_str(", so roadii may already be remapping it. Stop that one first, or run both with --no-grab to share it."),
+        false => message.push_str(", so stop whichever of them grabbed it, or have it leave the guitar ungrabbed and run roadii with --no-grab to share it."),
+    }
+
+    message
+}
+
+/// Lists processes for error messages, like `Dolphin (123), evsieve (456)`
+fn list(processes: &[(u32, String)]) -> String {
+    processes
+        .iter()
+        .map(|(pid, name)| format!("{} ({})", name, pid))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Whether process `pid`, called `name`, which has `path` open, has grabbed
+/// it too, going by its command line. That's only something roadii and
+/// evsieve say, so anything else is taken to be sharing it.
+fn grabs(pid: u32, name: &str, path: &Path) -> bool {
+    let Ok(cmdline) = fs::read(format!("/proc/{}/cmdline", pid)) else {
+        return false;
+    };
+    let args: Vec<&[u8]> = cmdline.split(|&byte| byte == 0).collect();
+
+    match name {
+        // Remapping is what has it make virtual devices, and it grabs what
+        // it reads unless it's mirroring too
+        "roadii" => {
+            !args
+                .iter()
+                .any(|arg| *arg == b"--no-grab" || *arg == b"--no-grab=true")
+                && holders(Path::new(outputs::UINPUT))
+                    .iter()
+                    .any(|(holder, _)| *holder == pid)
+        }
+        // Each --input is followed by its paths and then its flags, which
+        // include grab if it's to be grabbed
+        "evsieve" => args
+            .iter()
+            .enumerate()
+            .filter(|(_, arg)| **arg == b"--input")
+            .any(|(index, _)| {
+                let input: Vec<&[u8]> = args[index + 1..]
+                    .iter()
+                    .take_while(|arg| !arg.starts_with(b"--"))
+                    .copied()
+                    .collect();
+
+                let ours = input.iter().any(|arg| {
+                    Path::new(OsStr::from_bytes(arg))
+                        .canonicalize()
+                        .is_ok_and(|other| path.canonicalize().is_ok_and(|path| path == other))
+                });
+
+                ours && input
+                    .iter()
+                    .any(|arg| *arg == b"grab" || arg.starts_with(b"grab="))
+            }),
+        _ => false,
+    }
+}
+
+/// The process IDs and names of the other programs which have `path` open,
+/// as far as we're allowed to see
+pub fn holders(path: &Path) -> Vec<(u32, String)> {
+    let Ok(path) = path.canonicalize() else {
+        return Vec::new();
+    };
+    let Ok(processes) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+
+    let mut holders: Vec<(u32, String)> = processes
+        .flatten()
+        .filter_map(|process| {
+            let pid: u32 = process.file_name().to_str()?.parse().ok()?;
+            if pid == std::process::id() {
+                return None;
+            }
+
+            // Other users' processes can't be looked into without root
+            let open = fs::read_dir(process.path().join("fd"))
+                .ok()?
+                .flatten()
+                .any(|descriptor| fs::read_link(descriptor.path()).is_ok_and(|link| link == path));
+            if !open {
+                return None;
+            }
+
+            let name = fs::read_to_string(process.path().join("comm")).ok()?;
+            Some((pid, name.trim().to_owned()))
+        })
+        .collect();
+
+    holders.sort();
+    holders
+}
@@ -0,0 +1,43 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+
+/// The way the virtual output device presents itself to games
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Identity {
+    /// A generic device simply called "Wiitar"
+    #[default]
+    Wiitar,
+
+    /// A RedOctane Guitar Hero PlayStation 3 guitar, which RPCS3 and the
+    /// PS3 Guitar Hero and Rock Band titles expect to see
+    Ps3Guitar,
+
+    /// A Santroller guitar, which Clone Hero and YARG configure automatically
+    Santroller,
+}
+
+impl Identity {
+    /// The name the output device will be given, unless overridden
+    pub fn name(&self) -> &'static str {
+        match self {
+            Identity::Wiitar => "Wiitar",
+            // This is the product string the real guitar's dongle reports
+            Identity::Ps3Guitar => "Guitar Hero3 for PlayStation (R) 3",
+            Identity::Santroller => "Santroller",
+        }
+    }
+
+    /// The USB vendor and product IDs the output device will advertise, if any
+    pub fn device_id(&self) -> Option<(u16, u16)> {
+        match self {
+            Identity::Wiitar => None,
+            // Licensed by Sony Computer Entertainment America, the ID
+            // RedOctane used for the Guitar Hero PS3 guitar dongles
+            Identity::Ps3Guitar => Some((0x12ba, 0x0100)),
+            // Santroller's allocation from pid.codes, which is what the
+            // games actually look for when detecting one
+            Identity::Santroller => Some((0x1209, 0x2882)),
+        }
+    }
+}
@@ -0,0 +1,337 @@
+use anyhow::{anyhow, bail, Context, Error, Result};
+use evdev::{AbsoluteAxisCode, EventSummary, InputEvent, KeyCode, RelativeAxisCode};
+use std::fmt;
+use std::str::FromStr;
+
+/// The type of event a key refers to, the part before the first colon
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventType {
+    Key,
+    Btn,
+    Abs,
+    Rel,
+}
+
+impl EventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventType::Key => "key",
+            EventType::Btn => "btn",
+            EventType::Abs => "abs",
+            EventType::Rel => "rel",
+        }
+    }
+}
+
+impl FromStr for EventType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "key" => Ok(EventType::Key),
+            "btn" => Ok(EventType::Btn),
+            "abs" => Ok(EventType::Abs),
+            "rel" => Ok(EventType::Rel),
+            _ => bail!("{:?} is not an event type we know about", s),
+        }
+    }
+}
+
+/// An inclusive range of values, either end of which may be unbounded
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Range {
+    pub min: Option<i32>,
+    pub max: Option<i32>,
+}
+
+impl Range {
+    pub fn exactly(value: i32) -> Self {
+        Range {
+            min: Some(value),
+            max: Some(value),
+        }
+    }
+
+    pub fn contains(&self, value: i32) -> bool {
+        self.min.is_none_or(|min| value >= min) && self.max.is_none_or(|max| value <= max)
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) if min == max => write!(f, "{}", min),
+            (Some(min), Some(max)) => write!(f, "{}~{}", min, max),
+            (Some(min), None) => write!(f, "{}~", min),
+            (None, Some(max)) => write!(f, "~{}", max),
+            (None, None) => write!(f, "~"),
+        }
+    }
+}
+
+impl FromStr for Range {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = |bound: &str| -> Result<Option<i32>> {
+            if bound.is_empty() {
+                Ok(None)
+            } else {
+                Ok(Some(bound.parse().with_context(|| {
+                    format!("{:?} is not a valid value in {:?}", bound, s)
+                })?))
+            }
+        };
+
+        match s.split_once('~') {
+            Some((min, max)) => Ok(Range {
+                min: parse(min)?,
+                max: parse(max)?,
+            }),
+            None => Ok(Range::exactly(
+                parse(s)?.ok_or_else(|| anyhow!("a value can't be empty"))?,
+            )),
+        }
+    }
+}
+
+/// The value part of a key, after the code
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Value {
+    /// The value is within `current`, and if `previous` is given, the value
+    /// before this event was within that. On an output, a single value.
+    Range {
+        previous: Option<Range>,
+        current: Range,
+    },
+
+    /// On an output, the input's value, or if `delta` is set, how much it
+    /// changed from the previous value, scaled by `factor` then offset
+    Linear {
+        factor: f64,
+        offset: f64,
+        delta: bool,
+    },
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Range {
+                previous: Some(previous),
+                current,
+            } => write!(f, "{}..{}", previous, current),
+            Value::Range {
+                previous: None,
+                current,
+            } => write!(f, "{}", current),
+            Value::Linear {
+                factor,
+                offset,
+                delta,
+            } => {
+                let variable = if *delta { "d" } else { "x" };
+
+                if *factor == 1.0 {
+                    write!(f, "{}", variable)?;
+                } else if *factor == -1.0 {
+                    write!(f, "-{}", variable)?;
+                } else {
+                    write!(f, "{}{}", factor, variable)?;
+                }
+
+                if *offset > 0.0 {
+                    write!(f, "+{}", offset)?;
+                } else if *offset < 0.0 {
+                    write!(f, "{}", offset)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl FromStr for Value {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some((factor, offset, delta)) = s
+            .split_once('x')
+            .map(|(factor, offset)| (factor, offset, false))
+            .or_else(|| {
+                s.split_once('d')
+                    .map(|(factor, offset)| (factor, offset, true))
+            })
+        {
+            let factor = match factor {
+                "" => 1.0,
+                "-" => -1.0,
+                factor => factor
+                    .parse()
+                    .ok()
+                    .filter(|factor: &f64| factor.is_finite())
+                    .ok_or_else(|| anyhow!("{:?} is not a valid factor in {:?}", factor, s))?,
+            };
+            let offset = match offset {
+                "" => 0.0,
+                offset => offset
+                    .strip_prefix('+')
+                    .unwrap_or(offset)
+                    .parse()
+                    .ok()
+                    .filter(|offset: &f64| offset.is_finite())
+                    .ok_or_else(|| anyhow!("{:?} is not a valid offset in {:?}", offset, s))?,
+            };
+
+            return Ok(Value::Linear {
+                factor,
+                offset,
+                delta,
+            });
+        }
+
+        match s.split_once("..") {
+            Some((previous, current)) => Ok(Value::Range {
+                previous: Some(previous.parse()?),
+                current: current.parse()?,
+            }),
+            None => Ok(Value::Range {
+                previous: None,
+                current: s.parse()?,
+            }),
+        }
+    }
+}
+
+/// An event key, in the same syntax `evsieve` uses, like `btn:south@wiitar`
+/// or `abs:ry:-59~..~-60@accel`. Every part is optional, an empty part
+/// matches anything.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Key {
+    pub event_type: Option<EventType>,
+    pub code: Option<String>,
+    pub value: Option<Value>,
+    pub domain: Option<String>,
+}
+
+impl Key {
+    /// A key which matches every event in `domain`
+    pub fn domain(domain: &str) -> Self {
+        Key {
+            domain: Some(domain.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    /// The numeric event code, if this key refers to a specific code
+    pub fn code_number(&self) -> Option<u16> {
+        let code = self.code.as_ref()?;
+        let name = format!("{}_{}", self.event_type?.as_str(), code).to_uppercase();
+
+        match self.event_type? {
+            EventType::Key | EventType::Btn => KeyCode::from_str(&name).ok().map(|c| c.code()),
+            EventType::Abs => AbsoluteAxisCode::from_str(&name).ok().map(|c| c.0),
+            EventType::Rel => RelativeAxisCode::from_str(&name).ok().map(|c| c.0),
+        }
+    }
+
+    /// The key which exactly describes `event`, tagged with `domain`, if it's
+    /// a key, button or axis event we know the name of
+    pub fn from_event(event: &InputEvent, domain: &str) -> Option<Self> {
+        let name = match event.destructure() {
+            EventSummary::Key(_, code, _) => format!("{:?}", code),
+            EventSummary::AbsoluteAxis(_, code, _) => format!("{:?}", code),
+            EventSummary::RelativeAxis(_, code, _) => format!("{:?}", code),
+            _ => return None,
+        };
+
+        let (event_type, code) = name
+            .to_lowercase()
+            .split_once('_')
+            .map(|(event_type, code)| (event_type.to_owned(), code.to_owned()))?;
+
+        let key = Key {
+            event_type: Some(event_type.parse().ok()?),
+            code: Some(code),
+            value: Some(Value::Range {
+                previous: None,
+                current: Range::exactly(event.value()),
+            }),
+            domain: Some(domain.to_owned()),
+        };
+
+        // Buttons are named after whichever gamepad, joystick or mouse button
+        // they are, not all of which have a `btn:` name
+        (key.code_number() == Some(event.code())).then_some(key)
+    }
+
+    /// Whether this key refers to the absolute axis `code` in `domain`
+    pub fn is_axis(&self, code: &str, domain: &str) -> bool {
+        self.event_type == Some(EventType::Abs)
+            && self.code.as_deref() == Some(code)
+            && self.domain.as_deref() == Some(domain)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(event_type) = self.event_type {
+            write!(f, "{}", event_type.as_str())?;
+
+            if let Some(code) = &self.code {
+                write!(f, ":{}", code)?;
+
+                if let Some(value) = &self.value {
+                    write!(f, ":{}", value)?;
+                }
+            }
+        }
+
+        if let Some(domain) = &self.domain {
+            write!(f, "@{}", domain)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Key {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (event, domain) = match s.split_once('@') {
+            Some((event, domain)) => (event, Some(domain.to_owned())),
+            None => (s, None),
+        };
+
+        // Easily done when putting a key together, and it never matches
+        if domain.as_deref().is_some_and(|domain| domain.contains(':')) {
+            bail!("the domain should come after the value in {:?}", s);
+        }
+
+        let mut parts = event.splitn(3, ':');
+        let mut key = Key {
+            domain,
+            ..Default::default()
+        };
+
+        if let Some(event_type) = parts.next().filter(|part| !part.is_empty()) {
+            key.event_type = Some(event_type.parse().with_context(|| format!("in {:?}", s))?);
+        }
+
+        if let Some(code) = parts.next() {
+            key.code = Some(code.to_owned());
+
+            if key.code_number().is_none() {
+                bail!("{:?} is not an event code we know about in {:?}", code, s);
+            }
+        }
+
+        if let Some(value) = parts.next() {
+            key.value = Some(value.parse().with_context(|| format!("in {:?}", s))?);
+        }
+
+        Ok(key)
+    }
+}
@@ -0,0 +1,49 @@
+//! Roadii's workings, which the `roadii` command is built on, and which
+//! the fuzz targets and tests get at directly
+
+pub mod backend;
+pub mod battery;
+pub mod bench;
+pub mod calibration;
+pub mod color;
+pub mod completions;
+pub mod config;
+pub mod doctor;
+pub mod dump;
+pub mod evsieve;
+pub mod failure;
+pub mod grab;
+pub mod identity;
+pub mod key;
+pub mod list;
+pub mod log;
+pub mod man;
+pub mod mapping;
+pub mod memory;
+pub mod monitor;
+pub mod native;
+pub mod notify;
+pub mod observe;
+pub mod osc;
+pub mod outputs;
+#[cfg(feature = "websocket")]
+pub mod overlay;
+#[cfg(feature = "bluetooth")]
+pub mod pair;
+pub mod permissions;
+pub mod pipeline;
+pub mod plan;
+pub mod preset;
+pub mod priority;
+pub mod privileges;
+pub mod profile;
+pub mod record;
+pub mod report;
+pub mod resolve;
+pub mod sandbox;
+pub mod sdl;
+pub mod selftest;
+pub mod stats;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod wiitar;
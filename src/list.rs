@@ -0,0 +1,75 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use udev::Udev;
+
+use crate::battery;
+use crate::color;
+use crate::wiitar::Wiitar;
+
+/// A connected Wiimote with a guitar attached
+#[derive(Debug, Serialize)]
+struct Listed {
+    /// The kernel name of the guitar, to remap it by
+    kernel_name: String,
+
+    /// The Wiimote's Bluetooth address, if it has one
+    address: Option<String>,
+
+    /// How charged the Wiimote's batteries are, as a percentage, if that
+    /// can be found out
+    battery: Option<u8>,
+}
+
+/// Prints each connected Wiimote with a guitar attached, with the kernel
+/// name to remap it by, its Bluetooth address and how charged it is. If
+/// `json`, they're printed as a JSON array for scripts instead.
+pub fn run(json: bool) -> Result<()> {
+    let udev = Udev::new().context("couldn't get access to Udev")?;
+
+    let mut listed = Vec::new();
+
+    for guitar in Wiitar::guitars(udev.clone())? {
+        let battery = match guitar.parent() {
+            Some(wiimote) => battery::find(udev.clone(), &wiimote)?,
+            None => None,
+        };
+
+        listed.push(Listed {
+            kernel_name: guitar.sysname().to_string_lossy().into_owned(),
+            address: guitar
+                .attribute_value("uniq")
+                .map(|uniq| uniq.to_string_lossy().into_owned())
+                .filter(|uniq| !uniq.is_empty()),
+            battery: battery.and_then(|battery| battery::level(&battery).ok()),
+        });
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&listed)?);
+        return Ok(());
+    }
+
+    if listed.is_empty() {
+        println!("There are no Wiimotes with a guitar attached");
+        return Ok(());
+    }
+
+    for listed in listed {
+        let level = match listed.battery {
+            Some(level) if level < battery::DEFAULT_LOW => {
+                color::warning(format!("battery at {}%", level))
+            }
+            Some(level) => format!("battery at {}%", level),
+            None => "battery unknown".to_owned(),
+        };
+
+        println!(
+            "{}: {}, {}",
+            color::strong(&listed.kernel_name),
+            listed.address.as_deref().unwrap_or("unknown address"),
+            level
+        );
+    }
+
+    Ok(())
+}
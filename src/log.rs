@@ -0,0 +1,95 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::Span;
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+use tracing_subscriber::prelude::*;
+
+use crate::color;
+use crate::wiitar::Wiitar;
+
+/// How what roadii is up to gets written out
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Plain lines on standard output
+    #[default]
+    Text,
+
+    /// A JSON object a line on standard output, for log collectors
+    Json,
+
+    /// Straight to the systemd journal, with the Wiimote's address, syspath
+    /// and the session as fields which can be searched on, like
+    /// `journalctl ROADII_ADDRESS=00:19:1d:…`
+    Journald,
+}
+
+/// Sets up logging in `format`. Shows info and above, or more for each of
+/// `verbose`, or only errors if `quiet`, unless `RUST_LOG` says otherwise.
+/// Text is coloured as `color::init` was told to.
+pub fn init(format: LogFormat, verbose: u8, quiet: bool) -> Result<()> {
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::ERROR,
+        (false, 0) => LevelFilter::INFO,
+        (false, 1) => LevelFilter::DEBUG,
+        (false, _) => LevelFilter::TRACE,
+    };
+
+    let filter = EnvFilter::builder()
+        .with_default_directive(level.into())
+        .from_env_lossy();
+
+    let registry = tracing_subscriber::registry().with(filter);
+
+    match format {
+        LogFormat::Text => registry
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(color::stdout())
+                    .with_target(false)
+                    .without_time(),
+            )
+            .init(),
+        LogFormat::Json => registry
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .with_current_span(true),
+            )
+            .init(),
+        LogFormat::Journald => registry
+            .with(
+                tracing_journald::layer()
+                    .context("couldn't connect to the journal")?
+                    .with_field_prefix(Some("ROADII".to_owned())),
+            )
+            .init(),
+    }
+
+    Ok(())
+}
+
+/// A span for everything done with `parts`, so each line logged says which
+/// Wiimote it's about, and which time it was connected
+pub fn session(parts: &Wiitar) -> Span {
+    let syspath = parts
+        .wiimote
+        .as_ref()
+        .map(|wiimote| wiimote.syspath().display().to_string());
+
+    // systemd gives each run of the service its own ID, which is as good a
+    // session as any, otherwise roughly when we started will do
+    let session = std::env::var("INVOCATION_ID").unwrap_or_else(|_| {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        format!("{:x}", started.as_micros())
+    });
+
+    tracing::info_span!(
+        "wiitar",
+        address = parts.address.as_deref(),
+        syspath = syspath.as_deref(),
+        session = session.as_str(),
+    )
+}
@@ -1,17 +1,46 @@
-use anyhow::{anyhow, bail};
-use anyhow::{Context, Result};
-use clap::Parser;
+use anyhow::{bail, Result};
+use clap::{ArgAction, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::{ArgValueCompleter, CompleteEnv};
 use std::ffi::OsString;
 use std::path::PathBuf;
-use udev::{Device, Enumerator, Udev};
+use std::process::ExitCode;
+use std::time::Duration;
+use tracing::{info, warn};
+
+#[cfg(feature = "websocket")]
+use roadii::overlay;
+#[cfg(feature = "bluetooth")]
+use roadii::pair;
+#[cfg(feature = "tui")]
+use roadii::tui;
+use roadii::{
+    backend, battery, bench, calibration, color, completions, config, doctor, dump, evsieve,
+    failure, grab, list, log, man, mapping, memory, monitor, native, notify, observe, osc, outputs,
+    permissions, plan, priority, privileges, record, report, resolve, sdl, selftest, stats, wiitar,
+};
+
+use backend::Backend;
+use calibration::Calibration;
+use config::{Config, Settings};
+use failure::Failure;
+use log::LogFormat;
+use native::Stopped;
+use notify::Notifier;
+use record::Recording;
+use stats::Stats;
+use wiitar::Wiitar;
 
 /// Wii Guitar mapping utility
 #[derive(Parser, Debug)]
+#[command(subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// The kernel name of the device to match, for example `input19`.
     /// If it is a Wiimote with a guitar attached it will be remapped.
-    #[arg(short, long)]
-    kernel_name: OsString,
+    #[arg(short, long, required = true, add = ArgValueCompleter::new(completions::kernel_names))]
+    kernel_name: Option<OsString>,
 
     /// The path to the `evsieve` binary, useful if it isn't
     /// available in the `PATH` environment variable.
@@ -19,279 +48,640 @@ struct Args {
     /// If not supplied, `evsieve` will be run from the PATH.
     #[arg(short, long)]
     evsieve_path: Option<PathBuf>,
+
+    /// The path to the configuration file.
+    ///
+    /// If not supplied, `/etc/roadii/config.toml` is used if it exists.
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// The path to the file calibrations are saved to.
+    ///
+    /// If not supplied, `/var/lib/roadii/calibration.toml` is used.
+    #[arg(long)]
+    calibration: Option<PathBuf>,
+
+    /// Finds the Wiimote's devices, then just prints the events they send
+    /// without grabbing them or creating anything, to check it all works
+    #[arg(long)]
+    observe: bool,
+
+    /// When to colour what roadii writes out. `NO_COLOR` turns it off for
+    /// `auto`.
+    #[arg(long, value_enum, global = true, default_value_t = color::When::default())]
+    color: color::When,
+
+    /// How to write out what roadii is up to
+    #[arg(long, value_enum, global = true, default_value_t = LogFormat::default())]
+    log_format: LogFormat,
+
+    /// Writes out more about what roadii is up to, like each device
+    /// considered while finding the Wiimote and why it was skipped. Twice
+    /// writes out even more.
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Only writes out errors. `RUST_LOG` overrides this and `--verbose`,
+    /// like `RUST_LOG=debug`.
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Finds the Wiimote's devices and works out the mapping, then prints
+    /// which devices would be grabbed and the `evsieve` command, or the
+    /// native backend's steps, without running anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Finds the Wiimote's devices and works out the mapping, then writes a
+    /// shell script to this path which runs `evsieve` the same way, without
+    /// running anything
+    #[arg(long, value_name = "PATH", conflicts_with = "dry_run")]
+    emit_script: Option<PathBuf>,
+
+    /// Finds the Wiimote's devices, then checks they and uinput can be
+    /// opened, saying how to fix any which can't, without running anything
+    #[arg(long)]
+    check_permissions: bool,
+
+    #[command(flatten)]
+    settings: Settings,
+
+    /// Anything after `--` is passed on to `evsieve` as it is, after the
+    /// arguments roadii builds, for hooks, maps or prints roadii has no
+    /// option for
+    #[arg(last = true, value_name = "EVSIEVE ARGUMENTS")]
+    evsieve_args: Vec<OsString>,
 }
 
-#[derive(Debug, Default)]
-struct Wiitar {
-    wiimote: Option<Device>,
-    guitar: Option<Device>,
-    accel: Option<Device>,
+/// Things to do other than remapping a Wiitar
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Prints the line for `SDL_GAMECONTROLLERCONFIG` which describes the
+    /// gamepad, so SDL games know what each of its controls is
+    SdlMapping {
+        /// The Bluetooth address of the Wiimote, to pick up its settings
+        /// from the configuration file
+        #[arg(short, long, add = ArgValueCompleter::new(completions::addresses))]
+        address: Option<String>,
+    },
+
+    /// Checks everything roadii needs, like evsieve, uinput, the hid-wiimote
+    /// driver and the udev rules, and says how to fix anything missing
+    Doctor {
+        /// Prints the checks as JSON, for scripts and launchers
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Prints the device given by `--kernel-name`, or every Wiimote's
+    /// guitar, with its parents and everything else under the Wiimote, and
+    /// what finding the Wiimote's devices makes of each, to see why one
+    /// isn't found
+    Dump,
+
+    /// Lists the connected Wiimotes with a guitar attached, with their
+    /// kernel names, Bluetooth addresses and battery levels
+    List {
+        /// Prints them as JSON, for scripts and launchers
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Prints where the devices of the Wiimote with a guitar attached which
+    /// `selector` picks out are, one `part path` a line, for scripts which
+    /// drive evsieve or other tools themselves
+    Resolve {
+        /// The kernel name of any of the Wiimote's devices, its Bluetooth
+        /// address, or a path to one of its device nodes, like a
+        /// `/dev/input/by-id` link
+        #[arg(add = ArgValueCompleter::new(completions::selectors))]
+        selector: String,
+
+        /// Prints them as a JSON object, with the guitar's kernel name and
+        /// the Wiimote's address, for scripts and launchers
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Pairs with a Wiimote once its red sync button is pressed, trusts it
+    /// so it reconnects by itself, and checks the hid-wiimote driver takes
+    /// it
+    Pair {
+        /// How many seconds to look for the Wiimote for
+        #[arg(short, long, default_value_t = 30)]
+        timeout: u64,
+    },
+
+    /// Keeps looking out for paired and trusted Wiimotes, and connects to
+    /// any which turn up when a button is pressed, so remapping starts
+    /// again by itself
+    Reconnect,
+
+    /// Shows the events the Wiimote, guitar and accelerometer send side by
+    /// side, with the guitar's controls named. Uses the guitar given by
+    /// `--kernel-name`, or the only one connected.
+    Monitor {
+        /// Also shows what roadii's virtual devices send, if it's already
+        /// remapping this guitar. It has the guitar to itself while it's
+        /// remapping, so the other columns stay empty unless it's paused.
+        #[arg(short, long)]
+        output: bool,
+    },
+
+    /// Shows what the frets, strum, whammy, stick and tilt are doing in the
+    /// terminal, and whether star power would go off. Uses the guitar given
+    /// by `--kernel-name`, or the only one connected.
+    Tui,
+
+    /// Records the events the Wiimote, guitar and accelerometer send to a
+    /// file, for bug reports and working out what went wrong later. Uses the
+    /// guitar given by `--kernel-name`, or the only one connected.
+    Record {
+        /// The file to record to
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Also records what roadii's virtual devices send, if it's already
+        /// remapping this guitar
+        #[arg(long)]
+        with_output: bool,
+    },
+
+    /// Feeds a recording through the mapping as `--backend native` would,
+    /// printing what comes out. If the recording has roadii's output in it
+    /// too, fails if the replay doesn't produce the same.
+    Replay {
+        /// The recording to replay
+        recording: PathBuf,
+
+        /// The Bluetooth address of the Wiimote, to pick up its settings
+        /// from the configuration file
+        #[arg(short, long, add = ArgValueCompleter::new(completions::addresses))]
+        address: Option<String>,
+
+        /// Writes what comes out to virtual devices at the pace it was
+        /// recorded at, rather than printing it
+        #[arg(long)]
+        emit: bool,
+    },
+
+    /// Asks for each of the guitar's buttons to be pressed in turn, and
+    /// checks roadii's virtual devices send what they should, to see the
+    /// whole Wiitar works. Needs roadii to already be remapping the guitar
+    /// given by `--kernel-name`, or the only one connected.
+    Test,
+
+    /// Bundles up everything useful for working out what's wrong, like the
+    /// configuration, udev's view of the Wiimote and roadii's logs, into one
+    /// file to attach to a bug report. Bluetooth addresses and the hostname
+    /// are left out.
+    Report {
+        /// Where to write the report
+        #[arg(short, long, default_value = "roadii-report.tar.gz")]
+        output: PathBuf,
+    },
+
+    /// Times how long the backend takes to pass a fret press through the
+    /// mapping, using virtual devices in place of a Wiimote
+    Bench {
+        /// How many presses to time
+        #[arg(short, long, default_value_t = 200)]
+        presses: usize,
+    },
+
+    /// Measures how far a control on the guitar actually travels, and saves
+    /// it for that Wiimote. Uses the guitar given by `--kernel-name`, or
+    /// the only one connected.
+    Calibrate {
+        #[arg(value_enum)]
+        control: Calibratable,
+    },
+
+    /// Prints a script which sets up a shell to complete roadii's
+    /// arguments, including the kernel names and addresses of whichever
+    /// Wiimotes are connected
+    Completions {
+        #[arg(value_enum)]
+        shell: completions::Shell,
+    },
+
+    /// Writes man pages for roadii and each of its subcommands, for
+    /// packagers to install
+    Man {
+        /// The directory to write them to
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+    },
 }
 
-impl Wiitar {
-    fn from_kernel_name(kernel_name: OsString) -> Result<Self> {
-        let udev = Udev::new().context("couldn't get access to Udev")?;
+/// The controls which can be calibrated
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Calibratable {
+    Whammy,
 
-        Self::from_kernel_name_with_udev(kernel_name, udev)
+    /// Where the screen is, as far as the IR camera is concerned, for
+    /// absolute pointers and the lightgun
+    Pointer,
+}
+
+fn main() -> ExitCode {
+    // When a shell asks what to complete, answer and stop there
+    CompleteEnv::with_factory(Args::command)
+        .var(completions::VARIABLE)
+        .complete();
+
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{} {:?}", color::error("Error:"), error);
+            ExitCode::from(failure::code(&error))
+        }
     }
+}
 
-    fn from_kernel_name_with_udev(kernel_name: OsString, udev: Udev) -> Result<Self> {
-        let guitar = {
-            let mut kernel_name_enumerator = Enumerator::with_udev(udev.clone())
-                .context("couldn't start a device enumerator")?;
-            kernel_name_enumerator
-                .match_sysname(&kernel_name)
-                .unwrap_or_else(|_| {
-                    panic!("couldn't set {:?} as parent device matcher", kernel_name)
-                });
-
-            let matching_devices: Vec<Device> = kernel_name_enumerator
-                .scan_devices()
-                .context("couldn't scan devices")?
-                .collect();
-
-            if matching_devices.len() != 1 {
-                bail!(
-                    "couldn't find a single matching device for {:?}",
-                    kernel_name
+fn run() -> Result<()> {
+    // We put this in a block so the main function can drop
+    // everything else afterwards in preparation for exec'ing
+    let mut evsieve = {
+        let args = Args::parse();
+        color::init(args.color);
+        // What resolve prints is for scripts to read, so it only says what
+        // it's up to when asked
+        let quiet = args.quiet
+            || (matches!(args.command, Some(Command::Resolve { .. })) && args.verbose == 0);
+        log::init(args.log_format, args.verbose, quiet)?;
+
+        let config = Config::load(args.config.as_deref())?;
+
+        let calibration_path = calibration::path(args.calibration);
+        let calibration = Calibration::load(&calibration_path)?;
+
+        match args.command {
+            Some(Command::SdlMapping { address }) => {
+                let settings = args
+                    .settings
+                    .resolve(&config, &calibration, address.as_deref());
+                println!("{}", sdl::mapping(&settings)?);
+                return Ok(());
+            }
+            Some(Command::Calibrate { control }) => {
+                let parts = match args.kernel_name {
+                    Some(kernel_name) => Wiitar::from_kernel_name(kernel_name)?,
+                    None => Wiitar::find()?,
+                };
+
+                let settings =
+                    args.settings
+                        .resolve(&config, &calibration, parts.address.as_deref());
+
+                return match control {
+                    Calibratable::Whammy => calibration::whammy(
+                        parts,
+                        settings.whammy_source.unwrap_or_default(),
+                        &calibration_path,
+                    ),
+                    Calibratable::Pointer => calibration::pointer(parts, &calibration_path),
+                };
+            }
+            Some(Command::Doctor { json }) => return doctor::run(args.evsieve_path, json),
+            Some(Command::Dump) => return dump::run(args.kernel_name),
+            Some(Command::Completions { shell }) => return completions::run(shell),
+            Some(Command::Man { output }) => return man::run(Args::command(), &output),
+            Some(Command::List { json }) => return list::run(json),
+            Some(Command::Resolve { selector, json }) => return resolve::run(&selector, json),
+            Some(Command::Report { output }) => {
+                let config_path = args
+                    .config
+                    .unwrap_or_else(|| PathBuf::from(config::DEFAULT_PATH));
+
+                return report::run(
+                    &output,
+                    report::Sources {
+                        evsieve_path: args.evsieve_path,
+                        config_path: &config_path,
+                        config: &config,
+                        calibration: &calibration,
+                        settings: &args.settings,
+                    },
                 );
             }
+            Some(Command::Monitor { output }) => {
+                let parts = match args.kernel_name {
+                    Some(kernel_name) => Wiitar::from_kernel_name(kernel_name)?,
+                    None => Wiitar::find()?,
+                };
 
-            matching_devices[0].clone()
-        };
+                // The pipeline knows what the virtual devices are called
+                let pipeline = match output {
+                    true => {
+                        let settings =
+                            args.settings
+                                .resolve(&config, &calibration, parts.address.as_deref());
+                        Some(mapping::pipeline(&parts, &settings)?)
+                    }
+                    false => None,
+                };
 
-        {
-            // First up, we want to bail if this device doesn't pass our basic
-            // sniff test. Theoretically the udev rule should guard against
-            // this too but better to make sure than not!
-            let name = guitar
-                .attribute_value("name")
-                .context("This device has no name? That's very strange.")?
-                .to_string_lossy();
-
-            // Unfortunately, despite an `extension` attribute on the hid-wiimote
-            // driver, it isn't accessible after mount, so we may need to rely on
-            // the display name, which is kind of strange, but if it works?
-            if !name.contains("Wii") || !name.ends_with("Guitar") {
-                bail!("That's a weird looking Wii Guitar (are the udev rules set right?)");
+                return monitor::run(&parts, pipeline.as_ref());
             }
-        }
+            Some(Command::Record {
+                output,
+                with_output,
+            }) => {
+                let parts = match args.kernel_name {
+                    Some(kernel_name) => Wiitar::from_kernel_name(kernel_name)?,
+                    None => Wiitar::find()?,
+                };
+
+                let pipeline = match with_output {
+                    true => {
+                        let settings =
+                            args.settings
+                                .resolve(&config, &calibration, parts.address.as_deref());
+                        Some(mapping::pipeline(&parts, &settings)?)
+                    }
+                    false => None,
+                };
 
-        // Next, we need to look at the parent device. Ultimately we want to
-        // operate on the guitar device's siblings, but to get those we first
-        // need to look at the parent, so, here we go...
-        let wiimote = guitar
-            .parent()
-            .context("guitar didn't have a parent device")?;
-
-        {
-            // Sanity checks; the parent should be a hid-wiimote device
-            if wiimote
-                .subsystem()
-                .context("The parent of the wiitar didn't have a subsystem")?
-                != "hid"
-            {
-                bail!("The parent of the Wiitar is not a HID device?");
+                return record::run(&parts, pipeline.as_ref(), &output);
             }
+            Some(Command::Replay {
+                recording,
+                address,
+                emit,
+            }) => {
+                let settings = args
+                    .settings
+                    .resolve(&config, &calibration, address.as_deref());
+
+                let recording = Recording::load(&recording)?;
+                let pipeline = mapping::replay_pipeline(&settings, &recording.source_ranges())?;
+
+                return native::replay(&pipeline, &recording, emit);
+            }
+            Some(Command::Test) => {
+                let parts = match args.kernel_name {
+                    Some(kernel_name) => Wiitar::from_kernel_name(kernel_name)?,
+                    None => Wiitar::find()?,
+                };
+
+                // This has to match what the running roadii was given
+                let settings =
+                    args.settings
+                        .resolve(&config, &calibration, parts.address.as_deref());
 
-            if wiimote
-                .driver()
-                .context("The parent of the wiitar didn't have a driver")?
-                != "wiimote"
-            {
-                bail!("The parent of the Wiitar is an HID device but not a Wiimote?");
+                return selftest::run(&mapping::pipeline(&parts, &settings)?);
             }
-        }
+            Some(Command::Bench { presses }) => {
+                let settings = args.settings.resolve(&config, &calibration, None);
 
-        println!(
-            "Looks like {} is a Wiimote, with a guitar attached at {}!",
-            wiimote.sysname().to_string_lossy(),
-            guitar.sysname().to_string_lossy()
-        );
-
-        // Cool, let's get the party started, now we initialise our struct
-        let mut inputs: Self = Default::default();
-
-        {
-            // Now we want to query siblings of the guitar
-            let mut sibling_enumerator = Enumerator::with_udev(udev.clone())
-                .context("couldn't start a device enumerator")?;
-            sibling_enumerator
-                .match_parent(&wiimote)
-                .context("couldn't set wiimote as parent device matcher")?;
-            sibling_enumerator
-                .match_subsystem("input")
-                .context("couldn't set input as device subsystem matcher")?;
-
-            for device in sibling_enumerator
-                .scan_devices()
-                .context("couldn't scan sibling devices")?
-                .filter(|device| {
-                    device.syspath() != wiimote.syspath()
-                        && device.parent().expect("device had no parent").syspath()
-                            == wiimote.syspath()
-                })
-            {
-                // Like mentioned above, the name is the best we can match
-                // these on, thankfully these strings are constants in the
-                // Linux kernel, and unlikely to change much, if at all.
-                match device.attribute_value("name") {
-                    Some(os_name) => match os_name.to_string_lossy().into_owned().as_str() {
-                        "Nintendo Wii Remote" => {
-                            if inputs.wiimote.is_none() {
-                                let wiimote = Self::get_event_device_from_input_device_with_udev(
-                                    &device,
-                                    udev.clone(),
-                                )?;
-                                inputs.wiimote = Some(wiimote);
-                            }
-                        }
-                        "Nintendo Wii Remote Guitar" => {
-                            if inputs.guitar.is_none() {
-                                let guitar = Self::get_event_device_from_input_device_with_udev(
-                                    &device,
-                                    udev.clone(),
-                                )?;
-                                inputs.guitar = Some(guitar);
-                            }
-                        }
-                        "Nintendo Wii Remote Accelerometer" => {
-                            if inputs.accel.is_none() {
-                                let accel = Self::get_event_device_from_input_device_with_udev(
-                                    &device,
-                                    udev.clone(),
-                                )?;
-                                inputs.accel = Some(accel);
-                            }
-                        }
-                        &_ => continue,
-                    },
-                    None => continue,
+                return bench::run(&settings, args.evsieve_path, presses);
+            }
+            #[cfg(feature = "tui")]
+            Some(Command::Tui) => {
+                let parts = match args.kernel_name {
+                    Some(kernel_name) => Wiitar::from_kernel_name(kernel_name)?,
+                    None => Wiitar::find()?,
                 };
 
-                if inputs.is_complete() {
-                    break;
-                }
+                // The tilt and whammy settings say when star power goes off
+                // and how far the whammy goes
+                let settings =
+                    args.settings
+                        .resolve(&config, &calibration, parts.address.as_deref());
+
+                return tui::run(&parts, &settings);
+            }
+            #[cfg(not(feature = "tui"))]
+            Some(Command::Tui) => bail!("roadii was built without the TUI"),
+            #[cfg(feature = "bluetooth")]
+            Some(Command::Pair { timeout }) => return pair::run(Duration::from_secs(timeout)),
+            #[cfg(feature = "bluetooth")]
+            Some(Command::Reconnect) => return pair::reconnect(),
+            #[cfg(not(feature = "bluetooth"))]
+            Some(Command::Pair { .. } | Command::Reconnect) => {
+                bail!("roadii was built without Bluetooth support")
             }
+            None => {}
         }
 
-        if !inputs.is_complete() {
-            bail!("Failed to find wiimote, guitar and accelerometer input devices");
+        let kernel_name = args.kernel_name.expect("clap requires a kernel name");
+        let parts = Wiitar::from_kernel_name(kernel_name)?;
+        let _session = log::session(&parts).entered();
+
+        if args.check_permissions {
+            return permissions::check(&parts);
         }
 
-        Ok(inputs)
-    }
+        if args.observe {
+            return observe::run(&parts);
+        }
 
-    fn get_event_device_from_input_device_with_udev(device: &Device, udev: Udev) -> Result<Device> {
-        let mut enumerator =
-            Enumerator::with_udev(udev).context("couldn't start a device enumerator")?;
-        enumerator
-            .match_parent(device)
-            .context("couldn't set device as parent device matcher")?;
-        enumerator
-            .match_subsystem("input")
-            .context("couldn't set event as device subsystem matcher")?;
-
-        for child in enumerator
-            .scan_devices()
-            .context("couldn't scan sibling devices")?
-        {
-            if child.syspath() == device.syspath() {
-                continue;
+        let settings = args
+            .settings
+            .resolve(&config, &calibration, parts.address.as_deref());
+
+        let pipeline = mapping::pipeline(&parts, &settings)?;
+        let backend = settings.backend.unwrap_or_default();
+
+        let idle = settings
+            .idle_timeout
+            .filter(|&minutes| minutes > 0)
+            .map(|minutes| Duration::from_secs(minutes * 60));
+        let stall = settings
+            .stall_timeout
+            .filter(|&seconds| seconds > 0)
+            .map(Duration::from_secs);
+        let sandbox = settings.sandbox.unwrap_or_default();
+
+        if backend == Backend::Native && !args.evsieve_args.is_empty() {
+            bail!("the native backend doesn't take evsieve arguments, try --backend evsieve");
+        }
+
+        if backend == Backend::Evsieve {
+            if idle.is_some() {
+                bail!("evsieve can't stop when nothing's being pressed, try --backend native");
+            }
+            if stall.is_some() {
+                bail!("evsieve can't tell when the Wiimote stalls, try --backend native");
+            }
+            if settings.websocket.is_some() || settings.osc.is_some() {
+                bail!("evsieve can't stream the guitar's state, try --backend native");
+            }
+            if settings.user.is_some() {
+                bail!("evsieve opens the devices itself, so it can't carry on as another user, try --backend native");
+            }
+            if sandbox {
+                bail!("evsieve can't be sandboxed, try --backend native");
             }
+            if settings.realtime.is_some() || settings.nice.is_some() {
+                bail!("evsieve runs its own event loop, so its priority can't be set, try --backend native");
+            }
+            if settings.mlock.is_some() {
+                bail!("evsieve's memory can't be locked, try --backend native");
+            }
+        }
 
-            if child.sysname().to_string_lossy().starts_with("event") {
-                return Ok(child);
+        if sandbox {
+            if settings.websocket.is_some() {
+                bail!("the sandbox doesn't allow accepting WebSocket connections");
+            }
+            if idle.is_some() {
+                bail!("the sandbox doesn't allow disconnecting the Wiimote when it's idle");
+            }
+            if stall.is_some() {
+                bail!(
+                    "the sandbox doesn't allow opening the Wiimote's devices again when it stalls"
+                );
             }
         }
 
-        bail!("didn't find a child event device")
-    }
+        // Only root can open the Wiimote's devices, unless the udev rules
+        // let someone else, and by the time it stalls we aren't root any more
+        if stall.is_some() && settings.user.is_some() {
+            warn!("Once it's running as another user, roadii can only open a stalled Wiimote's devices again if that user is allowed to");
+        }
 
-    fn is_complete(&self) -> bool {
-        self.wiimote.is_some() && self.guitar.is_some() && self.accel.is_some()
-    }
-}
+        let user = match (&settings.user, &settings.group) {
+            (Some(user), group) => Some(privileges::User::lookup(user, group.as_deref())?),
+            (None, Some(_)) => bail!("--group says which group to carry on as with --user"),
+            (None, None) => None,
+        };
 
-fn main() -> Result<()> {
-    // We put this in a block so the main function can drop
-    // everything else afterwards in preparation for exec'ing
-    let mut evsieve = {
-        let args = Args::parse();
+        if args.dry_run {
+            return plan::print(&pipeline, backend, args.evsieve_path, &args.evsieve_args);
+        }
+
+        if let Some(path) = args.emit_script {
+            if backend == Backend::Native {
+                bail!("the script runs evsieve, so it can't use --backend native");
+            }
 
-        let parts = Wiitar::from_kernel_name(args.kernel_name)?;
-
-        let mut evsieve = exec::Command::new(args.evsieve_path.unwrap_or("evsieve".into()));
-
-        evsieve
-            .arg("--input")
-            .arg(
-                parts
-                    .wiimote
-                    .ok_or(anyhow!("missing wiimote"))?
-                    .devnode()
-                    .ok_or(anyhow!("failed to retrieve wiimote devnode"))?,
-            )
-            .args(&["domain=wiimote", "grab", "persist=exit"]);
-
-        evsieve.args(&["--map", "btn:south@wiimote", "btn:mode@wiitar"]);
-        evsieve.args(&["--map", "btn:1@wiimote", "btn:thumbl@wiitar"]);
-        evsieve.args(&["--map", "btn:2@wiimote", "btn:thumbr@wiitar"]);
-        evsieve.args(&["--map", "btn:mode@wiimote", "btn:z@wiitar"]);
-        evsieve.args(&["--map", "key:next@wiimote", "btn:start@wiitar"]);
-        evsieve.args(&["--map", "key:previous@wiimote", "btn:select@wiitar"]);
-        evsieve.args(&["--map", "key:left@wiimote", "btn:dpad_up@wiitar"]);
-        evsieve.args(&["--map", "key:right@wiimote", "btn:dpad_down@wiitar"]);
-        evsieve.args(&["--map", "key:up@wiimote", "btn:dpad_left@wiitar"]);
-        evsieve.args(&["--map", "key:down@wiimote", "btn:dpad_right@wiitar"]);
-
-        evsieve
-            .arg("--input")
-            .arg(
-                parts
-                    .guitar
-                    .ok_or(anyhow!("missing wiimote guitar"))?
-                    .devnode()
-                    .ok_or(anyhow!("failed to retrieve wiimote guitar devnode"))?,
-            )
-            .args(&["domain=guitar", "grab", "persist=exit"]);
-
-        evsieve.args(&["--map", "btn:south@wiimote", "btn:mode@wiitar"]);
-        evsieve.args(&["--map", "btn:1@guitar", "btn:south@wiitar"]);
-        evsieve.args(&["--map", "btn:2@guitar", "btn:east@wiitar"]);
-        evsieve.args(&["--map", "btn:3@guitar", "btn:north@wiitar"]);
-        evsieve.args(&["--map", "btn:4@guitar", "btn:west@wiitar"]);
-        evsieve.args(&["--map", "btn:5@guitar", "btn:tl@wiitar"]);
-        evsieve.args(&["--map", "btn:start@guitar", "btn:start@wiitar"]);
-        evsieve.args(&["--map", "btn:select@guitar", "btn:select@wiitar"]);
-        evsieve.args(&["--map", "btn:dpad_up@guitar", "btn:dpad_up@wiitar"]);
-        evsieve.args(&["--map", "btn:dpad_down@guitar", "btn:dpad_down@wiitar"]);
-        evsieve.args(&["--map", "abs:hat1x@guitar", "abs:rx:3x@wiitar"]);
-        evsieve.args(&["--map", "abs:x@guitar", "abs:x@wiitar"]);
-        evsieve.args(&["--map", "abs:y@guitar", "abs:y@wiitar"]);
-
-        evsieve
-            .arg("--input")
-            .arg(
-                parts
-                    .accel
-                    .ok_or(anyhow!("missing wiimote accelerometer"))?
-                    .devnode()
-                    .ok_or(anyhow!("failed to retrieve wiimote accelerometer devnode"))?,
-            )
-            .args(&["domain=accel", "grab", "persist=exit"]);
-
-        evsieve.args(&["--block", "abs:rz@accel", "abs:rx@accel"]);
-        evsieve.args(&["--map", "abs:ry:-59~..~-60@accel", "btn:select:1@wiitar"]);
-        evsieve.args(&["--map", "abs:ry:~-60..-59~@accel", "btn:select:0@wiitar"]);
-
-        // TODO: device-id et. al.
-        evsieve.args(&["--output", "name=Wiitar", "@wiitar"]);
-
-        evsieve
+            return plan::script(&pipeline, args.evsieve_path, &args.evsieve_args, &path);
+        }
+
+        // Games could pick another roadii's virtual devices over ours, and
+        // it could have the guitar grabbed as well
+        outputs::check(&pipeline, settings.existing_outputs.unwrap_or_default())?;
+
+        // Something else grabbing the devices first would leave us, or
+        // evsieve, unable to, or with nothing to mirror, so say what it is
+        // before getting any further
+        grab::check(&pipeline)?;
+
+        // Better to find out evsieve isn't there before saying we're connected
+        let evsieve = match backend {
+            Backend::Evsieve => Some(evsieve::command(
+                args.evsieve_path,
+                &pipeline,
+                &args.evsieve_args,
+            )?),
+            Backend::Native => None,
+        };
+
+        let notifier = Notifier::new(&settings);
+
+        // The sandbox keeps us from reaching the desktop, so there's no
+        // point trying once we're in it
+        let later = match sandbox {
+            true => Notifier::silent(),
+            false => notifier,
+        };
+
+        if let Some(battery) = &parts.battery {
+            let low = settings.low_battery.unwrap_or(battery::DEFAULT_LOW);
+            let level = battery::level(battery)?;
+
+            match level < low {
+                true => {
+                    warn!("The Wiimote's battery is down to {}%", level);
+                    notifier.send(&format!("Guitar battery low, down to {}%", level));
+                }
+                false => info!("The Wiimote's battery is at {}%", level),
+            }
+
+            // Only we stick around to keep watching it
+            if backend == Backend::Native {
+                battery::watch(battery.clone(), low, later);
+            }
+        }
+
+        if let Some(player) = settings.player {
+            parts.set_player(player)?;
+        }
+
+        let name = settings
+            .output_name
+            .as_deref()
+            .unwrap_or(settings.identity.unwrap_or_default().name());
+        notifier.send(&match settings.player {
+            Some(player) => format!("{} connected as Player {}", name, player),
+            None => format!("{} connected", name),
+        });
+
+        match evsieve {
+            Some(evsieve) => evsieve,
+            None => {
+                #[cfg(feature = "websocket")]
+                let mut overlay = settings
+                    .websocket
+                    .map(overlay::Overlay::start)
+                    .transpose()?;
+                #[cfg(not(feature = "websocket"))]
+                if settings.websocket.is_some() {
+                    bail!("roadii was built without the WebSocket stream");
+                }
+
+                let osc = settings.osc.map(osc::Osc::start).transpose()?;
+
+                // The threads reading from the Wiimote are started by the
+                // event loop, and take after it
+                priority::raise(settings.realtime.unwrap_or_default(), settings.nice)?;
+
+                // Before the event loop starts its threads, so their stacks
+                // are locked too
+                if settings.mlock.unwrap_or_default() {
+                    memory::lock();
+                }
+
+                let mut stats = Stats::new(&settings);
+                let stopped = native::run(
+                    &pipeline,
+                    later,
+                    idle,
+                    stall,
+                    user.as_ref(),
+                    sandbox,
+                    |domain, event| {
+                        stats.record(domain, event);
+
+                        if let Some(osc) = &osc {
+                            osc.record(domain, event);
+                        }
+
+                        #[cfg(feature = "websocket")]
+                        if let Some(overlay) = &mut overlay {
+                            overlay.record(domain, event);
+                        }
+                    },
+                )?;
+                stats.print();
+
+                // Disconnecting stops a forgotten Wiimote draining its battery,
+                // or lets a stalled one start afresh, and we'll be started
+                // again when it reconnects
+                if stopped == Stopped::Idle || stopped == Stopped::Stalled {
+                    if let Err(error) = parts.disconnect() {
+                        warn!("Couldn't disconnect the Wiimote: {:#}", error);
+                    }
+                }
+
+                return Ok(());
+            }
+        }
     };
 
     let error = evsieve.exec();
 
-    Err(error.into())
+    Err(Failure::Evsieve.error(format!("couldn't run evsieve: {}", error)))
 }
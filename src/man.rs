@@ -0,0 +1,35 @@
+use anyhow::{Context, Result};
+use clap_mangen::Man;
+use std::fs;
+use std::path::Path;
+
+/// Writes a man page for `command` and each of its subcommands to
+/// `directory`, like `roadii.1` and `roadii-dump.1`, straight from the
+/// arguments roadii takes so they can't drift apart
+pub fn run(mut command: clap::Command, directory: &Path) -> Result<()> {
+    fs::create_dir_all(directory)
+        .with_context(|| format!("couldn't create {}", directory.display()))?;
+
+    command = command.disable_help_subcommand(true);
+    command.build();
+
+    write(command, directory)
+}
+
+fn write(command: clap::Command, directory: &Path) -> Result<()> {
+    for subcommand in command
+        .get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set())
+    {
+        write(subcommand.clone(), directory)?;
+    }
+
+    let man = Man::new(command);
+    let path = directory.join(man.get_filename());
+    man.generate_to(directory)
+        .with_context(|| format!("couldn't write {}", path.display()))?;
+
+    println!("Wrote {}", path.display());
+
+    Ok(())
+}
@@ -0,0 +1,1129 @@
+use anyhow::{anyhow, bail, Result};
+use clap::ValueEnum;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::Duration;
+use udev::Device;
+
+use crate::calibration::{Area, Span};
+use crate::config::Settings;
+use crate::failure::Failure;
+use crate::key::{EventType, Key, Range, Value};
+use crate::pipeline::{Choice, Pipeline, SourceRanges, Step};
+use crate::profile::{
+    Chord, Control, Curve, KeyBinding, MacroStep, Pair, PointerMode, Profile, TiltMode, TiltSource,
+    WhammySource, WiimoteOrientation,
+};
+use crate::wiitar::Wiitar;
+
+/// How long the switch chord has to be held to change profiles
+const SWITCH_HOLD: Duration = Duration::from_secs(2);
+
+/// How long the exit chord has to be held to stop remapping
+const EXIT_HOLD: Duration = Duration::from_secs(3);
+
+/// How long a macro holds down keys it taps, long enough for games which only
+/// look at buttons once a frame to notice
+const MACRO_TAP: Duration = Duration::from_millis(50);
+
+/// How often the stick moves the pointer while it's held still, with
+/// `--stick-mouse`
+const STICK_MOUSE_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long the pause chord has to be held to pause or resume remapping
+const PAUSE_HOLD: Duration = Duration::from_secs(2);
+
+/// Builds the remapping pipeline for `parts`, configured by `settings`
+pub fn pipeline(parts: &Wiitar, settings: &Settings) -> Result<Pipeline> {
+    build(Some(parts), &SourceRanges::new(), settings)
+}
+
+/// Builds the remapping pipeline configured by `settings` without a Wiitar
+/// to read from, for replaying recordings. Its inputs have no paths, only
+/// their domains, so the ranges of their axes come from `ranges` instead.
+pub fn replay_pipeline(settings: &Settings, ranges: &SourceRanges) -> Result<Pipeline> {
+    build(None, ranges, settings)
+}
+
+fn build(parts: Option<&Wiitar>, ranges: &SourceRanges, settings: &Settings) -> Result<Pipeline> {
+    let mut pipeline = match &settings.switch_chord {
+        Some(chord) => switch(parts, ranges, settings, chord)?,
+        None => profile_pipeline(
+            parts,
+            ranges,
+            settings,
+            settings.profile.unwrap_or_default(),
+        )?,
+    };
+
+    // These go before anything else gets its hands on the events
+    let mut first = Vec::new();
+
+    if let Some(chord) = &settings.exit_chord {
+        first.push(Step::Exit {
+            keys: chord_keys(chord)?,
+            hold: EXIT_HOLD,
+        });
+    }
+
+    if let Some(chord) = &settings.pause_chord {
+        first.push(Step::Pause {
+            keys: chord_keys(chord)?,
+            hold: PAUSE_HOLD,
+        });
+    }
+
+    // Before the profiles, so they see every event as it comes from the
+    // Wiimote, whichever profile is active
+    if let Some(path) = &settings.script {
+        first.push(Step::Script { path: path.clone() });
+    }
+
+    for path in &settings.plugins {
+        first.push(Step::Plugin { path: path.clone() });
+    }
+
+    let inputs = pipeline
+        .steps
+        .iter()
+        .take_while(|step| matches!(step, Step::Input { .. }))
+        .count();
+    pipeline.steps.splice(inputs..inputs, first);
+
+    // Games rumble the gamepad, and it's the Wiimote which can do it
+    if settings.rumble.unwrap_or_default() {
+        pipeline.rumble("wiitar", "wiimote");
+    }
+
+    pipeline.mirror = settings.no_grab.unwrap_or_default();
+
+    Ok(pipeline)
+}
+
+/// Builds a pipeline which switches between several profiles when `chord`
+/// is held down
+fn switch(
+    parts: Option<&Wiitar>,
+    ranges: &SourceRanges,
+    settings: &Settings,
+    chord: &Chord,
+) -> Result<Pipeline> {
+    let mut profiles = match settings.switch_profiles.is_empty() {
+        true => vec![Profile::Gamepad, Profile::Keyboard],
+        false => settings.switch_profiles.clone(),
+    };
+
+    if profiles.len() < 2 {
+        bail!("there needs to be more than one profile to switch between");
+    }
+
+    // Start with the chosen profile, if it's one of them
+    if let Some(start) = settings
+        .profile
+        .and_then(|profile| profiles.iter().position(|&other| other == profile))
+    {
+        profiles.rotate_left(start);
+    }
+
+    // Every profile gets its own copy of the steps, reading from domains of
+    // its own, and the switch decides which of them the inputs are sent to
+    let mut inputs = Vec::new();
+    let mut steps = Vec::new();
+    let mut outputs = Vec::new();
+    let mut choices = Vec::new();
+    let mut shared = HashSet::new();
+
+    for profile in profiles {
+        let name = profile
+            .to_possible_value()
+            .map(|value| value.get_name().to_owned())
+            .unwrap_or_default();
+
+        let mut pipeline = profile_pipeline(parts, ranges, settings, profile)?;
+
+        let sources: Vec<String> = pipeline
+            .steps
+            .iter()
+            .filter_map(|step| match step {
+                Step::Input { domain, .. } => Some(domain.clone()),
+                _ => None,
+            })
+            .collect();
+        let targets: Vec<String> = pipeline
+            .steps
+            .iter()
+            .filter_map(|step| match step {
+                Step::Output { keys, .. } => keys[0].domain.clone(),
+                _ => None,
+            })
+            .collect();
+
+        pipeline.rename_domains(|domain| {
+            (!targets.iter().any(|target| target == domain)).then(|| format!("{}.{}", domain, name))
+        });
+
+        choices.push(Choice {
+            routes: sources
+                .iter()
+                .map(|source| (source.clone(), format!("{}.{}", source, name)))
+                .collect(),
+            name,
+        });
+
+        for step in pipeline.steps {
+            let (list, domain) = match &step {
+                Step::Input { domain, .. } => (&mut inputs, Some(domain.clone())),
+                Step::Output { keys, .. } => (&mut outputs, keys[0].domain.clone()),
+                _ => {
+                    steps.push(step);
+                    continue;
+                }
+            };
+
+            // Profiles share their inputs and outputs
+            if shared.insert(domain) {
+                list.push(step);
+            }
+        }
+    }
+
+    let mut pipeline = Pipeline::default();
+    pipeline.steps.extend(inputs);
+    pipeline.steps.push(Step::Switch {
+        keys: chord_keys(chord)?,
+        hold: SWITCH_HOLD,
+        choices,
+    });
+    pipeline.steps.extend(steps);
+    pipeline.steps.extend(outputs);
+
+    Ok(pipeline)
+}
+
+/// `key` with exactly `value`, moved to `domain` if one is given
+fn with_value(key: &Key, value: i32, domain: Option<&str>) -> Key {
+    Key {
+        value: Some(Value::Range {
+            previous: None,
+            current: Range::exactly(value),
+        }),
+        domain: domain.map(str::to_owned).or(key.domain.clone()),
+        ..key.clone()
+    }
+}
+
+/// The domain of the output most things go to in `profile`
+fn main_output(profile: Profile) -> &'static str {
+    if profile.has_gamepad() {
+        "wiitar"
+    } else if profile.has_keyboard() {
+        "keyboard"
+    } else {
+        "pointer"
+    }
+}
+
+/// The keys for the buttons which make up `chord`
+fn chord_keys(chord: &Chord) -> Result<Vec<Key>> {
+    chord
+        .buttons()
+        .iter()
+        .map(|button| button.parse())
+        .collect()
+}
+
+/// Builds the remapping pipeline for `parts` using one particular profile
+fn profile_pipeline(
+    parts: Option<&Wiitar>,
+    ranges: &SourceRanges,
+    settings: &Settings,
+    profile: Profile,
+) -> Result<Pipeline> {
+    let mut pipeline = Pipeline {
+        source_ranges: ranges.clone(),
+        ..Pipeline::default()
+    };
+
+    pipeline.input(
+        part(parts, |parts| parts.wiimote.as_ref(), "wiimote")?,
+        "wiimote",
+    );
+    pipeline.input(
+        part(parts, |parts| parts.guitar.as_ref(), "wiimote guitar")?,
+        "guitar",
+    );
+    pipeline.input(
+        part(parts, |parts| parts.accel.as_ref(), "wiimote accelerometer")?,
+        "accel",
+    );
+
+    for merge in &settings.merges {
+        pipeline.input(merge.path.clone(), &merge.domain);
+    }
+
+    let gyro = settings.gyro.unwrap_or_default();
+    if gyro {
+        pipeline.input(
+            part(
+                parts,
+                |parts| parts.motion_plus.as_ref(),
+                "wiimote Motion Plus",
+            )?,
+            "gyro",
+        );
+    }
+
+    // Only open the IR camera if we need it, since it's a drain on the battery
+    let pointer = match profile {
+        Profile::Pointer => Some(settings.pointer.unwrap_or(PointerMode::Relative)),
+        _ => settings.pointer,
+    };
+    let lightgun = settings.lightgun.unwrap_or_default();
+
+    if pointer.is_some() || lightgun {
+        pipeline.input(
+            part(parts, |parts| parts.ir.as_ref(), "wiimote IR camera")?,
+            "ir",
+        );
+        self::pointer(&mut pipeline, settings, pointer, lightgun)?;
+    }
+
+    let stick_mouse = settings.stick_mouse.unwrap_or_default();
+    if stick_mouse {
+        self::stick_mouse(&mut pipeline, settings)?;
+    }
+
+    for control in &settings.toggles {
+        // The whammy is the only control which isn't a button
+        let button = control.button().ok_or(anyhow!(
+            "the whammy can't be toggled, since it isn't a button"
+        ))?;
+
+        pipeline.toggle(button)?;
+    }
+
+    let long_press = Duration::from_millis(settings.long_press_time.unwrap_or(500));
+
+    for binding in &settings.long_presses {
+        let target = Key {
+            domain: Some(main_output(profile).to_owned()),
+            ..binding.target.clone()
+        };
+
+        if let Some(button) = binding.control.button() {
+            pipeline.long_press(button, &target.to_string(), long_press)?;
+        }
+    }
+
+    if let Some(interval) = settings.strum_debounce.filter(|&interval| interval > 0) {
+        let interval = Duration::from_millis(interval);
+        pipeline.debounce("btn:dpad_up@guitar", interval)?;
+        pipeline.debounce("btn:dpad_down@guitar", interval)?;
+    }
+
+    if let Some(rate) = settings.auto_strum {
+        if rate <= 0.0 || !rate.is_finite() {
+            bail!("auto-strum should be more than 0 strums a second");
+        }
+
+        let interval = Duration::from_secs_f64(1.0 / rate);
+        pipeline.repeat("btn:dpad_up@guitar", interval)?;
+        pipeline.repeat("btn:dpad_down@guitar", interval)?;
+    }
+
+    // Through another domain, so the second map doesn't undo the first.
+    // Flipping the guitar over turns the strum bar upside down.
+    if settings.swap_strum.unwrap_or_default() != settings.lefty.unwrap_or_default() {
+        pipeline.map("btn:dpad_up@guitar", "btn:dpad_down@swapped")?;
+        pipeline.map("btn:dpad_down@guitar", "btn:dpad_up@swapped")?;
+        pipeline.map("@swapped", "@guitar")?;
+    }
+
+    if settings.reverse_frets.unwrap_or_default() {
+        for (fret, reversed) in [(1, 5), (2, 4), (4, 2), (5, 1)] {
+            pipeline.map(
+                &format!("btn:{}@guitar", fret),
+                &format!("btn:{}@reversed", reversed),
+            )?;
+        }
+        pipeline.map("@reversed", "@guitar")?;
+    }
+
+    if settings.motion.unwrap_or_default() {
+        pipeline.copy("@accel", "@motion")?;
+    }
+
+    if gyro {
+        pipeline.copy("@gyro", "@rotation")?;
+    }
+
+    // Star power is Select, wherever it ends up
+    if let Some(chord) = &settings.star_power_chord {
+        let target = match profile {
+            Profile::Gamepad | Profile::GamepadKeyboard => Some("btn:select@wiitar".to_owned()),
+            Profile::Keyboard => Some(format!(
+                "key:{}@keyboard",
+                KeyBinding::key_for(&settings.keys, Control::Select)
+            )),
+            Profile::Pointer => bail!("there's no star power in the pointer profile"),
+            // Menus have no use for it, but can be switched to alongside ones
+            // which do
+            Profile::Menu => None,
+        };
+
+        if chord.0.len() < 2 {
+            bail!("the star power chord should be at least two controls, like `select+green`");
+        }
+
+        if let Some(target) = target {
+            pipeline.chord(&chord.buttons(), &target)?;
+        }
+    }
+
+    for binding in &settings.axis_buttons {
+        let target = Key {
+            domain: binding
+                .target
+                .domain
+                .clone()
+                .or(Some(main_output(profile).to_owned())),
+            ..binding.target.clone()
+        };
+
+        threshold(
+            &mut pipeline,
+            &binding.source.to_string(),
+            &target.to_string(),
+            binding.press,
+            binding.release,
+            true,
+        )?;
+    }
+
+    for binding in &settings.button_axes {
+        let output = binding
+            .target
+            .domain
+            .as_deref()
+            .unwrap_or(main_output(profile));
+
+        for (value, axis) in [(1, binding.pressed), (0, binding.released)] {
+            pipeline.copy(
+                &with_value(&binding.source, value, None).to_string(),
+                &with_value(&binding.target, axis, Some(output)).to_string(),
+            )?;
+        }
+    }
+
+    for binding in &settings.chords {
+        let target = Key {
+            domain: Some(main_output(profile).to_owned()),
+            ..binding.target.clone()
+        };
+
+        pipeline.chord(&binding.chord.buttons(), &target.to_string())?;
+    }
+
+    for binding in &settings.macros {
+        let output = main_output(profile);
+        let mut delay = Duration::ZERO;
+        let mut events = Vec::new();
+
+        for step in &binding.steps {
+            match step {
+                MacroStep::Tap(key) => {
+                    events.push((delay, with_value(key, 1, Some(output))));
+                    delay += MACRO_TAP;
+                    events.push((delay, with_value(key, 0, Some(output))));
+                }
+                MacroStep::Set(key) => events.push((
+                    delay,
+                    Key {
+                        domain: Some(output.to_owned()),
+                        ..key.clone()
+                    },
+                )),
+                MacroStep::Wait(wait) => delay += *wait,
+            }
+        }
+
+        pipeline.steps.push(Step::Macro {
+            keys: chord_keys(&binding.chord)?,
+            events,
+        });
+    }
+
+    for map in &settings.maps {
+        let target = Key {
+            domain: map
+                .target
+                .domain
+                .clone()
+                .or(Some(main_output(profile).to_owned())),
+            ..map.target.clone()
+        };
+
+        pipeline.map(&map.source.to_string(), &target.to_string())?;
+    }
+
+    // B is the trigger and A the other button, as well as their usual jobs
+    if lightgun {
+        pipeline.copy("btn:east@wiimote", "btn:left@lightgun")?;
+        pipeline.copy("btn:south@wiimote", "btn:right@lightgun")?;
+    }
+
+    if profile == Profile::Pointer {
+        pipeline.map("btn:south@wiimote", "btn:left@pointer")?;
+        pipeline.map("btn:east@wiimote", "btn:right@pointer")?;
+    } else if pointer.is_some() {
+        // B is the trigger, so it's left click while still doing its usual job
+        pipeline.copy("btn:east@wiimote", "btn:left@pointer")?;
+    }
+
+    if profile == Profile::Menu {
+        menu(&mut pipeline, settings)?;
+    } else if profile.has_gamepad() {
+        if profile.has_keyboard() {
+            // The frets are copied before the gamepad gets its hands on them
+            for control in Control::FRETS {
+                if let Some(button) = control.button() {
+                    let key = KeyBinding::key_for(&settings.keys, control);
+                    pipeline.copy(button, &format!("key:{}@keyboard", key))?;
+                }
+            }
+        }
+
+        gamepad(&mut pipeline, settings)?;
+    } else if profile.has_keyboard() {
+        keyboard(&mut pipeline, settings)?;
+    }
+
+    // Anything nothing else has taken goes to the main output as it is
+    if settings.passthrough_unmapped.unwrap_or_default() {
+        let target = format!("@{}", main_output(profile));
+
+        let inputs: Vec<String> = pipeline
+            .steps
+            .iter()
+            .filter_map(|step| match step {
+                Step::Input { domain, .. } => Some(format!("@{}", domain)),
+                _ => None,
+            })
+            .collect();
+
+        for input in inputs {
+            pipeline.map(&input, &target)?;
+        }
+    }
+
+    let identity = settings.identity.unwrap_or_default();
+    let name = settings.output_name.as_deref().unwrap_or(identity.name());
+
+    if profile.has_gamepad() {
+        pipeline.output("wiitar", name, identity.device_id());
+    }
+
+    if profile.has_keyboard() {
+        pipeline.output("keyboard", &format!("{} Keyboard", name), None);
+    }
+
+    if pointer.is_some() || stick_mouse {
+        pipeline.output("pointer", &format!("{} Pointer", name), None);
+    }
+
+    if lightgun {
+        pipeline.output("lightgun", &format!("{} Lightgun", name), None);
+    }
+
+    if settings.motion.unwrap_or_default() {
+        pipeline.output("motion", &format!("{} Motion", name), None);
+    }
+
+    if gyro {
+        pipeline.output("rotation", &format!("{} Gyro", name), None);
+    }
+
+    for transform in &settings.axis_transforms {
+        pipeline.transform_axis(transform)?;
+    }
+
+    for range in &settings.axis_ranges {
+        pipeline.set_axis_range(range)?;
+    }
+
+    Ok(pipeline)
+}
+
+/// Maps everything to a gamepad, laid out like a PlayStation 3 guitar
+pub fn gamepad(pipeline: &mut Pipeline, settings: &Settings) -> Result<()> {
+    // Steam wants Home to be the Guide button, so A takes Home's place
+    let (a, home) = match settings.steam.unwrap_or_default() {
+        true => ("btn:z@wiitar", "btn:mode@wiitar"),
+        false => ("btn:mode@wiitar", "btn:z@wiitar"),
+    };
+    pipeline.map("btn:south@wiimote", a)?;
+    pipeline.map("btn:1@wiimote", "btn:thumbl@wiitar")?;
+    pipeline.map("btn:2@wiimote", "btn:thumbr@wiitar")?;
+    pipeline.map("btn:mode@wiimote", home)?;
+    pipeline.map("key:next@wiimote", "btn:start@wiitar")?;
+    pipeline.map("key:previous@wiimote", "btn:select@wiitar")?;
+    for (key, direction) in dpad(settings) {
+        pipeline.map(key, &format!("btn:dpad_{}@wiitar", direction))?;
+    }
+
+    pipeline.map("btn:south@wiimote", a)?;
+    pipeline.map("btn:1@guitar", "btn:south@wiitar")?;
+    pipeline.map("btn:2@guitar", "btn:east@wiitar")?;
+    pipeline.map("btn:3@guitar", "btn:north@wiitar")?;
+    pipeline.map("btn:4@guitar", "btn:west@wiitar")?;
+    pipeline.map("btn:5@guitar", "btn:tl@wiitar")?;
+    pipeline.map("btn:start@guitar", "btn:start@wiitar")?;
+    pipeline.map("btn:select@guitar", "btn:select@wiitar")?;
+    pipeline.map("btn:dpad_up@guitar", "btn:dpad_up@wiitar")?;
+    pipeline.map("btn:dpad_down@guitar", "btn:dpad_down@wiitar")?;
+    whammy(pipeline, settings)?;
+    stick(pipeline, settings, "guitar", "wiitar")?;
+
+    match settings.tilt_mode.unwrap_or_default() {
+        TiltMode::Button => tilt(pipeline, settings, "btn:select@wiitar")?,
+        TiltMode::Axis => {
+            let (source, rises) = tilt_source(pipeline, settings)?;
+
+            // Flipped around if need be, so raising the neck makes it go up
+            match rises {
+                true => pipeline.map(source.axis(), "abs:ry@wiitar")?,
+                false => pipeline.map(source.axis(), "abs:ry:-x@wiitar")?,
+            };
+        }
+        TiltMode::Off => {}
+    }
+
+    pipeline.block(&["abs:rz@accel", "abs:rx@accel"])?;
+
+    Ok(())
+}
+
+/// Pairs each of the Wiimote's d-pad keys with the direction it points,
+/// the other way around if it's plugged into a guitar played left-handed
+fn dpad(settings: &Settings) -> [(&'static str, &'static str); 4] {
+    let orientation = settings.wiimote_orientation.unwrap_or_default();
+    let mut dpad = orientation.dpad();
+
+    if orientation == WiimoteOrientation::Guitar && settings.lefty.unwrap_or_default() {
+        for (_, direction) in &mut dpad {
+            *direction = match *direction {
+                "up" => "down",
+                "down" => "up",
+                "left" => "right",
+                "right" => "left",
+                other => other,
+            };
+        }
+    }
+
+    dpad
+}
+
+/// Maps everything to keys on a keyboard, as bound by `keys`
+fn keyboard(pipeline: &mut Pipeline, settings: &Settings) -> Result<()> {
+    let key = |control| KeyBinding::key_for(&settings.keys, control);
+
+    for (key, direction) in dpad(settings) {
+        pipeline.map(key, &format!("key:{}@keyboard", direction))?;
+    }
+
+    let start = format!("key:{}@keyboard", key(Control::Start));
+    let select = key(Control::Select);
+
+    pipeline.map("key:next@wiimote", &start)?;
+    pipeline.map("key:previous@wiimote", &format!("key:{}@keyboard", select))?;
+
+    for control in Control::ALL {
+        if let Some(button) = control.button() {
+            pipeline.map(button, &format!("key:{}@keyboard", key(control)))?;
+        }
+    }
+
+    // Pressing the whammy more than halfway presses its key
+    let whammy = key(Control::Whammy);
+    let (axis, span) = whammy_source(pipeline, settings)?;
+    let (code, domain) = axis.split_once('@').unwrap_or_default();
+    let middle = span.value(0.5);
+
+    let (press, release) = match span.falls() {
+        false => (
+            format!("~{}..{}~", middle - 1, middle),
+            format!("{}~..~{}", middle, middle - 1),
+        ),
+        true => (
+            format!("{}~..~{}", middle + 1, middle),
+            format!("~{}..{}~", middle, middle + 1),
+        ),
+    };
+
+    pipeline.map(
+        &format!("{}:{}@{}", code, press, domain),
+        &format!("key:{}:1@keyboard", whammy),
+    )?;
+    pipeline.map(
+        &format!("{}:{}@{}", code, release, domain),
+        &format!("key:{}:0@keyboard", whammy),
+    )?;
+
+    // Tilting the guitar is star power, which is Select, same as the gamepad
+    if settings.tilt_mode != Some(TiltMode::Off) {
+        tilt(pipeline, settings, &format!("key:{}@keyboard", select))?;
+    }
+
+    Ok(())
+}
+
+/// Maps the controls to the keys for getting around menus
+fn menu(pipeline: &mut Pipeline, settings: &Settings) -> Result<()> {
+    for (key, direction) in dpad(settings) {
+        pipeline.map(key, &format!("key:{}@keyboard", direction))?;
+    }
+
+    pipeline.map("btn:dpad_up@guitar", "key:up@keyboard")?;
+    pipeline.map("btn:dpad_down@guitar", "key:down@keyboard")?;
+    pipeline.map("btn:3@guitar", "key:left@keyboard")?;
+    pipeline.map("btn:4@guitar", "key:right@keyboard")?;
+    pipeline.map("btn:5@guitar", "key:tab@keyboard")?;
+
+    // Anything which goes forward is Enter, and anything which goes back is
+    // Escape
+    pipeline.map("btn:1@guitar", "key:enter@keyboard")?;
+    pipeline.map("btn:start@guitar", "key:enter@keyboard")?;
+    pipeline.map("btn:south@wiimote", "key:enter@keyboard")?;
+    pipeline.map("key:next@wiimote", "key:enter@keyboard")?;
+    pipeline.map("btn:2@guitar", "key:esc@keyboard")?;
+    pipeline.map("btn:select@guitar", "key:esc@keyboard")?;
+    pipeline.map("btn:east@wiimote", "key:esc@keyboard")?;
+    pipeline.map("key:previous@wiimote", "key:esc@keyboard")?;
+
+    Ok(())
+}
+
+/// Maps the whammy to the gamepad, stretched to fill its range if it has
+/// been calibrated, and shaped by its deadzone and curve
+fn whammy(pipeline: &mut Pipeline, settings: &Settings) -> Result<()> {
+    let (axis, span) = whammy_source(pipeline, settings)?;
+    let deadzone = settings.whammy_deadzone.unwrap_or_default();
+    let curve = settings.whammy_curve.unwrap_or_default();
+
+    let positions = settings.whammy_positions;
+
+    // The whammy bar goes from 0 to 15, but games like a bit more travel
+    if settings.whammy_source.unwrap_or_default() == WhammySource::Bar
+        && settings.whammy_range.is_none()
+        && deadzone == 0.0
+        && curve == Curve::Linear
+        && positions.is_none()
+    {
+        pipeline.map(&axis, "abs:rx:3x@wiitar")?;
+        return Ok(());
+    }
+
+    if !(0.0..1.0).contains(&deadzone) {
+        bail!("the whammy deadzone must be at least 0 and less than 1");
+    }
+
+    if positions.is_some_and(|positions| positions < 2) {
+        bail!("the whammy needs at least 2 positions");
+    }
+    let (code, domain) = axis.split_once('@').unwrap_or_default();
+
+    // Anything past either end is stuck at that end
+    let (beyond_rest, beyond_full) = match span.falls() {
+        false => (format!("~{}", span.rest), format!("{}~", span.full)),
+        true => (format!("{}~", span.rest), format!("~{}", span.full)),
+    };
+
+    pipeline.map(
+        &format!("{}:{}@{}", code, beyond_rest, domain),
+        "abs:rx:0@wiitar",
+    )?;
+    pipeline.map(
+        &format!("{}:{}@{}", code, beyond_full, domain),
+        "abs:rx:45@wiitar",
+    )?;
+
+    // If there are only a handful of positions, like on the whammy bar, it's
+    // easiest to just spell out what each of them turns into
+    let (low, high) = (span.rest.min(span.full), span.rest.max(span.full));
+
+    if high - low <= 64 {
+        for value in low + 1..high {
+            let travel = ((span.travel(value) - deadzone) / (1.0 - deadzone)).max(0.0);
+            let mut travel = curve.apply(travel);
+
+            // Snapped to the nearest of the positions, if there are any
+            if let Some(positions) = positions {
+                let steps = (positions - 1) as f64;
+                travel = (travel * steps).round() / steps;
+            }
+
+            pipeline.map(
+                &format!("{}:{}@{}", code, value, domain),
+                &format!("abs:rx:{}@wiitar", (travel * 45.0).round()),
+            )?;
+        }
+
+        return Ok(());
+    }
+
+    if curve != Curve::Linear || positions.is_some() {
+        bail!("the whammy can only have a curve or positions if it travels no more than 64 steps");
+    }
+
+    // Otherwise it's a straight line from the end of the deadzone to full
+    let start = span.value(deadzone);
+    let factor = 45.0 / (span.full - start) as f64;
+
+    if start != span.rest {
+        let (low, high) = (span.rest.min(start), span.rest.max(start));
+        pipeline.map(
+            &format!("{}:{}~{}@{}", code, low, high, domain),
+            "abs:rx:0@wiitar",
+        )?;
+    }
+
+    pipeline.map(
+        &axis,
+        &format!("abs:rx:{}x{:+}@wiitar", factor, -start as f64 * factor),
+    )?;
+
+    Ok(())
+}
+
+/// Sets up wherever the whammy comes from, and returns the axis to read it
+/// from along with its span
+fn whammy_source(pipeline: &mut Pipeline, settings: &Settings) -> Result<(String, Span)> {
+    let source = settings.whammy_source.unwrap_or_default();
+
+    let (axis, span) = match source {
+        WhammySource::Bar => (
+            source.axis().to_owned(),
+            settings.whammy_range.unwrap_or(Span { rest: 0, full: 15 }),
+        ),
+        // The accelerometer is needed for tilt too, so the whammy gets its
+        // own copy to map however it likes
+        _ => {
+            let span = settings.whammy_range.ok_or_else(|| {
+                anyhow!("the whammy needs a range to come from the accelerometer, run `roadii calibrate whammy` or use --whammy-range")
+            })?;
+
+            let axis = source.axis().replace("@accel", "@whammy");
+            pipeline.copy(source.axis(), &axis)?;
+
+            (axis, span)
+        }
+    };
+
+    let smoothing = settings.whammy_smoothing.unwrap_or_default();
+    if !(0.0..1.0).contains(&smoothing) {
+        bail!("the whammy smoothing must be at least 0 and less than 1");
+    }
+
+    if smoothing > 0.0 {
+        pipeline.smooth(&axis, smoothing)?;
+    }
+
+    Ok((axis, span))
+}
+
+/// Maps the guitar's stick to the gamepad, recentred and with its deadzone
+fn stick(pipeline: &mut Pipeline, settings: &Settings, source: &str, target: &str) -> Result<()> {
+    let deadzone = settings.stick_deadzone.unwrap_or(Pair { x: 0.0, y: 0.0 });
+    let center = settings.stick_center.unwrap_or(Pair { x: 0, y: 0 });
+    let lefty = settings.lefty.unwrap_or_default();
+
+    for (axis, deadzone, center) in [("x", deadzone.x, center.x), ("y", deadzone.y, center.y)] {
+        if !(0.0..1.0).contains(&deadzone) {
+            bail!("the stick deadzone must be at least 0 and less than 1");
+        }
+
+        // hid-wiimote gives the stick 6 bits, so there are few enough values
+        // to spell out each of the ones which need to move
+        let (min, max): (i32, i32) = (-32, 31);
+        if !(min < center && center < max) {
+            bail!(
+                "the stick can't rest at {} when it only goes from {} to {}",
+                center,
+                min,
+                max
+            );
+        }
+
+        for value in min..=max {
+            // How far along it is from the centre to that side's end, which
+            // ends up that far along from 0 to the same end on the output
+            let (travel, end) = match value < center {
+                true => ((center - value) as f64 / (center - min) as f64, min),
+                false => ((value - center) as f64 / (max - center) as f64, max),
+            };
+
+            let travel = ((travel - deadzone) / (1.0 - deadzone)).max(0.0);
+            let output = (travel * end.abs() as f64).round() as i32 * end.signum();
+
+            // Flipping the guitar over turns the stick upside down too
+            let output = match lefty {
+                true => (-output).min(max),
+                false => output,
+            };
+
+            if output != value {
+                pipeline.map(
+                    &format!("abs:{}:{}@{}", axis, value, source),
+                    &format!("abs:{}:{}@{}", axis, output, target),
+                )?;
+            }
+        }
+
+        pipeline.map(
+            &format!("abs:{}@{}", axis, source),
+            &format!("abs:{}@{}", axis, target),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Moves the pointer with the stick, for as long as it's pushed away from
+/// where it rests, and clicks with the frets
+fn stick_mouse(pipeline: &mut Pipeline, settings: &Settings) -> Result<()> {
+    let speed = settings.stick_mouse_speed.unwrap_or(800.0);
+    if speed <= 0.0 || !speed.is_finite() {
+        bail!("the stick mouse speed should be more than 0 pixels a second");
+    }
+
+    // Copied so the stick still does its usual job, and given the same
+    // centre and deadzone as the gamepad's stick
+    pipeline.copy("abs:x@guitar", "abs:x@stick")?;
+    pipeline.copy("abs:y@guitar", "abs:y@stick")?;
+    stick(pipeline, settings, "stick", "mouse")?;
+    pipeline.sustain("abs@mouse", STICK_MOUSE_INTERVAL)?;
+
+    // The stick goes 32 either way, which moves it the whole speed
+    let factor = speed * STICK_MOUSE_INTERVAL.as_secs_f64() / 32.0;
+    pipeline.map("abs:x@mouse", &format!("rel:x:{}x@pointer", factor))?;
+    pipeline.map("abs:y@mouse", &format!("rel:y:{}x@pointer", factor))?;
+
+    pipeline.copy("btn:1@guitar", "btn:left@pointer")?;
+    pipeline.copy("btn:2@guitar", "btn:right@pointer")?;
+    pipeline.copy("btn:3@guitar", "btn:middle@pointer")?;
+
+    Ok(())
+}
+
+/// Presses `target` while the guitar's neck is raised, as set by the tilt
+/// threshold and hysteresis
+fn tilt(pipeline: &mut Pipeline, settings: &Settings, target: &str) -> Result<()> {
+    let (source, rises) = tilt_source(pipeline, settings)?;
+    let threshold = tilt_threshold(settings);
+    let hysteresis = settings.tilt_hysteresis.unwrap_or(1);
+    if hysteresis < 1 {
+        bail!("the tilt hysteresis must be at least 1");
+    }
+
+    // It's tilted once the axis reaches the threshold, and only stops being
+    // tilted once it has come back past the threshold by the hysteresis
+    let release = match rises {
+        true => threshold - hysteresis,
+        false => threshold + hysteresis,
+    };
+
+    self::threshold(pipeline, source.axis(), target, threshold, release, false)
+}
+
+/// The value of the tilt source at which the neck counts as raised
+pub fn tilt_threshold(settings: &Settings) -> i32 {
+    let source = settings.tilt_source.unwrap_or_default();
+    let rises = source.rises() != tilt_inverted(settings);
+
+    settings.tilt_threshold.unwrap_or(match rises {
+        true => source.default_threshold().abs(),
+        false => -source.default_threshold().abs(),
+    })
+}
+
+/// Whether raising the neck moves the tilt source the other way to usual,
+/// which flipping the guitar over to play it left-handed does too
+pub fn tilt_inverted(settings: &Settings) -> bool {
+    settings.tilt_invert.unwrap_or_default() != settings.lefty.unwrap_or_default()
+}
+
+/// Presses `target` once the axis `source` reaches `press`, and lets go of it
+/// once it comes back to `release`, which is below `press` if it's pressed as
+/// the axis rises. The axis events which cross the thresholds are kept if
+/// `keep` is set, or replaced by the button otherwise.
+fn threshold(
+    pipeline: &mut Pipeline,
+    source: &str,
+    target: &str,
+    press: i32,
+    release: i32,
+    keep: bool,
+) -> Result<()> {
+    let (press, release) = match release < press {
+        true => (
+            format!("~{}..{}~", press - 1, press),
+            format!("{}~..~{}", release + 1, release),
+        ),
+        false => (
+            format!("{}~..~{}", press + 1, press),
+            format!("~{}..{}~", release - 1, release),
+        ),
+    };
+
+    let (code, domain) = source.split_once('@').unwrap_or_default();
+    let (target, target_domain) = target.split_once('@').unwrap_or_default();
+
+    let rules = [
+        (format!("{}:{}@{}", code, press, domain), 1),
+        (format!("{}:{}@{}", code, release, domain), 0),
+    ];
+
+    for (source, value) in rules {
+        let target = format!("{}:{}@{}", target, value, target_domain);
+
+        match keep {
+            true => pipeline.copy(&source, &target)?,
+            false => pipeline.map(&source, &target)?,
+        };
+    }
+
+    Ok(())
+}
+
+/// Sets up whatever the chosen tilt source needs, and returns it, along
+/// with whether it rises as the neck is raised once inverted if need be
+fn tilt_source(pipeline: &mut Pipeline, settings: &Settings) -> Result<(TiltSource, bool)> {
+    let source = settings.tilt_source.unwrap_or_default();
+
+    if source == TiltSource::Magnitude {
+        pipeline.magnitude(&["abs:rx@accel", "abs:ry@accel"], source.axis())?;
+    }
+
+    // Strumming hard shakes the accelerometer about, which smoothing hides
+    let smoothing = settings.tilt_smoothing.unwrap_or_default();
+    if !(0.0..1.0).contains(&smoothing) {
+        bail!("the tilt smoothing must be at least 0 and less than 1");
+    }
+
+    if smoothing > 0.0 {
+        pipeline.smooth(source.axis(), smoothing)?;
+    }
+
+    Ok((source, source.rises() != tilt_inverted(settings)))
+}
+
+/// Turns the IR camera's view of the sensor bar into pointer movement, and
+/// a position on the screen for the lightgun if there is one
+fn pointer(
+    pipeline: &mut Pipeline,
+    settings: &Settings,
+    mode: Option<PointerMode>,
+    lightgun: bool,
+) -> Result<()> {
+    // The camera reports 1023 for both axes when it can't see anything
+    pipeline.block(&["abs:hat0x:1023@ir", "abs:hat0y:768~@ir"])?;
+
+    let smoothing = settings.pointer_smoothing.unwrap_or_default();
+    if !(0.0..1.0).contains(&smoothing) {
+        bail!("the pointer smoothing must be at least 0 and less than 1");
+    }
+
+    if smoothing > 0.0 {
+        pipeline.smooth("abs:hat0x@ir", smoothing)?;
+        pipeline.smooth("abs:hat0y@ir", smoothing)?;
+    }
+
+    // The sensor bar moves across the camera's view the opposite way to
+    // the Wiimote, so unless the screen has been calibrated, the whole view
+    // is flipped around to cover it
+    let area = settings.pointer_area.unwrap_or(Area {
+        left: 1023,
+        top: 767,
+        right: 0,
+        bottom: 0,
+    });
+
+    let screen = |axis: &str, near: i32, far: i32, size: i32, domain: &str| {
+        let factor = size as f64 / (far - near) as f64;
+
+        Key {
+            event_type: Some(EventType::Abs),
+            code: Some(axis.to_owned()),
+            value: Some(Value::Linear {
+                factor,
+                offset: -near as f64 * factor,
+                delta: false,
+            }),
+            domain: Some(domain.to_owned()),
+        }
+        .to_string()
+    };
+
+    if lightgun {
+        pipeline.copy(
+            "abs:hat0x@ir",
+            &screen("x", area.left, area.right, 1023, "lightgun"),
+        )?;
+        pipeline.copy(
+            "abs:hat0y@ir",
+            &screen("y", area.top, area.bottom, 767, "lightgun"),
+        )?;
+    }
+
+    match mode {
+        Some(PointerMode::Relative) => {
+            let sensitivity = -settings.pointer_sensitivity.unwrap_or(2.0);
+
+            // Don't jump about when the camera loses sight of the sensor bar
+            pipeline.map(
+                "abs:hat0x:~1022..~1022@ir",
+                &format!("rel:x:{}d@pointer", sensitivity),
+            )?;
+            pipeline.map(
+                "abs:hat0y:~767..~767@ir",
+                &format!("rel:y:{}d@pointer", sensitivity),
+            )?;
+        }
+        Some(PointerMode::Absolute) => {
+            pipeline.map(
+                "abs:hat0x@ir",
+                &screen("x", area.left, area.right, 1023, "pointer"),
+            )?;
+            pipeline.map(
+                "abs:hat0y@ir",
+                &screen("y", area.top, area.bottom, 767, "pointer"),
+            )?;
+        }
+        None => {}
+    }
+
+    Ok(())
+}
+
+/// The device node for one of the parts of the Wiitar, if there is one to
+/// read from
+fn part(
+    parts: Option<&Wiitar>,
+    device: impl Fn(&Wiitar) -> Option<&Device>,
+    description: &str,
+) -> Result<PathBuf> {
+    match parts {
+        Some(parts) => devnode(device(parts), description),
+        None => Ok(PathBuf::new()),
+    }
+}
+
+/// The device node of one of the Wiitar's parts
+pub fn devnode(device: Option<&Device>, description: &str) -> Result<PathBuf> {
+    Ok(device
+        .ok_or_else(|| Failure::MissingPart.error(format!("missing {}", description)))?
+        .devnode()
+        .ok_or(anyhow!("failed to retrieve {} devnode", description))?
+        .to_path_buf())
+}
@@ -0,0 +1,17 @@
+use std::io;
+use tracing::{info, warn};
+
+/// Keeps all of roadii's memory, and whatever it uses from here on, in RAM,
+/// so reading what the guitar sends never has to wait for a page to be
+/// brought back in. If we aren't allowed to, it carries on as it was, with a
+/// warning.
+pub fn lock() {
+    // SAFETY: this only changes how the process's memory is kept
+    match unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) } {
+        0 => info!("Locked roadii's memory into RAM"),
+        _ => warn!(
+            "Couldn't lock roadii's memory into RAM, which needs root or an unlimited memlock limit in /etc/security/limits.conf: {}",
+            io::Error::last_os_error()
+        ),
+    }
+}
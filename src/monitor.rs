@@ -0,0 +1,173 @@
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use evdev::EventType;
+use std::path::PathBuf;
+
+use crate::key::Key;
+use crate::mapping;
+use crate::observe;
+use crate::pipeline::{Pipeline, Step};
+use crate::profile::Control;
+use crate::wiitar::Wiitar;
+
+/// How wide each device's column is
+const COLUMN: usize = 28;
+
+/// Shows the events coming from the Wiimote, guitar and accelerometer in
+/// `parts` in a column each, with the guitar's controls called by name. If
+/// given the `pipeline` roadii is running with, its outputs get a column
+/// too, to see what each control ends up as. A running roadii grabs the
+/// Wiimote's devices, so nothing comes from them unless it's paused.
+pub fn run(parts: &Wiitar, pipeline: Option<&Pipeline>) -> Result<()> {
+    let mut columns = vec![
+        (
+            mapping::devnode(parts.wiimote.as_ref(), "wiimote")?,
+            "wiimote".to_owned(),
+        ),
+        (
+            mapping::devnode(parts.guitar.as_ref(), "wiimote guitar")?,
+            "guitar".to_owned(),
+        ),
+        (
+            mapping::devnode(parts.accel.as_ref(), "wiimote accelerometer")?,
+            "accel".to_owned(),
+        ),
+    ];
+
+    // Every output shares a column, since there's usually only the one
+    // doing anything
+    let output = columns.len();
+    if let Some(pipeline) = pipeline {
+        columns.extend(outputs(pipeline)?);
+    }
+
+    let titles = columns
+        .iter()
+        .take(output + 1)
+        .enumerate()
+        .map(|(index, (_, domain))| match index == output {
+            true => "output",
+            false => domain,
+        })
+        .map(|title| format!("{:<COLUMN$}", title))
+        .collect::<String>();
+    println!("{}", titles.trim_end());
+    println!("{}", "-".repeat(titles.trim_end().len()));
+
+    let (paths, domains): (Vec<PathBuf>, Vec<String>) = columns.into_iter().unzip();
+
+    for message in observe::read(paths)? {
+        let (index, event) = match message {
+            Ok(message) => message,
+            Err(error) if error.raw_os_error() == Some(libc::ENODEV) => {
+                println!("A device went away, so we're done here");
+                return Ok(());
+            }
+            Err(error) => return Err(error).context("couldn't read from a device"),
+        };
+
+        if event.event_type() == EventType::SYNCHRONIZATION {
+            continue;
+        }
+
+        let domain = &domains[index];
+        let text = match Key::from_event(&event, domain) {
+            // Outputs keep their domain, to tell the gamepad from the keyboard
+            Some(key) if index >= output => key.to_string(),
+            // The column already says where the rest come from
+            Some(key) => {
+                let label = label(&key);
+                let key = Key {
+                    domain: None,
+                    ..key
+                };
+
+                match label {
+                    Some(label) => format!("{} {}", key, label),
+                    None => key.to_string(),
+                }
+            }
+            None => format!(
+                "type {} code {} value {}",
+                event.event_type().0,
+                event.code(),
+                event.value()
+            ),
+        };
+
+        println!("{}{}", " ".repeat(COLUMN * index.min(output)), text);
+    }
+
+    Ok(())
+}
+
+/// What the Wiimote or guitar control `key` is called, if it's one we know
+fn label(key: &Key) -> Option<String> {
+    let key = Key {
+        value: None,
+        ..key.clone()
+    }
+    .to_string();
+
+    let control = Control::ALL
+        .iter()
+        .chain([&Control::Home])
+        .find(|control| control.button() == Some(key.as_str()));
+    if let Some(control) = control {
+        return control
+            .to_possible_value()
+            .map(|value| value.get_name().to_owned());
+    }
+
+    let label = match key.as_str() {
+        "abs:x@guitar" => "stick x",
+        "abs:y@guitar" => "stick y",
+        "abs:hat0x@guitar" => "touch bar",
+        "abs:hat1x@guitar" => "whammy",
+        "btn:south@wiimote" => "a",
+        "btn:east@wiimote" => "b",
+        "btn:1@wiimote" => "1",
+        "btn:2@wiimote" => "2",
+        "key:next@wiimote" => "plus",
+        "key:previous@wiimote" => "minus",
+        "key:up@wiimote" => "d-pad up",
+        "key:down@wiimote" => "d-pad down",
+        "key:left@wiimote" => "d-pad left",
+        "key:right@wiimote" => "d-pad right",
+        "abs:rx@accel" => "accel x",
+        "abs:ry@accel" => "accel y",
+        "abs:rz@accel" => "accel z",
+        _ => return None,
+    };
+
+    Some(label.to_owned())
+}
+
+/// Finds the virtual devices a running roadii made for `pipeline`'s
+/// outputs, by their names, along with the domain of each
+pub fn outputs(pipeline: &Pipeline) -> Result<Vec<(PathBuf, String)>> {
+    let devices = evdev::enumerate().collect::<Vec<_>>();
+
+    let outputs = pipeline
+        .steps
+        .iter()
+        .filter_map(|step| match step {
+            Step::Output { keys, name, .. } => Some((keys, name)),
+            _ => None,
+        })
+        .filter_map(|(keys, name)| {
+            let domain = keys.first()?.domain.clone()?;
+            let (path, _) = devices
+                .iter()
+                .find(|(_, device)| device.name() == Some(name.as_str()))?;
+
+            Some((path.clone(), domain))
+        })
+        .collect::<Vec<_>>();
+
+    if outputs.is_empty() {
+        bail!("couldn't find any of roadii's virtual devices, is it running for this Wiimote?");
+    }
+
+    Ok(outputs)
+}
@@ -0,0 +1,90 @@
+use std::os::fd::RawFd;
+use std::panic;
+use std::process;
+use std::sync::{Mutex, Once, TryLockError};
+
+use super::EVIOCGRAB;
+
+/// The ioctl which destroys a virtual device created through uinput
+const UI_DEV_DESTROY: libc::c_ulong = 0x5502;
+
+/// The input devices which are grabbed and the virtual devices which have
+/// been created, for the panic hook to let go of and destroy
+static HELD: Mutex<Held> = Mutex::new(Held {
+    inputs: Vec::new(),
+    outputs: Vec::new(),
+});
+
+#[derive(Default)]
+struct Held {
+    inputs: Vec<RawFd>,
+    outputs: Vec<RawFd>,
+}
+
+/// Keeps track of what's held while remapping, so that if anything panics,
+/// on whichever thread, the input devices are let go of and the virtual
+/// devices destroyed before roadii exits, rather than leaving the guitar
+/// grabbed by a thread that's gone. Dropping it forgets them again.
+pub struct Guard;
+
+impl Guard {
+    /// Starts keeping track of the grabbed input devices at `inputs` and the
+    /// virtual devices at `outputs`
+    pub fn new(inputs: &[RawFd], outputs: &[RawFd]) -> Self {
+        static HOOK: Once = Once::new();
+        HOOK.call_once(|| {
+            let previous = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                previous(info);
+                release();
+
+                // Anything else would carry on without whichever thread
+                // panicked, and likely without the guitar
+                process::exit(101);
+            }));
+        });
+
+        let mut held = HELD.lock().unwrap_or_else(|error| error.into_inner());
+        held.inputs = inputs.to_vec();
+        held.outputs = outputs.to_vec();
+
+        Guard
+    }
+
+    /// Keeps track of the input devices at `inputs` instead, after they've
+    /// been opened again
+    pub fn inputs(&self, inputs: &[RawFd]) {
+        let mut held = HELD.lock().unwrap_or_else(|error| error.into_inner());
+        held.inputs = inputs.to_vec();
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let mut held = HELD.lock().unwrap_or_else(|error| error.into_inner());
+        *held = Held::default();
+    }
+}
+
+/// Lets go of the input devices and destroys the virtual devices which are
+/// held, if any are
+fn release() {
+    // Whatever panicked could have been holding the lock, and waiting on it
+    // would never end
+    let held = match HELD.try_lock() {
+        Ok(held) => held,
+        Err(TryLockError::Poisoned(error)) => error.into_inner(),
+        Err(TryLockError::WouldBlock) => return,
+    };
+
+    // SAFETY: the descriptors stay open until the guard is dropped, and
+    // these ioctls fail harmlessly if they've already been let go of
+    unsafe {
+        for &input in &held.inputs {
+            libc::ioctl(input, EVIOCGRAB as _, 0 as libc::c_int);
+        }
+        for &output in &held.outputs {
+            libc::ioctl(output, UI_DEV_DESTROY as _);
+        }
+    }
+}
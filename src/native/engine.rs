@@ -0,0 +1,1739 @@
+use anyhow::{anyhow, Result};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use tracing::info;
+
+use crate::key::{EventType, Key, Value};
+use crate::pipeline::{Pipeline, Step};
+
+#[cfg(feature = "plugins")]
+use super::plugin::Plugin;
+#[cfg(feature = "scripting")]
+use super::script::Script;
+
+pub const EV_KEY: u16 = 0x01;
+pub const EV_REL: u16 = 0x02;
+pub const EV_ABS: u16 = 0x03;
+
+/// An event as it travels through the pipeline
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub event_type: u16,
+    pub code: u16,
+    pub value: i32,
+    /// The value the last event with this type and code had
+    pub previous: i32,
+    pub domain: usize,
+}
+
+/// The range and tuning of an absolute axis
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Axis {
+    pub min: i32,
+    pub max: i32,
+    pub fuzz: i32,
+    pub flat: i32,
+    pub resolution: i32,
+}
+
+/// Something a device can produce: an event type and code, and for
+/// absolute axes, what range it spans
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capability {
+    pub event_type: u16,
+    pub code: u16,
+    pub domain: usize,
+    pub axis: Option<Axis>,
+}
+
+/// A source device, as declared by the pipeline
+#[derive(Clone, Debug)]
+pub struct Input {
+    pub path: PathBuf,
+    pub domain: usize,
+}
+
+/// A virtual device, as declared by the pipeline
+#[derive(Clone, Debug)]
+pub struct Output {
+    pub name: String,
+    pub device_id: Option<(u16, u16)>,
+    /// The input device force feedback sent to this one is passed on to
+    pub rumble: Option<PathBuf>,
+}
+
+/// Whether an `EV_KEY` code is a button rather than a key, the same way
+/// `evsieve` tells `btn:` and `key:` apart
+fn is_button(code: u16) -> bool {
+    (0x100..0x160).contains(&code)
+        || (0x220..=0x223).contains(&code)
+        || (0x2c0..0x2e8).contains(&code)
+}
+
+/// A key from the pipeline, with its names resolved to numbers
+#[derive(Clone, Debug)]
+struct Pattern {
+    event_type: Option<EventType>,
+    code: Option<u16>,
+    value: Option<Value>,
+    domain: Option<usize>,
+}
+
+impl Pattern {
+    fn matches_code(&self, event_type: u16, code: u16, domain: usize) -> bool {
+        let type_matches = match self.event_type {
+            None => true,
+            Some(EventType::Key) => event_type == EV_KEY && !is_button(code),
+            Some(EventType::Btn) => event_type == EV_KEY && is_button(code),
+            Some(EventType::Abs) => event_type == EV_ABS,
+            Some(EventType::Rel) => event_type == EV_REL,
+        };
+
+        type_matches
+            && self.code.is_none_or(|own| own == code)
+            && self.domain.is_none_or(|own| own == domain)
+    }
+
+    fn matches(&self, event: &Event) -> bool {
+        if !self.matches_code(event.event_type, event.code, event.domain) {
+            return false;
+        }
+
+        match self.value {
+            Some(Value::Range { previous, current }) => {
+                current.contains(event.value)
+                    && previous.is_none_or(|previous| previous.contains(event.previous))
+            }
+            _ => true,
+        }
+    }
+
+    /// Whether this could match some of the events described by `capability`
+    fn could_match(&self, capability: &Capability) -> bool {
+        self.matches_code(capability.event_type, capability.code, capability.domain)
+    }
+
+    /// Whether this matches every event described by `capability`
+    fn always_matches(&self, capability: &Capability) -> bool {
+        self.could_match(capability) && self.value.is_none()
+    }
+
+    /// Narrows `capability` down to the values this matches
+    fn narrow(&self, capability: &Capability) -> Capability {
+        let mut capability = *capability;
+
+        if let (Some(Value::Range { current, .. }), Some(axis)) = (self.value, &mut capability.axis)
+        {
+            axis.min = current
+                .min
+                .map_or(axis.min, |min| min.clamp(axis.min, axis.max));
+            axis.max = current
+                .max
+                .map_or(axis.max, |max| max.clamp(axis.min, axis.max));
+        }
+
+        capability
+    }
+
+    fn event_type_number(&self) -> Option<u16> {
+        self.event_type.map(|event_type| match event_type {
+            EventType::Key | EventType::Btn => EV_KEY,
+            EventType::Abs => EV_ABS,
+            EventType::Rel => EV_REL,
+        })
+    }
+
+    /// Turns `event` into the event this target describes
+    fn apply(&self, event: &Event) -> Event {
+        let (value, previous) = match self.value {
+            None => (event.value, event.previous),
+            Some(Value::Range { current, .. }) => {
+                let value = current.min.or(current.max).unwrap_or(event.value);
+                (value, value)
+            }
+            Some(Value::Linear {
+                factor,
+                offset,
+                delta: false,
+            }) => (
+                (event.value as f64 * factor + offset).round() as i32,
+                (event.previous as f64 * factor + offset).round() as i32,
+            ),
+            Some(Value::Linear {
+                factor,
+                offset,
+                delta: true,
+            }) => (
+                ((event.value - event.previous) as f64 * factor + offset).round() as i32,
+                0,
+            ),
+        };
+
+        Event {
+            event_type: self.event_type_number().unwrap_or(event.event_type),
+            code: self.code.unwrap_or(event.code),
+            value,
+            previous,
+            domain: self.domain.unwrap_or(event.domain),
+        }
+    }
+
+    /// Turns `capability` into what this target can produce from it
+    fn apply_capability(&self, capability: &Capability) -> Capability {
+        let event_type = self.event_type_number().unwrap_or(capability.event_type);
+
+        let axis = if event_type != EV_ABS {
+            None
+        } else {
+            match (self.value, capability.axis) {
+                (Some(Value::Range { current, .. }), _) => {
+                    let value = current.min.or(current.max).unwrap_or_default();
+                    Some(Axis {
+                        min: value,
+                        max: value,
+                        fuzz: 0,
+                        flat: 0,
+                        resolution: 0,
+                    })
+                }
+                (
+                    Some(Value::Linear {
+                        factor,
+                        offset,
+                        delta: false,
+                    }),
+                    Some(axis),
+                ) => {
+                    let a = (axis.min as f64 * factor + offset).round() as i32;
+                    let b = (axis.max as f64 * factor + offset).round() as i32;
+                    Some(Axis {
+                        min: a.min(b),
+                        max: a.max(b),
+                        fuzz: (axis.fuzz as f64 * factor.abs()).round() as i32,
+                        flat: (axis.flat as f64 * factor.abs()).round() as i32,
+                        resolution: axis.resolution,
+                    })
+                }
+                (None, Some(axis)) => Some(axis),
+                // Buttons and the like turned into an axis are either on or off
+                _ => Some(Axis {
+                    min: 0,
+                    max: 1,
+                    fuzz: 0,
+                    flat: 0,
+                    resolution: 0,
+                }),
+            }
+        };
+
+        Capability {
+            event_type,
+            code: self.code.unwrap_or(capability.code),
+            domain: self.domain.unwrap_or(capability.domain),
+            axis,
+        }
+    }
+}
+
+/// How long a tap is held down for when it's passed on, long enough for
+/// games which only look at buttons once a frame to notice it
+const TAP: Duration = Duration::from_millis(50);
+
+/// Where a button held back by a long press stage has got to
+#[derive(Clone, Copy, Debug)]
+enum Press {
+    /// Held down since then, not for long enough to count as a long press yet
+    Down(SystemTime),
+    /// Held down long enough that the long press target has been pressed
+    Long,
+    /// Let go of before it was a long press, so it's been pressed as a tap
+    /// which is let go of then
+    Tap(SystemTime),
+}
+
+/// Keeps track of how long some buttons have been held down together
+#[derive(Debug)]
+struct Hold {
+    keys: Vec<Pattern>,
+    duration: Duration,
+    held: Vec<bool>,
+    /// When every one of `keys` started being held down
+    since: Option<SystemTime>,
+}
+
+impl Hold {
+    fn new(keys: Vec<Pattern>, duration: Duration) -> Self {
+        Hold {
+            held: vec![false; keys.len()],
+            keys,
+            duration,
+            since: None,
+        }
+    }
+
+    fn update(&mut self, event: &Event, time: SystemTime) {
+        let Some(index) = self.keys.iter().position(|key| key.matches(event)) else {
+            return;
+        };
+
+        let was = self.held.iter().all(|&held| held);
+        self.held[index] = event.value != 0;
+
+        match self.held.iter().all(|&held| held) {
+            true if !was => self.since = Some(time),
+            true => {}
+            false => self.since = None,
+        }
+    }
+
+    /// Whether the keys have now been held for long enough, which only
+    /// happens once each time they're held
+    fn elapsed(&mut self, time: SystemTime) -> bool {
+        let elapsed = self.since.is_some_and(|since| {
+            time.duration_since(since)
+                .is_ok_and(|elapsed| elapsed >= self.duration)
+        });
+
+        if elapsed {
+            self.since = None;
+        }
+
+        elapsed
+    }
+}
+
+/// A step of the pipeline, ready to have events run through it
+#[derive(Debug)]
+enum Stage {
+    Map {
+        source: Pattern,
+        targets: Vec<Pattern>,
+    },
+    Copy {
+        source: Pattern,
+        targets: Vec<Pattern>,
+    },
+    Block(Vec<Pattern>),
+    Smooth {
+        key: Pattern,
+        amount: f64,
+        /// The last raw value and smoothed value of each axis
+        state: HashMap<(usize, u16), (i32, f64)>,
+    },
+    Magnitude {
+        sources: Vec<Pattern>,
+        target: Pattern,
+        /// The latest value of each source, and the last magnitude produced
+        values: Vec<i32>,
+        last: i32,
+    },
+    Debounce {
+        key: Pattern,
+        interval: Duration,
+        /// When each button was last pressed, and which presses were dropped
+        pressed: HashMap<(usize, u16), SystemTime>,
+        dropped: HashSet<(usize, u16)>,
+    },
+    Toggle {
+        key: Pattern,
+        /// Which buttons are latched down
+        latched: HashSet<(usize, u16)>,
+    },
+    Repeat {
+        key: Pattern,
+        interval: Duration,
+        /// Each button being held down, with whether it's pressed as far as
+        /// anyone else knows and when it next changes
+        held: HashMap<(usize, u16), (bool, SystemTime)>,
+    },
+    Sustain {
+        key: Pattern,
+        interval: Duration,
+        /// Each axis away from 0, with its value and when it's next passed on
+        held: HashMap<(usize, u16), (i32, SystemTime)>,
+    },
+    LongPress {
+        key: Pattern,
+        target: Pattern,
+        hold: Duration,
+        presses: HashMap<(usize, u16), Press>,
+    },
+    Macro {
+        hold: Hold,
+        events: Vec<(Duration, Pattern)>,
+        /// The events still to be played, in order, with when they're due
+        queue: VecDeque<(SystemTime, Event)>,
+    },
+    Chord {
+        keys: Vec<Pattern>,
+        target: Pattern,
+        held: Vec<bool>,
+        active: bool,
+    },
+    Switch {
+        hold: Hold,
+        /// The name of each choice, and the domains it moves events between
+        choices: Vec<(String, Vec<(usize, usize)>)>,
+        active: usize,
+        /// Which buttons have been pressed in the domains events were moved to
+        pressed: HashSet<(usize, u16)>,
+    },
+    Exit(Hold),
+    Pause(Hold),
+    #[cfg(feature = "scripting")]
+    Script {
+        script: Box<Script>,
+        /// What the script can produce besides the events it's given
+        produces: Vec<Pattern>,
+    },
+    #[cfg(feature = "plugins")]
+    Plugin {
+        plugin: Box<Plugin>,
+        /// What the plugin can produce besides the events it's given, once
+        /// it's been set up
+        produces: Vec<Capability>,
+    },
+    Output {
+        index: usize,
+        keys: Vec<Pattern>,
+    },
+}
+
+/// Runs events through a pipeline, the way `evsieve` would
+#[derive(Debug)]
+pub struct Engine {
+    domains: Vec<String>,
+    inputs: Vec<Input>,
+    outputs: Vec<Output>,
+    stages: Vec<Stage>,
+    /// The flat of absolute axes on the outputs, by code, where it's been
+    /// given rather than left to whatever is mapped to them
+    flats: HashMap<u16, i32>,
+    previous: HashMap<(usize, u16, u16), i32>,
+    exiting: bool,
+    paused: bool,
+    /// Which keys are held down on each output, and the domain they came from
+    pressed: HashSet<(usize, (usize, u16))>,
+}
+
+impl Engine {
+    pub fn new(pipeline: &Pipeline) -> Result<Self> {
+        let mut engine = Engine {
+            domains: Vec::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            stages: Vec::new(),
+            flats: pipeline.flats.clone(),
+            previous: HashMap::new(),
+            exiting: false,
+            paused: false,
+            pressed: HashSet::new(),
+        };
+
+        for step in &pipeline.steps {
+            let stage = match step {
+                Step::Input { path, domain } => {
+                    let domain = engine.domain(domain);
+                    engine.inputs.push(Input {
+                        path: path.clone(),
+                        domain,
+                    });
+                    continue;
+                }
+                Step::Map { source, targets } => Stage::Map {
+                    source: engine.pattern(source)?,
+                    targets: engine.patterns(targets)?,
+                },
+                Step::Copy { source, targets } => Stage::Copy {
+                    source: engine.pattern(source)?,
+                    targets: engine.patterns(targets)?,
+                },
+                Step::Block { keys } => Stage::Block(engine.patterns(keys)?),
+                Step::Smooth { key, amount } => Stage::Smooth {
+                    key: engine.pattern(key)?,
+                    amount: *amount,
+                    state: HashMap::new(),
+                },
+                Step::Magnitude { sources, target } => Stage::Magnitude {
+                    sources: engine.patterns(sources)?,
+                    target: engine.pattern(target)?,
+                    values: vec![0; sources.len()],
+                    last: 0,
+                },
+                Step::Debounce { key, interval } => Stage::Debounce {
+                    key: engine.pattern(key)?,
+                    interval: *interval,
+                    pressed: HashMap::new(),
+                    dropped: HashSet::new(),
+                },
+                Step::Toggle { key } => Stage::Toggle {
+                    key: engine.pattern(key)?,
+                    latched: HashSet::new(),
+                },
+                Step::Repeat { key, interval } => Stage::Repeat {
+                    key: engine.pattern(key)?,
+                    interval: *interval,
+                    held: HashMap::new(),
+                },
+                Step::Sustain { key, interval } => Stage::Sustain {
+                    key: engine.pattern(key)?,
+                    interval: *interval,
+                    held: HashMap::new(),
+                },
+                Step::LongPress { key, target, hold } => Stage::LongPress {
+                    key: engine.pattern(key)?,
+                    target: engine.pattern(target)?,
+                    hold: *hold,
+                    presses: HashMap::new(),
+                },
+                Step::Macro { keys, events } => {
+                    let mut patterns = Vec::new();
+
+                    for (delay, key) in events {
+                        patterns.push((*delay, engine.pattern(key)?));
+                    }
+
+                    Stage::Macro {
+                        hold: Hold::new(engine.patterns(keys)?, Duration::ZERO),
+                        events: patterns,
+                        queue: VecDeque::new(),
+                    }
+                }
+                Step::Chord { keys, target } => Stage::Chord {
+                    keys: engine.patterns(keys)?,
+                    target: engine.pattern(target)?,
+                    held: vec![false; keys.len()],
+                    active: false,
+                },
+                Step::Switch {
+                    keys,
+                    hold,
+                    choices,
+                } => {
+                    let mut resolved = Vec::new();
+
+                    for choice in choices {
+                        let routes = choice
+                            .routes
+                            .iter()
+                            .map(|(from, to)| (engine.domain(from), engine.domain(to)))
+                            .collect();
+                        resolved.push((choice.name.clone(), routes));
+                    }
+
+                    Stage::Switch {
+                        hold: Hold::new(engine.patterns(keys)?, *hold),
+                        choices: resolved,
+                        active: 0,
+                        pressed: HashSet::new(),
+                    }
+                }
+                Step::Pause { keys, hold } => {
+                    Stage::Pause(Hold::new(engine.patterns(keys)?, *hold))
+                }
+                Step::Exit { keys, hold } => Stage::Exit(Hold::new(engine.patterns(keys)?, *hold)),
+                #[cfg(feature = "scripting")]
+                Step::Script { path } => {
+                    let script = Box::new(Script::load(path)?);
+                    let produces = engine.patterns(&script.produces()?)?;
+
+                    Stage::Script { script, produces }
+                }
+                #[cfg(not(feature = "scripting"))]
+                Step::Script { .. } => return Err(anyhow!("roadii was built without scripting")),
+                #[cfg(feature = "plugins")]
+                Step::Plugin { path } => Stage::Plugin {
+                    plugin: Box::new(Plugin::load(path)?),
+                    produces: Vec::new(),
+                },
+                #[cfg(not(feature = "plugins"))]
+                Step::Plugin { .. } => return Err(anyhow!("roadii was built without plugins")),
+                Step::Output {
+                    keys,
+                    name,
+                    device_id,
+                } => {
+                    engine.outputs.push(Output {
+                        name: name.clone(),
+                        device_id: *device_id,
+                        rumble: None,
+                    });
+                    Stage::Output {
+                        index: engine.outputs.len() - 1,
+                        keys: engine.patterns(keys)?,
+                    }
+                }
+                Step::Rumble { output, input } => {
+                    let (output, input) = (engine.domain(output), engine.domain(input));
+
+                    let path = engine
+                        .inputs
+                        .iter()
+                        .find(|other| other.domain == input)
+                        .map(|other| other.path.clone())
+                        .ok_or(anyhow!("there's no input to pass rumble on to"))?;
+
+                    let index = engine
+                        .stages
+                        .iter()
+                        .find_map(|stage| match stage {
+                            Stage::Output { index, keys }
+                                if keys.iter().any(|key| key.domain == Some(output)) =>
+                            {
+                                Some(*index)
+                            }
+                            _ => None,
+                        })
+                        .ok_or(anyhow!("there's no output for games to rumble"))?;
+
+                    engine.outputs[index].rumble = Some(path);
+                    continue;
+                }
+            };
+
+            engine.stages.push(stage);
+        }
+
+        // Plugins can only look domains up once they're all known
+        #[cfg(feature = "plugins")]
+        for stage in &mut engine.stages {
+            if let Stage::Plugin { plugin, produces } = stage {
+                *produces = plugin.init(&engine.domains)?;
+            }
+        }
+
+        Ok(engine)
+    }
+
+    pub fn inputs(&self) -> &[Input] {
+        &self.inputs
+    }
+
+    pub fn outputs(&self) -> &[Output] {
+        &self.outputs
+    }
+
+    /// How long until something is due to happen without an event coming
+    /// in, if anything is, as of `time`
+    pub fn until_due(&self, time: SystemTime) -> Option<Duration> {
+        self.stages
+            .iter()
+            .filter_map(|stage| match stage {
+                Stage::Repeat { held, .. } => held
+                    .values()
+                    .map(|(_, next)| next.duration_since(time).unwrap_or_default())
+                    .min(),
+                Stage::Sustain { held, .. } => held
+                    .values()
+                    .map(|(_, next)| next.duration_since(time).unwrap_or_default())
+                    .min(),
+                Stage::Macro { queue, .. } => queue
+                    .front()
+                    .map(|(due, _)| due.duration_since(time).unwrap_or_default()),
+                Stage::LongPress { hold, presses, .. } => presses
+                    .values()
+                    .filter_map(|press| match *press {
+                        Press::Down(since) => Some(since + *hold),
+                        Press::Long => None,
+                        Press::Tap(until) => Some(until),
+                    })
+                    .map(|due| due.duration_since(time).unwrap_or_default())
+                    .min(),
+                _ => None,
+            })
+            .min()
+    }
+
+    /// Whether an exit chord has been held, so remapping should stop
+    pub fn exiting(&self) -> bool {
+        self.exiting
+    }
+
+    /// Whether a pause chord has been held, so the inputs should be let go
+    /// until it's held again
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// The number for a domain name, allocating one if it's new
+    fn domain(&mut self, name: &str) -> usize {
+        match self.domains.iter().position(|domain| domain == name) {
+            Some(index) => index,
+            None => {
+                self.domains.push(name.to_owned());
+                self.domains.len() - 1
+            }
+        }
+    }
+
+    fn pattern(&mut self, key: &Key) -> Result<Pattern> {
+        Ok(Pattern {
+            event_type: key.event_type,
+            code: match &key.code {
+                Some(_) => Some(
+                    key.code_number()
+                        .ok_or_else(|| anyhow!("{} isn't an event we know about", key))?,
+                ),
+                None => None,
+            },
+            value: key.value,
+            domain: key.domain.as_deref().map(|domain| self.domain(domain)),
+        })
+    }
+
+    fn patterns(&mut self, keys: &[Key]) -> Result<Vec<Pattern>> {
+        keys.iter().map(|key| self.pattern(key)).collect()
+    }
+
+    /// Works out what each output can produce, given what the inputs can
+    pub fn capabilities(&self, mut capabilities: Vec<Capability>) -> Vec<Vec<Capability>> {
+        let mut outputs = vec![Vec::new(); self.outputs.len()];
+
+        for stage in &self.stages {
+            capabilities = match stage {
+                Stage::Map { source, targets } => capabilities
+                    .into_iter()
+                    .flat_map(|capability| {
+                        if !source.could_match(&capability) {
+                            return vec![capability];
+                        }
+
+                        let narrowed = source.narrow(&capability);
+                        let mut produced: Vec<Capability> = targets
+                            .iter()
+                            .map(|target| target.apply_capability(&narrowed))
+                            .collect();
+
+                        // Events with values the source doesn't match pass on
+                        if !source.always_matches(&capability) {
+                            produced.push(capability);
+                        }
+
+                        produced
+                    })
+                    .collect(),
+                Stage::Copy { source, targets } => capabilities
+                    .into_iter()
+                    .flat_map(|capability| {
+                        let mut produced = vec![capability];
+
+                        if source.could_match(&capability) {
+                            let narrowed = source.narrow(&capability);
+                            produced.extend(
+                                targets
+                                    .iter()
+                                    .map(|target| target.apply_capability(&narrowed)),
+                            );
+                        }
+
+                        produced
+                    })
+                    .collect(),
+                Stage::Block(keys) => capabilities
+                    .into_iter()
+                    .filter(|capability| !keys.iter().any(|key| key.always_matches(capability)))
+                    .collect(),
+                Stage::Smooth { .. }
+                | Stage::Debounce { .. }
+                | Stage::Toggle { .. }
+                | Stage::Repeat { .. }
+                | Stage::Sustain { .. }
+                | Stage::Exit(_)
+                | Stage::Pause(_) => capabilities,
+                #[cfg(feature = "scripting")]
+                Stage::Script { produces, .. } => {
+                    for pattern in produces {
+                        let mut capability = pattern.apply_capability(&Capability {
+                            event_type: EV_KEY,
+                            code: 0,
+                            domain: 0,
+                            axis: None,
+                        });
+
+                        // Axes span whatever range the script gives them
+                        if let (Some(axis), Some(Value::Range { current, .. })) =
+                            (&mut capability.axis, pattern.value)
+                        {
+                            if let (Some(min), Some(max)) = (current.min, current.max) {
+                                axis.min = min.min(max);
+                                axis.max = min.max(max);
+                            }
+                        }
+
+                        capabilities.push(capability);
+                    }
+
+                    capabilities
+                }
+                #[cfg(feature = "plugins")]
+                Stage::Plugin { produces, .. } => {
+                    capabilities.extend(produces);
+                    capabilities
+                }
+                Stage::Macro { events, .. } => {
+                    for (_, pattern) in events {
+                        capabilities.push(pattern.apply_capability(&Capability {
+                            event_type: EV_KEY,
+                            code: 0,
+                            domain: 0,
+                            axis: None,
+                        }));
+                    }
+
+                    capabilities
+                }
+                Stage::LongPress { key, target, .. } => {
+                    if let Some(first) = capabilities
+                        .iter()
+                        .find(|capability| key.could_match(capability))
+                    {
+                        let capability = target.apply_capability(first);
+                        capabilities.push(capability);
+                    }
+
+                    capabilities
+                }
+                Stage::Chord { keys, target, .. } => {
+                    if let Some(first) = capabilities
+                        .iter()
+                        .find(|capability| keys.iter().any(|key| key.could_match(capability)))
+                    {
+                        let capability = target.apply_capability(first);
+                        capabilities.push(capability);
+                    }
+
+                    capabilities
+                }
+                Stage::Magnitude {
+                    sources, target, ..
+                } => {
+                    let matched: Vec<Capability> = capabilities
+                        .iter()
+                        .filter(|capability| {
+                            sources.iter().any(|source| source.could_match(capability))
+                        })
+                        .copied()
+                        .collect();
+
+                    if let Some(first) = matched.first() {
+                        let max = matched
+                            .iter()
+                            .filter_map(|capability| capability.axis)
+                            .map(|axis| {
+                                (axis.min.unsigned_abs().max(axis.max.unsigned_abs()) as f64)
+                                    .powi(2)
+                            })
+                            .sum::<f64>()
+                            .sqrt()
+                            .round() as i32;
+
+                        let mut capability = target.apply_capability(first);
+                        capability.axis = Some(Axis {
+                            min: 0,
+                            max,
+                            fuzz: 0,
+                            flat: 0,
+                            resolution: 0,
+                        });
+                        capabilities.push(capability);
+                    }
+
+                    capabilities
+                }
+                Stage::Switch { choices, .. } => capabilities
+                    .into_iter()
+                    .flat_map(|capability| {
+                        let moved: Vec<Capability> = choices
+                            .iter()
+                            .flat_map(|(_, routes)| routes)
+                            .filter(|(from, _)| *from == capability.domain)
+                            .map(|(_, to)| Capability {
+                                domain: *to,
+                                ..capability
+                            })
+                            .collect();
+
+                        match moved.is_empty() {
+                            true => vec![capability],
+                            false => moved,
+                        }
+                    })
+                    .collect(),
+                Stage::Output { index, keys } => capabilities
+                    .into_iter()
+                    .filter(|capability| {
+                        if !keys.iter().any(|key| key.could_match(capability)) {
+                            return true;
+                        }
+
+                        outputs[*index].push(*capability);
+
+                        !keys.iter().any(|key| key.always_matches(capability))
+                    })
+                    .collect(),
+            };
+        }
+
+        // Several things may be mapped to the same event, so merge them,
+        // widening axes so they span everything mapped to them
+        outputs
+            .into_iter()
+            .map(|capabilities| {
+                let mut merged: Vec<Capability> = Vec::new();
+
+                for capability in capabilities {
+                    match merged.iter_mut().find(|existing| {
+                        existing.event_type == capability.event_type
+                            && existing.code == capability.code
+                    }) {
+                        Some(existing) => {
+                            if let (Some(axis), Some(other)) = (&mut existing.axis, capability.axis)
+                            {
+                                axis.min = axis.min.min(other.min);
+                                axis.max = axis.max.max(other.max);
+                            }
+                        }
+                        None => merged.push(capability),
+                    }
+                }
+
+                for capability in &mut merged {
+                    if let (Some(axis), Some(&flat)) =
+                        (&mut capability.axis, self.flats.get(&capability.code))
+                    {
+                        axis.flat = flat;
+                    }
+                }
+
+                merged
+            })
+            .collect()
+    }
+
+    /// Runs an event from input `input` which happened at `time` through the
+    /// pipeline, returning the events it produced on each output, as the
+    /// index of the output and the event itself
+    pub fn process(
+        &mut self,
+        input: usize,
+        event_type: u16,
+        code: u16,
+        value: i32,
+        time: SystemTime,
+    ) -> Vec<(usize, Event)> {
+        let domain = self.inputs[input].domain;
+        let previous = self
+            .previous
+            .insert((domain, event_type, code), value)
+            .unwrap_or(value);
+
+        let event = Event {
+            event_type,
+            code,
+            value,
+            previous,
+            domain,
+        };
+
+        self.run(vec![event], time)
+    }
+
+    /// Lets anything waiting for time to pass act, now that it's `time`,
+    /// returning the events that produced the same way as `process`
+    pub fn tick(&mut self, time: SystemTime) -> Vec<(usize, Event)> {
+        self.run(Vec::new(), time)
+    }
+
+    fn run(&mut self, mut events: Vec<Event>, time: SystemTime) -> Vec<(usize, Event)> {
+        let mut produced = Vec::new();
+        let exiting = &mut self.exiting;
+        let was_paused = self.paused;
+        let paused = &mut self.paused;
+
+        for stage in &mut self.stages {
+            // Holds can act on their own once they've been held long enough
+            if events.is_empty()
+                && !matches!(
+                    stage,
+                    Stage::Switch { .. }
+                        | Stage::Exit(_)
+                        | Stage::Pause(_)
+                        | Stage::Repeat { .. }
+                        | Stage::Sustain { .. }
+                        | Stage::LongPress { .. }
+                        | Stage::Macro { .. }
+                )
+            {
+                continue;
+            }
+
+            events = match stage {
+                Stage::Map { source, targets } => events
+                    .into_iter()
+                    .flat_map(|event| {
+                        if source.matches(&event) {
+                            targets.iter().map(|target| target.apply(&event)).collect()
+                        } else {
+                            vec![event]
+                        }
+                    })
+                    .collect(),
+                Stage::Copy { source, targets } => events
+                    .into_iter()
+                    .flat_map(|event| {
+                        let mut events = vec![event];
+
+                        if source.matches(&event) {
+                            events.extend(targets.iter().map(|target| target.apply(&event)));
+                        }
+
+                        events
+                    })
+                    .collect(),
+                Stage::Block(keys) => events
+                    .into_iter()
+                    .filter(|event| !keys.iter().any(|key| key.matches(event)))
+                    .collect(),
+                Stage::Smooth { key, amount, state } => events
+                    .into_iter()
+                    .map(|event| {
+                        if event.event_type != EV_ABS || !key.matches(&event) {
+                            return event;
+                        }
+
+                        let entry = state
+                            .entry((event.domain, event.code))
+                            .or_insert((event.previous, event.previous as f64));
+
+                        // If we didn't see the last value, there's been a gap
+                        // in the events, so start smoothing afresh from here
+                        if entry.0 != event.previous {
+                            *entry = (event.value, event.value as f64);
+                            return event;
+                        }
+
+                        let last = entry.1;
+                        let smoothed = last + (event.value as f64 - last) * (1.0 - *amount);
+                        *entry = (event.value, smoothed);
+
+                        Event {
+                            value: smoothed.round() as i32,
+                            previous: last.round() as i32,
+                            ..event
+                        }
+                    })
+                    .collect(),
+                Stage::Magnitude {
+                    sources,
+                    target,
+                    values,
+                    last,
+                } => events
+                    .into_iter()
+                    .flat_map(|event| {
+                        let Some(index) = sources.iter().position(|source| source.matches(&event))
+                        else {
+                            return vec![event];
+                        };
+
+                        values[index] = event.value;
+                        let magnitude = values
+                            .iter()
+                            .map(|&value| (value as f64).powi(2))
+                            .sum::<f64>()
+                            .sqrt()
+                            .round() as i32;
+
+                        let produced = Event {
+                            value: magnitude,
+                            previous: *last,
+                            ..target.apply(&event)
+                        };
+                        *last = magnitude;
+
+                        vec![event, produced]
+                    })
+                    .collect(),
+                Stage::Debounce {
+                    key,
+                    interval,
+                    pressed,
+                    dropped,
+                } => events
+                    .into_iter()
+                    .filter(|event| {
+                        if event.event_type != EV_KEY || !key.matches(event) {
+                            return true;
+                        }
+
+                        let button = (event.domain, event.code);
+
+                        match event.value {
+                            1 => {
+                                let bouncing = pressed.get(&button).is_some_and(|last| {
+                                    time.duration_since(*last)
+                                        .is_ok_and(|since| since < *interval)
+                                });
+
+                                if bouncing {
+                                    dropped.insert(button);
+                                    return false;
+                                }
+
+                                pressed.insert(button, time);
+                                true
+                            }
+                            0 => !dropped.remove(&button),
+                            _ => !dropped.contains(&button),
+                        }
+                    })
+                    .collect(),
+                #[cfg(feature = "scripting")]
+                Stage::Script { script, .. } => events
+                    .into_iter()
+                    .flat_map(|event| script.process(event, &self.domains))
+                    .collect(),
+                #[cfg(feature = "plugins")]
+                Stage::Plugin { plugin, .. } => events
+                    .into_iter()
+                    .flat_map(|event| plugin.process(event))
+                    .collect(),
+                Stage::Toggle { key, latched } => events
+                    .into_iter()
+                    .filter_map(|mut event| {
+                        if event.event_type != EV_KEY || !key.matches(&event) {
+                            return Some(event);
+                        }
+
+                        // Only presses do anything, flipping it the other way
+                        if event.value != 1 {
+                            return None;
+                        }
+
+                        let button = (event.domain, event.code);
+
+                        if !latched.remove(&button) {
+                            latched.insert(button);
+                        } else {
+                            event.value = 0;
+                        }
+
+                        event.previous = 1 - event.value;
+                        Some(event)
+                    })
+                    .collect(),
+                Stage::Repeat {
+                    key,
+                    interval,
+                    held,
+                } => {
+                    let half = *interval / 2;
+
+                    let mut repeated: Vec<Event> = events
+                        .into_iter()
+                        .filter(|event| {
+                            if event.event_type != EV_KEY || !key.matches(event) {
+                                return true;
+                            }
+
+                            let button = (event.domain, event.code);
+
+                            match event.value {
+                                // It may already have been let go of
+                                0 => held.remove(&button).is_none_or(|(pressed, _)| pressed),
+                                1 => {
+                                    held.insert(button, (true, time + half));
+                                    true
+                                }
+                                _ => false,
+                            }
+                        })
+                        .collect();
+
+                    for (&(domain, code), (pressed, next)) in held.iter_mut() {
+                        if time < *next {
+                            continue;
+                        }
+
+                        *pressed = !*pressed;
+                        *next = time + half;
+
+                        let value = *pressed as i32;
+                        repeated.push(Event {
+                            event_type: EV_KEY,
+                            code,
+                            value,
+                            previous: 1 - value,
+                            domain,
+                        });
+                    }
+
+                    repeated
+                }
+                Stage::Sustain {
+                    key,
+                    interval,
+                    held,
+                } => {
+                    for event in &events {
+                        if event.event_type != EV_ABS || !key.matches(event) {
+                            continue;
+                        }
+
+                        let axis = (event.domain, event.code);
+
+                        match event.value {
+                            0 => held.remove(&axis),
+                            value => held.insert(axis, (value, time + *interval)),
+                        };
+                    }
+
+                    for (&(domain, code), (value, next)) in held.iter_mut() {
+                        if time < *next {
+                            continue;
+                        }
+
+                        *next = time + *interval;
+
+                        events.push(Event {
+                            event_type: EV_ABS,
+                            code,
+                            value: *value,
+                            previous: *value,
+                            domain,
+                        });
+                    }
+
+                    events
+                }
+                Stage::LongPress {
+                    key,
+                    target,
+                    hold,
+                    presses,
+                } => {
+                    let button = |(domain, code), value| Event {
+                        event_type: EV_KEY,
+                        code,
+                        value,
+                        previous: 1 - value,
+                        domain,
+                    };
+
+                    let mut passed = Vec::new();
+
+                    for event in events {
+                        if event.event_type != EV_KEY || !key.matches(&event) {
+                            passed.push(event);
+                            continue;
+                        }
+
+                        let id = (event.domain, event.code);
+
+                        match (event.value, presses.get(&id).copied()) {
+                            (1, previous) => {
+                                // Pressed again before the last tap was over
+                                if let Some(Press::Tap(_)) = previous {
+                                    passed.push(button(id, 0));
+                                }
+
+                                presses.insert(id, Press::Down(time));
+                            }
+                            (0, Some(Press::Down(_))) => {
+                                passed.push(button(id, 1));
+                                presses.insert(id, Press::Tap(time + TAP));
+                            }
+                            (0, Some(Press::Long)) => {
+                                presses.remove(&id);
+                                passed.push(target.apply(&button(id, 0)));
+                            }
+                            (0, Some(Press::Tap(_))) => {}
+                            (0, None) => passed.push(event),
+                            // Repeats are no use when we're holding it back
+                            _ => {}
+                        }
+                    }
+
+                    presses.retain(|&id, press| match *press {
+                        Press::Down(since) if time >= since + *hold => {
+                            passed.push(target.apply(&button(id, 1)));
+                            *press = Press::Long;
+                            true
+                        }
+                        Press::Tap(until) if time >= until => {
+                            passed.push(button(id, 0));
+                            false
+                        }
+                        _ => true,
+                    });
+
+                    passed
+                }
+                Stage::Macro {
+                    hold,
+                    events: steps,
+                    queue,
+                } => {
+                    for event in &events {
+                        hold.update(event, time);
+                    }
+
+                    if hold.elapsed(time) && queue.is_empty() {
+                        for (delay, pattern) in steps.iter() {
+                            let event = pattern.apply(&Event {
+                                event_type: EV_KEY,
+                                code: 0,
+                                value: 0,
+                                previous: 0,
+                                domain: 0,
+                            });
+
+                            queue.push_back((
+                                time + *delay,
+                                Event {
+                                    previous: 1 - event.value,
+                                    ..event
+                                },
+                            ));
+                        }
+                    }
+
+                    while let Some(&(due, event)) = queue.front() {
+                        if due > time {
+                            break;
+                        }
+
+                        queue.pop_front();
+                        events.push(event);
+                    }
+
+                    events
+                }
+                Stage::Chord {
+                    keys,
+                    target,
+                    held,
+                    active,
+                } => events
+                    .into_iter()
+                    .flat_map(|event| {
+                        let Some(index) = keys.iter().position(|key| key.matches(&event)) else {
+                            return vec![event];
+                        };
+
+                        held[index] = event.value != 0;
+                        let all = held.iter().all(|&held| held);
+                        if all == *active {
+                            return vec![event];
+                        }
+
+                        *active = all;
+                        let value = all as i32;
+
+                        vec![
+                            event,
+                            Event {
+                                value,
+                                previous: 1 - value,
+                                ..target.apply(&event)
+                            },
+                        ]
+                    })
+                    .collect(),
+                Stage::Switch {
+                    hold,
+                    choices,
+                    active,
+                    pressed,
+                } => {
+                    let mut moved = Vec::new();
+
+                    for mut event in events {
+                        hold.update(&event, time);
+
+                        let (_, routes) = &choices[*active];
+                        if let Some((_, to)) = routes.iter().find(|(from, _)| *from == event.domain)
+                        {
+                            event.domain = *to;
+
+                            if event.event_type == EV_KEY {
+                                match event.value {
+                                    0 => pressed.remove(&(event.domain, event.code)),
+                                    _ => pressed.insert((event.domain, event.code)),
+                                };
+                            }
+                        }
+
+                        moved.push(event);
+                    }
+
+                    if hold.elapsed(time) {
+                        // Let go of everything held in the old choice, or it'd
+                        // be stuck down until we came back around to it
+                        moved.extend(pressed.drain().map(|(domain, code)| Event {
+                            event_type: EV_KEY,
+                            code,
+                            value: 0,
+                            previous: 1,
+                            domain,
+                        }));
+
+                        *active = (*active + 1) % choices.len();
+                        info!("Switched to {}", choices[*active].0);
+                    }
+
+                    moved
+                }
+                Stage::Exit(hold) => {
+                    for event in &events {
+                        hold.update(event, time);
+                    }
+
+                    if hold.elapsed(time) {
+                        *exiting = true;
+                    }
+
+                    events
+                }
+                Stage::Pause(hold) => {
+                    for event in &events {
+                        hold.update(event, time);
+                    }
+
+                    if hold.elapsed(time) {
+                        *paused = !*paused;
+                    }
+
+                    events
+                }
+                Stage::Output { index, keys } => events
+                    .into_iter()
+                    .filter(|event| {
+                        if keys.iter().any(|key| key.matches(event)) {
+                            produced.push((*index, *event));
+                            false
+                        } else {
+                            true
+                        }
+                    })
+                    .collect(),
+            };
+        }
+
+        // Nothing comes out while paused, and anything held down when it was
+        // paused is let go so it isn't stuck down in the meantime
+        match (was_paused, self.paused) {
+            (false, true) => {
+                produced = self
+                    .pressed
+                    .drain()
+                    .map(|(output, (domain, code))| {
+                        (
+                            output,
+                            Event {
+                                event_type: EV_KEY,
+                                code,
+                                value: 0,
+                                previous: 1,
+                                domain,
+                            },
+                        )
+                    })
+                    .collect();
+            }
+            (_, true) => produced.clear(),
+            (_, false) => {
+                for (output, event) in &produced {
+                    if event.event_type == EV_KEY {
+                        let key = (*output, (event.domain, event.code));
+                        match event.value {
+                            0 => self.pressed.remove(&key),
+                            _ => self.pressed.insert(key),
+                        };
+                    }
+                }
+            }
+        }
+
+        produced
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::Choice;
+    use std::time::UNIX_EPOCH;
+
+    /// `ms` milliseconds into a session
+    fn at(ms: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_000_000) + Duration::from_millis(ms)
+    }
+
+    fn code(key: &str) -> u16 {
+        key.parse::<Key>().unwrap().code_number().unwrap()
+    }
+
+    fn key(key: &str) -> Key {
+        key.parse().unwrap()
+    }
+
+    /// An engine reading a guitar and writing everything from it to a
+    /// virtual device, with whatever `steps` adds in between
+    fn engine(steps: impl FnOnce(&mut Pipeline)) -> Engine {
+        let mut pipeline = Pipeline::default();
+        pipeline.input(PathBuf::new(), "guitar");
+        steps(&mut pipeline);
+        pipeline.map("@guitar", "@wiitar").unwrap();
+        pipeline.output("wiitar", "Wiitar", None);
+
+        Engine::new(&pipeline).unwrap()
+    }
+
+    /// The codes and values of the events that came out
+    fn codes(produced: Vec<(usize, Event)>) -> Vec<(u16, i32)> {
+        produced
+            .into_iter()
+            .map(|(_, event)| (event.code, event.value))
+            .collect()
+    }
+
+    /// Sets `button` on the guitar to `value` at `ms`
+    fn press(engine: &mut Engine, button: &str, value: i32, ms: u64) -> Vec<(u16, i32)> {
+        codes(engine.process(0, EV_KEY, code(button), value, at(ms)))
+    }
+
+    fn tick(engine: &mut Engine, ms: u64) -> Vec<(u16, i32)> {
+        codes(engine.tick(at(ms)))
+    }
+
+    #[test]
+    fn presses_which_come_too_soon_are_debounced() {
+        let mut engine = engine(|pipeline| {
+            pipeline
+                .debounce("btn:south@guitar", Duration::from_millis(30))
+                .unwrap();
+        });
+        let south = code("btn:south");
+
+        assert_eq!(press(&mut engine, "btn:south", 1, 0), [(south, 1)]);
+        assert_eq!(press(&mut engine, "btn:south", 0, 5), [(south, 0)]);
+        assert_eq!(press(&mut engine, "btn:south", 1, 10), []);
+        assert_eq!(press(&mut engine, "btn:south", 0, 15), []);
+        assert_eq!(press(&mut engine, "btn:south", 1, 50), [(south, 1)]);
+    }
+
+    #[test]
+    fn toggled_buttons_change_on_each_press() {
+        let mut engine = engine(|pipeline| {
+            pipeline.toggle("btn:south@guitar").unwrap();
+        });
+        let south = code("btn:south");
+
+        assert_eq!(press(&mut engine, "btn:south", 1, 0), [(south, 1)]);
+        assert_eq!(press(&mut engine, "btn:south", 0, 100), []);
+        assert_eq!(press(&mut engine, "btn:south", 1, 200), [(south, 0)]);
+        assert_eq!(press(&mut engine, "btn:south", 0, 300), []);
+    }
+
+    #[test]
+    fn held_buttons_repeat_until_let_go() {
+        let mut engine = engine(|pipeline| {
+            pipeline
+                .repeat("btn:south@guitar", Duration::from_millis(100))
+                .unwrap();
+        });
+        let south = code("btn:south");
+
+        assert_eq!(press(&mut engine, "btn:south", 1, 0), [(south, 1)]);
+        assert_eq!(tick(&mut engine, 50), [(south, 0)]);
+        assert_eq!(tick(&mut engine, 100), [(south, 1)]);
+        assert_eq!(press(&mut engine, "btn:south", 0, 120), [(south, 0)]);
+        assert_eq!(tick(&mut engine, 200), []);
+    }
+
+    #[test]
+    fn short_presses_tap_and_long_ones_hold_the_target() {
+        let mut engine = engine(|pipeline| {
+            pipeline
+                .long_press(
+                    "btn:south@guitar",
+                    "btn:mode@guitar",
+                    Duration::from_millis(500),
+                )
+                .unwrap();
+        });
+        let (south, mode) = (code("btn:south"), code("btn:mode"));
+
+        assert_eq!(press(&mut engine, "btn:south", 1, 0), []);
+        assert_eq!(press(&mut engine, "btn:south", 0, 100), [(south, 1)]);
+        assert_eq!(
+            tick(&mut engine, 100 + TAP.as_millis() as u64),
+            [(south, 0)]
+        );
+
+        assert_eq!(press(&mut engine, "btn:south", 1, 1000), []);
+        assert_eq!(tick(&mut engine, 1500), [(mode, 1)]);
+        assert_eq!(press(&mut engine, "btn:south", 0, 1600), [(mode, 0)]);
+    }
+
+    #[test]
+    fn chords_are_held_while_all_their_buttons_are() {
+        let mut engine = engine(|pipeline| {
+            pipeline
+                .chord(&["btn:1@guitar", "btn:2@guitar"], "btn:mode@guitar")
+                .unwrap();
+        });
+        let (one, two, mode) = (code("btn:1"), code("btn:2"), code("btn:mode"));
+
+        assert_eq!(press(&mut engine, "btn:1", 1, 0), [(one, 1)]);
+        assert_eq!(press(&mut engine, "btn:2", 1, 10), [(two, 1), (mode, 1)]);
+        assert_eq!(press(&mut engine, "btn:1", 0, 20), [(one, 0), (mode, 0)]);
+        assert_eq!(press(&mut engine, "btn:2", 0, 30), [(two, 0)]);
+    }
+
+    #[test]
+    fn switches_move_events_once_held_and_let_go_of_what_was_pressed() {
+        let mut pipeline = Pipeline::default();
+        pipeline.input(PathBuf::new(), "guitar");
+        pipeline.steps.push(Step::Switch {
+            keys: vec![key("btn:start@guitar")],
+            hold: Duration::from_secs(2),
+            choices: ["a", "b"]
+                .into_iter()
+                .map(|name| Choice {
+                    name: name.to_owned(),
+                    routes: vec![("guitar".to_owned(), format!("guitar.{}", name))],
+                })
+                .collect(),
+        });
+        pipeline.map("btn:1@guitar.a", "btn:south@wiitar").unwrap();
+        pipeline.map("btn:1@guitar.b", "btn:east@wiitar").unwrap();
+        pipeline.output("wiitar", "Wiitar", None);
+        let mut engine = Engine::new(&pipeline).unwrap();
+        let (south, east) = (code("btn:south"), code("btn:east"));
+
+        assert_eq!(press(&mut engine, "btn:1", 1, 0), [(south, 1)]);
+        assert_eq!(press(&mut engine, "btn:start", 1, 100), []);
+        assert_eq!(tick(&mut engine, 1000), []);
+        assert_eq!(tick(&mut engine, 2100), [(south, 0)]);
+        assert_eq!(press(&mut engine, "btn:start", 0, 2200), []);
+        assert_eq!(press(&mut engine, "btn:1", 0, 2300), [(east, 0)]);
+        assert_eq!(press(&mut engine, "btn:1", 1, 2400), [(east, 1)]);
+    }
+
+    #[test]
+    fn pausing_lets_go_of_everything_and_drops_events_until_resumed() {
+        let mut engine = engine(|pipeline| {
+            pipeline.steps.push(Step::Pause {
+                keys: vec![key("btn:start@guitar")],
+                hold: Duration::from_secs(2),
+            });
+        });
+        let (south, east, start) = (code("btn:south"), code("btn:east"), code("btn:start"));
+
+        assert_eq!(press(&mut engine, "btn:south", 1, 0), [(south, 1)]);
+        assert_eq!(press(&mut engine, "btn:start", 1, 100), [(start, 1)]);
+        assert!(!engine.paused());
+
+        let released = tick(&mut engine, 2100);
+        assert!(engine.paused());
+        assert!(released.contains(&(south, 0)));
+        assert!(released.contains(&(start, 0)));
+
+        assert_eq!(press(&mut engine, "btn:start", 0, 2200), []);
+        assert_eq!(press(&mut engine, "btn:east", 1, 2300), []);
+        assert_eq!(press(&mut engine, "btn:start", 1, 2400), []);
+        tick(&mut engine, 4400);
+        assert!(!engine.paused());
+
+        press(&mut engine, "btn:start", 0, 4500);
+        assert_eq!(press(&mut engine, "btn:east", 0, 4600), [(east, 0)]);
+    }
+
+    #[test]
+    fn macros_play_their_events_in_time() {
+        let mut engine = engine(|pipeline| {
+            pipeline.steps.push(Step::Macro {
+                keys: vec![key("btn:mode@guitar")],
+                events: vec![
+                    (Duration::ZERO, key("btn:south:1@wiitar")),
+                    (Duration::from_millis(50), key("btn:south:0@wiitar")),
+                ],
+            });
+        });
+        let (south, mode) = (code("btn:south"), code("btn:mode"));
+
+        assert_eq!(
+            press(&mut engine, "btn:mode", 1, 0),
+            [(mode, 1), (south, 1)]
+        );
+        assert_eq!(tick(&mut engine, 20), []);
+        assert_eq!(tick(&mut engine, 50), [(south, 0)]);
+        assert_eq!(press(&mut engine, "btn:mode", 0, 100), [(mode, 0)]);
+    }
+
+    #[test]
+    fn capabilities_follow_maps_and_merge_axes() {
+        let mut pipeline = Pipeline::default();
+        pipeline.input(PathBuf::new(), "guitar");
+        pipeline.map("btn:1@guitar", "btn:south@wiitar").unwrap();
+        pipeline.map("abs:x@guitar", "abs:x:-x@wiitar").unwrap();
+        pipeline.map("abs:y@guitar", "abs:x@wiitar").unwrap();
+        pipeline.output("wiitar", "Wiitar", None);
+        pipeline.flats.insert(code("abs:x"), 8);
+        let engine = Engine::new(&pipeline).unwrap();
+        let domain = engine.inputs()[0].domain;
+
+        let axis = |min, max| Axis {
+            min,
+            max,
+            fuzz: 0,
+            flat: 2,
+            resolution: 0,
+        };
+        let outputs = engine.capabilities(vec![
+            Capability {
+                event_type: EV_KEY,
+                code: code("btn:1"),
+                domain,
+                axis: None,
+            },
+            Capability {
+                event_type: EV_ABS,
+                code: code("abs:x"),
+                domain,
+                axis: Some(axis(-32, 31)),
+            },
+            Capability {
+                event_type: EV_ABS,
+                code: code("abs:y"),
+                domain,
+                axis: Some(axis(0, 100)),
+            },
+        ]);
+
+        assert_eq!(outputs.len(), 1);
+        let found: Vec<(u16, Option<Axis>)> = outputs[0]
+            .iter()
+            .map(|capability| (capability.code, capability.axis))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (code("btn:south"), None),
+                (
+                    code("abs:x"),
+                    Some(Axis {
+                        min: -31,
+                        max: 100,
+                        fuzz: 0,
+                        flat: 8,
+                        resolution: 0,
+                    })
+                ),
+            ]
+        );
+    }
+}
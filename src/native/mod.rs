@@ -0,0 +1,993 @@
+use anyhow::{anyhow, bail, Context, Result};
+use evdev::uinput::VirtualDevice;
+use evdev::{
+    AbsInfo, AbsoluteAxisCode, AttributeSet, BusType, EventSummary, EventType, FFEffect,
+    FFEffectCode, InputEvent, InputId, KeyCode, RelativeAxisCode, SynchronizationCode, UInputCode,
+    UinputAbsSetup,
+};
+use std::collections::HashMap;
+use std::io::{self, ErrorKind};
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, warn};
+
+use crate::key::Key;
+use crate::notify::Notifier;
+use crate::outputs;
+use crate::permissions;
+use crate::pipeline::{Pipeline, Step};
+use crate::privileges::User;
+use crate::record::Recording;
+use crate::sandbox;
+
+mod cleanup;
+mod engine;
+#[cfg(feature = "plugins")]
+mod plugin;
+#[cfg(feature = "scripting")]
+mod script;
+
+use engine::{Axis, Capability, Engine, Event, Output, EV_ABS, EV_KEY, EV_REL};
+
+/// The ioctl which grabs an input device, or lets go of it
+const EVIOCGRAB: libc::c_ulong = 0x40044590;
+
+/// How often the engine gets to act without an event coming in, such as to
+/// notice a switch has been held long enough, unless something's due sooner
+const TICK: Duration = Duration::from_millis(100);
+
+/// The least time a replay moves on by between ticks, so something which
+/// is already due can't keep it ticking forever
+const REPLAY_STEP: Duration = Duration::from_millis(1);
+
+/// How many force feedback effects games can give a virtual device at once
+const FF_EFFECTS: u32 = 16;
+
+/// What the threads feeding the main loop have to tell it about
+enum Message {
+    /// An input device produced a report, the events up to a SYN_REPORT
+    Report(usize, Vec<InputEvent>),
+
+    /// A game has sent force feedback to one of the virtual devices, which
+    /// its thread waits on being dealt with before looking again
+    Feedback(usize),
+}
+
+/// A thread reading reports from an input device, which can be stopped so
+/// the device can be opened again in its place
+struct Reader {
+    /// An eventfd which the thread also waits on, written to to stop it
+    stop: Arc<OwnedFd>,
+    thread: JoinHandle<()>,
+}
+
+impl Reader {
+    /// Stops the thread and waits for it to finish, which closes its device
+    fn stop(self) {
+        let one: u64 = 1;
+        // SAFETY: the eventfd is kept open by `self.stop`, and `one` is the
+        // eight bytes an eventfd expects
+        unsafe { libc::write(self.stop.as_raw_fd(), (&one as *const u64).cast(), 8) };
+
+        let _ = self.thread.join();
+    }
+}
+
+/// Where force feedback sent to a virtual device ends up
+struct Rumble {
+    device: evdev::Device,
+    /// The effects uploaded to `device`, by the ID the game gave them
+    effects: HashMap<i16, FFEffect>,
+    /// Lets the virtual device's thread know its feedback has been dealt with
+    done: mpsc::Sender<()>,
+}
+
+/// Why remapping stopped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stopped {
+    /// The input devices went away
+    Disconnected,
+
+    /// The exit chord was held
+    ExitChord,
+
+    /// Nothing was pressed for the idle timeout
+    Idle,
+
+    /// Nothing came from the input devices for the stall timeout, even
+    /// after opening them again
+    Stalled,
+}
+
+/// Runs `pipeline` ourselves rather than handing it to `evsieve`, reading
+/// from the input devices and writing to virtual devices created through
+/// uinput, until one of the input devices goes away, the exit chord is held
+/// or nothing is pressed for `idle`. If nothing at all comes from the input
+/// devices for `stall`, they're opened again, and if that doesn't help
+/// either, it stops. `notifier` is told if it's the input devices going
+/// away, and `watch` gets to see each event coming in from them, along with
+/// the domain of the device it came from. Once all the devices are open,
+/// carries on as `user`, if given, and enters the sandbox if `sandbox`.
+pub fn run(
+    pipeline: &Pipeline,
+    notifier: Notifier,
+    idle: Option<Duration>,
+    stall: Option<Duration>,
+    user: Option<&User>,
+    sandbox: bool,
+    mut watch: impl FnMut(&str, &InputEvent),
+) -> Result<Stopped> {
+    let mut engine = Engine::new(pipeline)?;
+    let input_domains = input_domains(pipeline);
+
+    let mut devices = Vec::new();
+    let mut capabilities = Vec::new();
+
+    for input in engine.inputs() {
+        let mut device = permissions::open(&input.path)?;
+
+        capabilities.extend(device_capabilities(&device, input.domain)?);
+
+        if !pipeline.mirror {
+            device
+                .grab()
+                .with_context(|| format!("couldn't grab {:?}", input.path))?;
+        }
+
+        devices.push(device);
+    }
+
+    // Pausing lets go of the devices from here while they're being read
+    let mut descriptors: Vec<RawFd> = devices.iter().map(|device| device.as_raw_fd()).collect();
+    let mut paused = false;
+
+    let mut outputs = engine
+        .outputs()
+        .iter()
+        .zip(engine.capabilities(capabilities))
+        .map(|(output, capabilities)| create_output(output, &capabilities))
+        .collect::<Result<Vec<VirtualDevice>>>()?;
+
+    let guard = cleanup::Guard::new(
+        &descriptors,
+        &outputs.iter().map(AsRawFd::as_raw_fd).collect::<Vec<_>>(),
+    );
+
+    // Each input device gets a thread to block on, which all feed events
+    // back to this one, so we can handle them in the order they arrive
+    let (sender, receiver) = mpsc::channel();
+
+    let mut readers = devices
+        .into_iter()
+        .enumerate()
+        .map(|(index, device)| read(index, device, sender.clone()))
+        .collect::<io::Result<Vec<Reader>>>()
+        .context("couldn't start reading an input device")?;
+
+    // Games' force feedback requests arrive on the virtual devices, which we
+    // also need to write to from here, so their threads only wait for them
+    let mut rumbles = Vec::new();
+
+    for (index, (output, device)) in engine.outputs().iter().zip(&outputs).enumerate() {
+        let Some(path) = &output.rumble else {
+            rumbles.push(None);
+            continue;
+        };
+
+        let (done, waiting) = mpsc::channel();
+        rumbles.push(Some(Rumble {
+            device: permissions::open(path)?,
+            effects: HashMap::new(),
+            done,
+        }));
+
+        let descriptor = device.as_raw_fd();
+        let sender = sender.clone();
+
+        thread::spawn(move || loop {
+            let mut poll = libc::pollfd {
+                fd: descriptor,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+
+            // SAFETY: the virtual devices are kept until we're done
+            if unsafe { libc::poll(&mut poll, 1, -1) } < 0 {
+                let error = std::io::Error::last_os_error();
+                if error.kind() == ErrorKind::Interrupted {
+                    continue;
+                }
+
+                let _ = sender.send(Err(error));
+                return;
+            }
+
+            if sender.send(Ok(Message::Feedback(index))).is_err() || waiting.recv().is_err() {
+                return;
+            }
+        });
+    }
+
+    // Opening the input devices again needs a way to feed their events back
+    let spare = stall.map(|_| sender.clone());
+    drop(sender);
+
+    if let Some(user) = user {
+        user.switch()?;
+    }
+    if sandbox {
+        sandbox::enter()?;
+    }
+
+    // Only buttons count, since the accelerometer never stays still
+    let mut active = Instant::now();
+
+    // Anything counts for noticing a stall, since the accelerometer never
+    // stays still
+    let mut heard = Instant::now();
+    let mut reopened = false;
+
+    loop {
+        if idle.is_some_and(|idle| active.elapsed() >= idle) {
+            info!("Nothing's been pressed in a while, so we're done here");
+            return Ok(Stopped::Idle);
+        }
+
+        if let (Some(stall), Some(spare)) = (stall, &spare) {
+            if heard.elapsed() >= stall {
+                if reopened {
+                    warn!("Still nothing from the Wiimote, so we're done here");
+                    notifier.send("Guitar stopped responding");
+                    return Ok(Stopped::Stalled);
+                }
+
+                warn!(
+                    "Nothing's come from the Wiimote in {} seconds, so opening its devices again",
+                    stall.as_secs()
+                );
+                let result = reopen(
+                    &engine,
+                    &mut readers,
+                    &mut descriptors,
+                    paused || pipeline.mirror,
+                    spare,
+                );
+                guard.inputs(&descriptors);
+
+                // The old devices are gone by now, so there's nothing left
+                // to read from
+                if let Err(error) = result {
+                    warn!("Couldn't open the Wiimote's devices again: {:#}", error);
+                    notifier.send("Guitar stopped responding");
+                    return Ok(Stopped::Stalled);
+                }
+
+                reopened = true;
+                heard = Instant::now();
+            }
+        }
+
+        let timeout = engine
+            .until_due(SystemTime::now())
+            .map_or(TICK, |due| due.min(TICK));
+        let timeout = match idle {
+            Some(idle) => timeout.min(idle.saturating_sub(active.elapsed())),
+            None => timeout,
+        };
+        let timeout = match stall {
+            Some(stall) => timeout.min(stall.saturating_sub(heard.elapsed())),
+            None => timeout,
+        };
+
+        let (index, report) = match receiver.recv_timeout(timeout) {
+            Ok(Ok(Message::Report(index, report))) => (index, report),
+            Ok(Ok(Message::Feedback(index))) => {
+                if let Some(rumble) = &mut rumbles[index] {
+                    feedback(&mut outputs[index], rumble)?;
+                    let _ = rumble.done.send(());
+                }
+
+                continue;
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                emit(&mut outputs, engine.tick(SystemTime::now()))?;
+                pause(&engine, &mut paused, &descriptors, pipeline.mirror)?;
+
+                if engine.exiting() {
+                    break;
+                }
+
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+            // This is what we get when the Wiimote disconnects
+            Ok(Err(error)) if error.raw_os_error() == Some(libc::ENODEV) => {
+                info!("An input device went away, so we're done here");
+                notifier.send("Guitar disconnected");
+                return Ok(Stopped::Disconnected);
+            }
+            Ok(Err(error)) => return Err(error).context("couldn't read from an input device"),
+        };
+
+        if reopened {
+            info!("The Wiimote's going again");
+        }
+        heard = Instant::now();
+        reopened = false;
+
+        let mut produced = Vec::new();
+
+        for event in report {
+            let event_type = event.event_type().0;
+            if ![EV_KEY, EV_ABS, EV_REL].contains(&event_type) {
+                continue;
+            }
+
+            if event_type == EV_KEY {
+                active = Instant::now();
+            }
+
+            watch(input_domains[index], &event);
+
+            produced.extend(engine.process(
+                index,
+                event_type,
+                event.code(),
+                event.value(),
+                event.timestamp(),
+            ));
+        }
+
+        emit(&mut outputs, produced)?;
+        pause(&engine, &mut paused, &descriptors, pipeline.mirror)?;
+
+        if engine.exiting() {
+            break;
+        }
+    }
+
+    if engine.exiting() {
+        info!("The exit chord was held, so we're done here");
+        return Ok(Stopped::ExitChord);
+    }
+
+    Ok(Stopped::Disconnected)
+}
+
+/// Runs the events in `recording` through `pipeline` the same way `run`
+/// would have, with time passing between them as it did when they were
+/// recorded. What comes out is printed, or if `emit` is set, written to
+/// virtual devices at the pace it was recorded at. If the recording has
+/// roadii's own output in it too, it's an error for the replay to differ.
+pub fn replay(pipeline: &Pipeline, recording: &Recording, emit: bool) -> Result<()> {
+    let mut engine = Engine::new(pipeline)?;
+
+    let input_domains = input_domains(pipeline);
+    let output_domains = output_domains(pipeline);
+
+    // Which of the engine's inputs each recorded device feeds, if any
+    let inputs: Vec<Option<usize>> = recording
+        .devices
+        .iter()
+        .map(|device| match device.output {
+            true => None,
+            false => input_domains
+                .iter()
+                .position(|&domain| domain == device.domain),
+        })
+        .collect();
+
+    let mut outputs = match emit {
+        true => Some(
+            engine
+                .outputs()
+                .iter()
+                .zip(engine.capabilities(replay_capabilities(recording, &inputs, &engine)))
+                .map(|(output, capabilities)| create_output(output, &capabilities))
+                .collect::<Result<Vec<VirtualDevice>>>()?,
+        ),
+        false => None,
+    };
+
+    let start = SystemTime::now();
+    let started = Instant::now();
+    let mut now = Duration::ZERO;
+
+    // What came out, to compare with what the recording says did
+    let mut produced = Vec::new();
+    let mut recorded = Vec::new();
+
+    for event in &recording.events {
+        // Let anything waiting for time to pass act, as often as it would
+        // have been able to live
+        loop {
+            let step = engine
+                .until_due(start + now)
+                .map_or(TICK, |due| due.min(TICK))
+                .max(REPLAY_STEP);
+
+            if now + step > event.time || engine.exiting() {
+                break;
+            }
+
+            now += step;
+            let events = engine.tick(start + now);
+            play(
+                now,
+                events,
+                &output_domains,
+                &mut outputs,
+                started,
+                &mut produced,
+            )?;
+        }
+
+        if engine.exiting() {
+            println!("The exit chord was held, so the replay stops here");
+            break;
+        }
+
+        now = now.max(event.time);
+
+        let event_type = event.event.event_type().0;
+        if ![EV_KEY, EV_ABS, EV_REL].contains(&event_type) {
+            continue;
+        }
+
+        let Some(input) = inputs[event.device] else {
+            if recording.devices[event.device].output
+                && !(event_type == EV_REL && event.event.value() == 0)
+            {
+                recorded.push(describe(
+                    &recording.devices[event.device].domain,
+                    event_type,
+                    event.event.code(),
+                    event.event.value(),
+                ));
+            }
+
+            continue;
+        };
+
+        let events = engine.process(
+            input,
+            event_type,
+            event.event.code(),
+            event.event.value(),
+            start + now,
+        );
+        play(
+            now,
+            events,
+            &output_domains,
+            &mut outputs,
+            started,
+            &mut produced,
+        )?;
+    }
+
+    if !recording.devices.iter().any(|device| device.output) {
+        return Ok(());
+    }
+
+    // The timing can't match exactly, but what was produced should
+    match produced
+        .iter()
+        .zip(&recorded)
+        .position(|(produced, recorded)| produced != recorded)
+        .or((produced.len() != recorded.len()).then(|| produced.len().min(recorded.len())))
+    {
+        None => {
+            println!(
+                "The replay produced the same {} events as the recording",
+                produced.len()
+            );
+            Ok(())
+        }
+        Some(index) => bail!(
+            "the replay differs from the recording after {} events, producing {} where the recording has {}",
+            index,
+            produced.get(index).map_or("nothing", String::as_str),
+            recorded.get(index).map_or("nothing", String::as_str),
+        ),
+    }
+}
+
+/// What `pipeline` produces straight away when the button `key` is pressed,
+/// written the same way `--observe` does
+pub fn expected(pipeline: &Pipeline, key: &Key) -> Result<Vec<String>> {
+    let mut engine = Engine::new(pipeline)?;
+
+    let domain = key.domain.as_deref().unwrap_or_default();
+    let input = pipeline
+        .steps
+        .iter()
+        .filter(|step| matches!(step, Step::Input { .. }))
+        .position(|step| matches!(step, Step::Input { domain: other, .. } if other == domain))
+        .ok_or_else(|| anyhow!("nothing is read from @{}", domain))?;
+    let code = key
+        .code_number()
+        .ok_or_else(|| anyhow!("{} isn't an event we know about", key))?;
+
+    let domains = output_domains(pipeline);
+
+    Ok(engine
+        .process(input, EV_KEY, code, 1, SystemTime::now())
+        .into_iter()
+        .map(|(output, event)| {
+            describe(&domains[output], event.event_type, event.code, event.value)
+        })
+        .collect())
+}
+
+/// The domain each of `pipeline`'s inputs tags its events with
+fn input_domains(pipeline: &Pipeline) -> Vec<&str> {
+    pipeline
+        .steps
+        .iter()
+        .filter_map(|step| match step {
+            Step::Input { domain, .. } => Some(domain.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The domain each of `pipeline`'s outputs takes its events from
+fn output_domains(pipeline: &Pipeline) -> Vec<String> {
+    pipeline
+        .steps
+        .iter()
+        .filter_map(|step| match step {
+            Step::Output { keys, .. } => Some(
+                keys.first()
+                    .and_then(|key| key.domain.clone())
+                    .unwrap_or_default(),
+            ),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Passes on the events the engine produced during a replay, `time` into
+/// it, printing them or writing them to `outputs` once it's time, and
+/// keeping a note of them in `produced`
+fn play(
+    time: Duration,
+    events: Vec<(usize, Event)>,
+    domains: &[String],
+    outputs: &mut Option<Vec<VirtualDevice>>,
+    started: Instant,
+    produced: &mut Vec<String>,
+) -> Result<()> {
+    // The same as `emit` skips
+    let events: Vec<(usize, Event)> = events
+        .into_iter()
+        .filter(|(_, event)| !(event.event_type == EV_REL && event.value == 0))
+        .collect();
+
+    if events.is_empty() {
+        return Ok(());
+    }
+
+    for (output, event) in &events {
+        let description = describe(&domains[*output], event.event_type, event.code, event.value);
+
+        if outputs.is_none() {
+            println!("{:>10.3}s  {}", time.as_secs_f64(), description);
+        }
+
+        produced.push(description);
+    }
+
+    if let Some(outputs) = outputs {
+        thread::sleep((started + time).saturating_duration_since(Instant::now()));
+        emit(outputs, events)?;
+    }
+
+    Ok(())
+}
+
+/// An event on the output `domain`, written the same way `--observe` does
+fn describe(domain: &str, event_type: u16, code: u16, value: i32) -> String {
+    match Key::from_event(&InputEvent::new(event_type, code, value), domain) {
+        Some(key) => key.to_string(),
+        None => format!(
+            "type {} code {} value {} @{}",
+            event_type, code, value, domain
+        ),
+    }
+}
+
+/// What the inputs could produce, going by what they did in `recording`,
+/// since the devices themselves might not be around. Axes are taken to only
+/// go as far as they were seen to.
+fn replay_capabilities(
+    recording: &Recording,
+    inputs: &[Option<usize>],
+    engine: &Engine,
+) -> Vec<Capability> {
+    let mut seen: HashMap<(usize, u16, u16), (i32, i32)> = HashMap::new();
+
+    for event in &recording.events {
+        let Some(input) = inputs[event.device] else {
+            continue;
+        };
+
+        let value = event.event.value();
+        let range = seen
+            .entry((input, event.event.event_type().0, event.event.code()))
+            .or_insert((value, value));
+        *range = (range.0.min(value), range.1.max(value));
+    }
+
+    seen.into_iter()
+        .filter(|((_, event_type, _), _)| [EV_KEY, EV_ABS, EV_REL].contains(event_type))
+        .map(|((input, event_type, code), (min, max))| Capability {
+            event_type,
+            code,
+            domain: engine.inputs()[input].domain,
+            axis: (event_type == EV_ABS).then_some(Axis {
+                min,
+                max,
+                fuzz: 0,
+                flat: 0,
+                resolution: 0,
+            }),
+        })
+        .collect()
+}
+
+/// Starts a thread reading reports from the input device `index`, and
+/// passing them on to `sender`, until it's stopped
+fn read(
+    index: usize,
+    mut device: evdev::Device,
+    sender: mpsc::Sender<io::Result<Message>>,
+) -> io::Result<Reader> {
+    // SAFETY: eventfd has no preconditions, and we own what it returns
+    let stop = match unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) } {
+        descriptor if descriptor < 0 => return Err(io::Error::last_os_error()),
+        descriptor => Arc::new(unsafe { OwnedFd::from_raw_fd(descriptor) }),
+    };
+
+    let waker = stop.clone();
+    let thread = thread::Builder::new().spawn(move || {
+        // Whatever's come in since the last SYN_REPORT, since games
+        // expect everything in a report to change at once
+        let mut report = Vec::new();
+
+        loop {
+            let mut polls = [
+                libc::pollfd {
+                    fd: device.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+                libc::pollfd {
+                    fd: waker.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+            ];
+
+            // SAFETY: both descriptors are kept open by this thread
+            if unsafe { libc::poll(polls.as_mut_ptr(), 2, -1) } < 0 {
+                let error = io::Error::last_os_error();
+                if error.kind() == ErrorKind::Interrupted {
+                    continue;
+                }
+
+                let _ = sender.send(Err(error));
+                return;
+            }
+
+            if polls[1].revents != 0 {
+                return;
+            }
+
+            match device.fetch_events() {
+                Ok(events) => {
+                    for event in events {
+                        if event.event_type() != EventType::SYNCHRONIZATION
+                            || event.code() != SynchronizationCode::SYN_REPORT.0
+                        {
+                            report.push(event);
+                            continue;
+                        }
+
+                        let report = mem::take(&mut report);
+                        if sender.send(Ok(Message::Report(index, report))).is_err() {
+                            return;
+                        }
+                    }
+                }
+                // A signal landing mid-read isn't the device's fault
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => {
+                    let _ = sender.send(Err(error));
+                    return;
+                }
+            }
+        }
+    })?;
+
+    Ok(Reader { stop, thread })
+}
+
+/// Opens the engine's input devices again in place of the ones `readers`
+/// are reading from, which are stopped and closed first so nothing is read
+/// twice, and reads from them as before. Unless `paused`, they're grabbed.
+/// hid-wiimote asks the Wiimote to start sending again each time its
+/// devices are opened, which can bring a stalled one back.
+fn reopen(
+    engine: &Engine,
+    readers: &mut Vec<Reader>,
+    descriptors: &mut [RawFd],
+    paused: bool,
+    sender: &mpsc::Sender<io::Result<Message>>,
+) -> Result<()> {
+    for reader in readers.drain(..) {
+        reader.stop();
+    }
+
+    // Nothing's left open at the old descriptors, which could be reused
+    descriptors.fill(-1);
+
+    for (index, input) in engine.inputs().iter().enumerate() {
+        let mut device = permissions::open(&input.path)?;
+
+        if !paused {
+            device
+                .grab()
+                .with_context(|| format!("couldn't grab {:?}", input.path))?;
+        }
+
+        descriptors[index] = device.as_raw_fd();
+        readers.push(
+            read(index, device, sender.clone())
+                .context("couldn't start reading an input device")?,
+        );
+    }
+
+    Ok(())
+}
+
+/// Lets go of or grabs the input devices again if the engine has been
+/// paused or resumed since we last looked, unless they're being mirrored
+fn pause(engine: &Engine, paused: &mut bool, descriptors: &[RawFd], mirror: bool) -> Result<()> {
+    if engine.paused() == *paused {
+        return Ok(());
+    }
+
+    *paused = engine.paused();
+
+    // Mirrored devices were never grabbed, so there's nothing to let go of
+    if !mirror {
+        for &descriptor in descriptors {
+            // SAFETY: the devices are kept open by their threads until we're
+            // done
+            if unsafe { libc::ioctl(descriptor, EVIOCGRAB as _, !*paused as libc::c_int) } < 0 {
+                return Err(std::io::Error::last_os_error()).context(match *paused {
+                    true => "couldn't let go of an input device",
+                    false => "couldn't grab an input device again",
+                });
+            }
+        }
+    }
+
+    match *paused {
+        true => info!("Paused, the Wiimote is all yours until the pause chord is held again"),
+        false => info!("Resumed"),
+    }
+
+    Ok(())
+}
+
+/// Writes events the engine produced to the virtual devices they're for,
+/// in as few reports as possible, so games see them change together. The
+/// same control changing twice, like a tap, starts a new report, so it isn't
+/// lost.
+fn emit(outputs: &mut [VirtualDevice], produced: Vec<(usize, Event)>) -> Result<()> {
+    let mut reports: Vec<Vec<InputEvent>> = vec![Vec::new(); outputs.len()];
+
+    for (output, event) in produced {
+        // There's no point telling anyone about relative motion of nothing
+        if event.event_type == EV_REL && event.value == 0 {
+            continue;
+        }
+
+        let report = &mut reports[output];
+        if report.iter().any(|pending| {
+            pending.event_type().0 == event.event_type && pending.code() == event.code
+        }) {
+            outputs[output]
+                .emit(&mem::take(report))
+                .context("couldn't write to a virtual device")?;
+        }
+
+        report.push(InputEvent::new(event.event_type, event.code, event.value));
+    }
+
+    for (output, report) in outputs.iter_mut().zip(reports) {
+        if !report.is_empty() {
+            output
+                .emit(&report)
+                .context("couldn't write to a virtual device")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Passes on the force feedback games have sent to `output` to the device
+/// in `rumble`, uploading, erasing and playing the same effects there
+fn feedback(output: &mut VirtualDevice, rumble: &mut Rumble) -> Result<()> {
+    let events: Vec<InputEvent> = output
+        .fetch_events()
+        .context("couldn't read force feedback from a virtual device")?
+        .collect();
+
+    for event in events {
+        match event.destructure() {
+            EventSummary::UInput(event, UInputCode::UI_FF_UPLOAD, _) => {
+                let mut upload = output
+                    .process_ff_upload(event)
+                    .context("couldn't take a force feedback effect")?;
+                let id = upload.effect_id();
+
+                let result = match rumble.effects.get_mut(&id) {
+                    Some(effect) => effect.update(upload.effect()),
+                    None => rumble
+                        .device
+                        .upload_ff_effect(upload.effect())
+                        .map(|effect| {
+                            rumble.effects.insert(id, effect);
+                        }),
+                };
+
+                // The game finds out if it didn't work once this is dropped
+                if let Err(error) = result {
+                    upload.set_retval(-error.raw_os_error().unwrap_or(libc::EINVAL));
+                }
+            }
+            EventSummary::UInput(event, UInputCode::UI_FF_ERASE, _) => {
+                let erase = output
+                    .process_ff_erase(event)
+                    .context("couldn't take a force feedback effect")?;
+                rumble.effects.remove(&(erase.effect_id() as i16));
+            }
+            EventSummary::ForceFeedback(_, code, value) => {
+                if let Some(effect) = rumble.effects.get_mut(&(code.0 as i16)) {
+                    match value {
+                        0 => effect.stop(),
+                        count => effect.play(count),
+                    }
+                    .context("couldn't rumble an input device")?;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Everything `device` can produce, tagged with `domain`
+fn device_capabilities(device: &evdev::Device, domain: usize) -> Result<Vec<Capability>> {
+    let mut capabilities = Vec::new();
+
+    if let Some(keys) = device.supported_keys() {
+        capabilities.extend(keys.iter().map(|key| Capability {
+            event_type: EV_KEY,
+            code: key.code(),
+            domain,
+            axis: None,
+        }));
+    }
+
+    if let Some(axes) = device.supported_relative_axes() {
+        capabilities.extend(axes.iter().map(|axis| Capability {
+            event_type: EV_REL,
+            code: axis.0,
+            domain,
+            axis: None,
+        }));
+    }
+
+    capabilities.extend(
+        device
+            .get_absinfo()
+            .context("couldn't read the axes of an input device")?
+            .map(|(axis, info)| Capability {
+                event_type: EV_ABS,
+                code: axis.0,
+                domain,
+                axis: Some(Axis {
+                    min: info.minimum(),
+                    max: info.maximum(),
+                    fuzz: info.fuzz(),
+                    flat: info.flat(),
+                    resolution: info.resolution(),
+                }),
+            }),
+    );
+
+    Ok(capabilities)
+}
+
+/// Creates the virtual device for `output`, able to produce `capabilities`
+fn create_output(output: &Output, capabilities: &[Capability]) -> Result<VirtualDevice> {
+    if capabilities.is_empty() {
+        bail!(
+            "nothing is mapped to {:?}, so it can't be created",
+            output.name
+        );
+    }
+
+    let id = match output.device_id {
+        Some((vendor, product)) => InputId::new(BusType::BUS_USB, vendor, product, 0),
+        None => InputId::new(BusType::BUS_VIRTUAL, 0, 0, 0),
+    };
+
+    // Saying it's ours lets another roadii replacing it stop only us
+    let mut builder = permissions::uinput()?
+        .name(&output.name)
+        .input_id(id)
+        .with_phys(&outputs::phys())?;
+
+    let mut keys = AttributeSet::<KeyCode>::new();
+    let mut relative_axes = AttributeSet::<RelativeAxisCode>::new();
+
+    for capability in capabilities {
+        match capability.event_type {
+            EV_KEY => keys.insert(KeyCode::new(capability.code)),
+            EV_REL => relative_axes.insert(RelativeAxisCode(capability.code)),
+            EV_ABS => {
+                let axis = capability.axis.unwrap_or(Axis {
+                    min: 0,
+                    max: 1,
+                    fuzz: 0,
+                    flat: 0,
+                    resolution: 0,
+                });
+
+                builder = builder.with_absolute_axis(&UinputAbsSetup::new(
+                    AbsoluteAxisCode(capability.code),
+                    AbsInfo::new(
+                        0,
+                        axis.min,
+                        // uinput doesn't like axes which can't move at all
+                        axis.max.max(axis.min + 1),
+                        axis.fuzz,
+                        axis.flat,
+                        axis.resolution,
+                    ),
+                ))?;
+            }
+            _ => {}
+        }
+    }
+
+    if keys.iter().next().is_some() {
+        builder = builder.with_keys(&keys)?;
+    }
+
+    if relative_axes.iter().next().is_some() {
+        builder = builder.with_relative_axes(&relative_axes)?;
+    }
+
+    if output.rumble.is_some() {
+        let mut effects = AttributeSet::<FFEffectCode>::new();
+        effects.insert(FFEffectCode::FF_RUMBLE);
+
+        builder = builder.with_ff(&effects)?.with_ff_effects_max(FF_EFFECTS);
+    }
+
+    builder
+        .build()
+        .with_context(|| format!("couldn't create {:?}", output.name))
+}
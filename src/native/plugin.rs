@@ -0,0 +1,377 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+use wasmi::{
+    Caller, Config, Extern, Instance, Linker, Module, Store, StoreLimits, StoreLimitsBuilder,
+    TypedFunc,
+};
+
+use super::engine::{Axis, Capability, Event, EV_ABS, EV_KEY, EV_REL};
+
+/// The version of the plugin interface roadii speaks, which a plugin's
+/// `roadii_abi_version` export has to return
+pub const ABI_VERSION: i32 = 1;
+
+/// How much fuel, roughly one per instruction, a plugin gets for each call,
+/// so one which never finishes can't hold up the guitar
+const FUEL: u64 = 100_000;
+
+/// How much memory a plugin can have
+const MAX_MEMORY: usize = 16 * 1024 * 1024;
+
+/// What the functions roadii gives plugins can get at
+struct Host {
+    limits: StoreLimits,
+    /// The name of each domain, by number
+    domains: Vec<String>,
+    /// What the plugin has passed on while handling an event, as the event
+    /// type, code, value and domain
+    emitted: Vec<(u16, u16, i32, usize)>,
+    /// What the plugin has said it can produce while being set up
+    produced: Vec<Capability>,
+    /// Whether the plugin is being set up, when it can say what it produces
+    initialising: bool,
+}
+
+/// A WebAssembly module, speaking version `ABI_VERSION` of the plugin
+/// interface, which every event is passed to. Whatever it emits while
+/// handling an event is passed on instead. It has nothing but the functions
+/// roadii gives it, so it can't get at files, the network or anything else,
+/// and it keeps its memory, with whatever state it likes, from one event to
+/// the next.
+///
+/// Plugins export:
+///
+/// - `roadii_abi_version() -> i32`, returning `ABI_VERSION`
+/// - `on_event(type, code, value, previous, domain)`, which is given each
+///   event, all as `i32`s, with the type and code numbered as they are in
+///   Linux
+/// - `init()`, if they like, which is called once roadii knows every domain
+/// - `memory`, if they pass roadii strings
+///
+/// And can import from `roadii`:
+///
+/// - `emit(type, code, value, domain)`, while handling an event, to pass an
+///   event on, whose previous value is whatever the plugin last emitted for
+///   it
+/// - `domain(name, length) -> i32`, the number of the domain with the UTF-8
+///   name in memory at `name`, or -1 if nothing reads or writes it
+/// - `produce(type, code, domain, min, max)`, while being set up, to say it
+///   can emit an event it isn't given, with the range of axes
+/// - `log(message, length)`, to write out the UTF-8 message in memory
+pub struct Plugin {
+    path: PathBuf,
+    store: Store<Host>,
+    instance: Instance,
+    on_event: TypedFunc<(i32, i32, i32, i32, i32), ()>,
+    /// The last value of each event the plugin has passed on, by domain,
+    /// type and code, for the previous value of the next
+    previous: HashMap<(usize, u16, u16), i32>,
+    /// Whether the plugin has gone wrong yet, to only warn about it once
+    failed: bool,
+}
+
+impl fmt::Debug for Plugin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Plugin").field("path", &self.path).finish()
+    }
+}
+
+impl Plugin {
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path)
+            .with_context(|| format!("couldn't read the plugin {}", path.display()))?;
+
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = wasmi::Engine::new(&config);
+
+        let module = Module::new(&engine, bytes)
+            .map_err(|error| anyhow!("{}", error))
+            .with_context(|| format!("couldn't load the plugin {}", path.display()))?;
+
+        let mut store = Store::new(
+            &engine,
+            Host {
+                limits: StoreLimitsBuilder::new()
+                    .memory_size(MAX_MEMORY)
+                    .instances(1)
+                    .build(),
+                domains: Vec::new(),
+                emitted: Vec::new(),
+                produced: Vec::new(),
+                initialising: false,
+            },
+        );
+        store.limiter(|host| &mut host.limits);
+        store.set_fuel(FUEL).map_err(|error| anyhow!("{}", error))?;
+
+        let instance = linker(&engine)?
+            .instantiate_and_start(&mut store, &module)
+            .map_err(|error| anyhow!("{}", error))
+            .with_context(|| format!("couldn't start the plugin {}", path.display()))?;
+
+        let version = instance
+            .get_typed_func::<(), i32>(&store, "roadii_abi_version")
+            .and_then(|function| function.call(&mut store, ()))
+            .map_err(|error| anyhow!("{}", error))
+            .with_context(|| {
+                format!(
+                    "the plugin {} doesn't say which version of the plugin interface it speaks",
+                    path.display()
+                )
+            })?;
+        if version != ABI_VERSION {
+            bail!(
+                "the plugin {} speaks version {} of the plugin interface, but roadii speaks version {}",
+                path.display(),
+                version,
+                ABI_VERSION
+            );
+        }
+
+        let on_event = instance
+            .get_typed_func(&store, "on_event")
+            .map_err(|error| anyhow!("{}", error))
+            .with_context(|| {
+                format!(
+                    "the plugin {} should export `on_event(type, code, value, previous, domain)`",
+                    path.display()
+                )
+            })?;
+
+        Ok(Plugin {
+            path: path.to_owned(),
+            store,
+            instance,
+            on_event,
+            previous: HashMap::new(),
+            failed: false,
+        })
+    }
+
+    /// Sets the plugin up, now that every domain in `domains` is known,
+    /// returning what it says it can produce besides the events it's given
+    pub fn init(&mut self, domains: &[String]) -> Result<Vec<Capability>> {
+        self.store.data_mut().domains = domains.to_vec();
+
+        let Ok(init) = self.instance.get_typed_func::<(), ()>(&self.store, "init") else {
+            return Ok(Vec::new());
+        };
+
+        self.store.data_mut().initialising = true;
+        let result = self
+            .store
+            .set_fuel(FUEL)
+            .and_then(|()| init.call(&mut self.store, ()));
+        self.store.data_mut().initialising = false;
+
+        result
+            .map_err(|error| anyhow!("{}", error))
+            .with_context(|| format!("couldn't set up the plugin {}", self.path.display()))?;
+
+        Ok(std::mem::take(&mut self.store.data_mut().produced))
+    }
+
+    /// Runs `event` through the plugin, returning the events it passes on.
+    /// If the plugin goes wrong, `event` is passed on as it is.
+    pub fn process(&mut self, event: Event) -> Vec<Event> {
+        match self.call(&event) {
+            Ok(events) => events,
+            Err(error) => {
+                match self.failed {
+                    false => warn!(
+                        "The plugin {} went wrong, so events are passed on as they are when it does: {:#}",
+                        self.path.display(),
+                        error
+                    ),
+                    true => debug!("The plugin {} went wrong: {:#}", self.path.display(), error),
+                }
+                self.failed = true;
+
+                self.previous
+                    .insert((event.domain, event.event_type, event.code), event.value);
+                vec![event]
+            }
+        }
+    }
+
+    fn call(&mut self, event: &Event) -> Result<Vec<Event>> {
+        self.store.data_mut().emitted.clear();
+        self.store
+            .set_fuel(FUEL)
+            .map_err(|error| anyhow!("{}", error))?;
+
+        self.on_event
+            .call(
+                &mut self.store,
+                (
+                    event.event_type as i32,
+                    event.code as i32,
+                    event.value,
+                    event.previous,
+                    event.domain as i32,
+                ),
+            )
+            .map_err(|error| anyhow!("{}", error))?;
+
+        let previous = &mut self.previous;
+
+        Ok(self
+            .store
+            .data_mut()
+            .emitted
+            .drain(..)
+            .map(|(event_type, code, value, domain)| Event {
+                event_type,
+                code,
+                value,
+                previous: previous
+                    .insert((domain, event_type, code), value)
+                    .unwrap_or(value),
+                domain,
+            })
+            .collect())
+    }
+}
+
+/// The functions plugins can import from roadii
+fn linker(engine: &wasmi::Engine) -> Result<Linker<Host>> {
+    let mut linker = Linker::new(engine);
+
+    linker
+        .func_wrap(
+            "roadii",
+            "emit",
+            |mut caller: Caller<'_, Host>,
+             event_type: i32,
+             code: i32,
+             value: i32,
+             domain: i32|
+             -> Result<(), wasmi::Error> {
+                if caller.data().initialising {
+                    return Err(wasmi::Error::new("emit can only be called from on_event"));
+                }
+
+                let (event_type, code, domain) = check(caller.data(), event_type, code, domain)?;
+                caller
+                    .data_mut()
+                    .emitted
+                    .push((event_type, code, value, domain));
+
+                Ok(())
+            },
+        )
+        .and_then(|linker| {
+            linker.func_wrap(
+                "roadii",
+                "domain",
+                |caller: Caller<'_, Host>, name: i32, length: i32| -> Result<i32, wasmi::Error> {
+                    let name = string(&caller, name, length)?;
+
+                    Ok(caller
+                        .data()
+                        .domains
+                        .iter()
+                        .position(|domain| *domain == name)
+                        .map_or(-1, |domain| domain as i32))
+                },
+            )
+        })
+        .and_then(|linker| {
+            linker.func_wrap(
+                "roadii",
+                "produce",
+                |mut caller: Caller<'_, Host>,
+                 event_type: i32,
+                 code: i32,
+                 domain: i32,
+                 min: i32,
+                 max: i32|
+                 -> Result<(), wasmi::Error> {
+                    if !caller.data().initialising {
+                        return Err(wasmi::Error::new("produce can only be called from init"));
+                    }
+
+                    let (event_type, code, domain) =
+                        check(caller.data(), event_type, code, domain)?;
+                    caller.data_mut().produced.push(Capability {
+                        event_type,
+                        code,
+                        domain,
+                        axis: (event_type == EV_ABS).then_some(Axis {
+                            min: min.min(max),
+                            max: min.max(max),
+                            fuzz: 0,
+                            flat: 0,
+                            resolution: 0,
+                        }),
+                    });
+
+                    Ok(())
+                },
+            )
+        })
+        .and_then(|linker| {
+            linker.func_wrap(
+                "roadii",
+                "log",
+                |caller: Caller<'_, Host>, message: i32, length: i32| -> Result<(), wasmi::Error> {
+                    info!("Plugin: {}", string(&caller, message, length)?);
+                    Ok(())
+                },
+            )
+        })
+        .map_err(|error| anyhow!("{}", error))?;
+
+    Ok(linker)
+}
+
+/// Makes sure a plugin has given an event type roadii passes on, a code and
+/// a domain which exists
+fn check(
+    host: &Host,
+    event_type: i32,
+    code: i32,
+    domain: i32,
+) -> Result<(u16, u16, usize), wasmi::Error> {
+    let event_type = match u16::try_from(event_type) {
+        Ok(event_type @ (EV_KEY | EV_REL | EV_ABS)) => event_type,
+        _ => {
+            return Err(wasmi::Error::new(format!(
+                "{} isn't a key, relative or absolute event type",
+                event_type
+            )))
+        }
+    };
+    let code = u16::try_from(code)
+        .map_err(|_| wasmi::Error::new(format!("{} isn't an event code", code)))?;
+    let domain = usize::try_from(domain)
+        .ok()
+        .filter(|&domain| domain < host.domains.len())
+        .ok_or_else(|| wasmi::Error::new(format!("{} isn't a domain", domain)))?;
+
+    Ok((event_type, code, domain))
+}
+
+/// Reads the UTF-8 string a plugin has put in its memory at `at`
+fn string(caller: &Caller<'_, Host>, at: i32, length: i32) -> Result<String, wasmi::Error> {
+    let Some(Extern::Memory(memory)) = caller.get_export("memory") else {
+        return Err(wasmi::Error::new("the plugin should export its memory"));
+    };
+
+    let length = usize::try_from(length)
+        .ok()
+        .filter(|&length| length <= MAX_MEMORY)
+        .ok_or_else(|| wasmi::Error::new("that string can't be in the plugin's memory"))?;
+
+    let mut bytes = vec![0; length];
+    memory
+        .read(caller, at as u32 as usize, &mut bytes)
+        .map_err(|_| wasmi::Error::new("that string isn't in the plugin's memory"))?;
+
+    String::from_utf8(bytes).map_err(|_| wasmi::Error::new("strings should be UTF-8"))
+}
@@ -0,0 +1,263 @@
+use anyhow::{anyhow, bail, Context, Result};
+use evdev::InputEvent;
+use rhai::{Array, Dynamic, Map, Scope, AST};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+use super::engine::Event;
+use crate::key::{EventType, Key};
+
+/// How many operations a script gets for each event, so one which never
+/// finishes can't hold up the guitar
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// How deeply a script's functions can call each other
+const MAX_CALL_LEVELS: usize = 32;
+
+/// How big the strings, arrays and maps a script makes can get, so its
+/// state can't grow without end
+const MAX_STRING_SIZE: usize = 4096;
+const MAX_COLLECTION_SIZE: usize = 1024;
+
+/// A Rhai script with an `on_event(event, state)` function, which every
+/// event is passed to, along with a map it can keep whatever it likes in.
+/// It returns the events to pass on instead, as an array, a single event or
+/// nothing at all. Events are maps like `#{ type: "btn", code: "south",
+/// value: 1, previous: 0, domain: "guitar" }`.
+///
+/// Scripts can't read or write files, run programs, import modules or use
+/// `eval`, and only get so long to handle each event.
+pub struct Script {
+    path: PathBuf,
+    engine: rhai::Engine,
+    ast: AST,
+    /// The map kept between calls, shared so what the script puts in it
+    /// stays there
+    state: Dynamic,
+    /// Whether the script has gone wrong yet, to only warn about it once
+    failed: bool,
+}
+
+impl fmt::Debug for Script {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Script")
+            .field("path", &self.path)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("couldn't read the script {}", path.display()))?;
+
+        let mut engine = rhai::Engine::new();
+        engine
+            .set_max_operations(MAX_OPERATIONS)
+            .set_max_call_levels(MAX_CALL_LEVELS)
+            .set_max_string_size(MAX_STRING_SIZE)
+            .set_max_array_size(MAX_COLLECTION_SIZE)
+            .set_max_map_size(MAX_COLLECTION_SIZE)
+            .disable_symbol("eval")
+            .on_print(|text| info!("Script: {}", text))
+            .on_debug(|text, _, position| debug!("Script at {}: {}", position, text));
+
+        let ast = engine
+            .compile(source)
+            .with_context(|| format!("couldn't load the script {}", path.display()))?;
+
+        if !ast
+            .iter_functions()
+            .any(|function| function.name == "on_event" && function.params.len() == 2)
+        {
+            bail!(
+                "the script {} should have an `on_event(event, state)` function",
+                path.display()
+            );
+        }
+
+        Ok(Script {
+            path: path.to_owned(),
+            engine,
+            ast,
+            state: Dynamic::from_map(Map::new()).into_shared(),
+            failed: false,
+        })
+    }
+
+    /// The events the script can produce other than those it's given, from
+    /// its `produces()` function if it has one, which returns them as keys
+    /// like `"btn:south@wiitar"`, with the range of any axes, like
+    /// `"abs:ry:-100~100@wiitar"`
+    pub fn produces(&self) -> Result<Vec<Key>> {
+        if !self
+            .ast
+            .iter_functions()
+            .any(|function| function.name == "produces" && function.params.is_empty())
+        {
+            return Ok(Vec::new());
+        }
+
+        let keys: Array = self
+            .engine
+            .call_fn(&mut Scope::new(), &self.ast, "produces", ())
+            .map_err(|error| anyhow!("{}", error))
+            .with_context(|| format!("in the script {}", self.path.display()))?;
+
+        keys.into_iter()
+            .map(|key| {
+                let key: Key = key
+                    .into_string()
+                    .map_err(|_| anyhow!("produces() should return keys as strings"))?
+                    .parse()?;
+
+                if key.code.is_none() || key.domain.is_none() {
+                    bail!(
+                        "{} should say which event it is and which domain it goes to",
+                        key
+                    );
+                }
+
+                Ok(key)
+            })
+            .collect::<Result<_>>()
+            .with_context(|| format!("in the script {}", self.path.display()))
+    }
+
+    /// Runs `event` through the script, returning the events it passes on.
+    /// If the script goes wrong, `event` is passed on as it is.
+    pub fn process(&mut self, event: Event, domains: &[String]) -> Vec<Event> {
+        // Only events with names can be described to the script
+        let Some(key) = Key::from_event(
+            &InputEvent::new(event.event_type, event.code, event.value),
+            &domains[event.domain],
+        ) else {
+            return vec![event];
+        };
+
+        match self.call(&key, &event, domains) {
+            Ok(events) => events,
+            Err(error) => {
+                match self.failed {
+                    false => warn!(
+                        "The script {} went wrong, so events are passed on as they are when it does: {:#}",
+                        self.path.display(),
+                        error
+                    ),
+                    true => debug!("The script {} went wrong: {:#}", self.path.display(), error),
+                }
+                self.failed = true;
+
+                vec![event]
+            }
+        }
+    }
+
+    fn call(&self, key: &Key, event: &Event, domains: &[String]) -> Result<Vec<Event>> {
+        let mut map = Map::new();
+        // Keys made from events always have all of these
+        map.insert(
+            "type".into(),
+            key.event_type
+                .map(|event_type| event_type.as_str())
+                .unwrap_or_default()
+                .into(),
+        );
+        map.insert("code".into(), key.code.clone().unwrap_or_default().into());
+        map.insert("value".into(), (event.value as i64).into());
+        map.insert("previous".into(), (event.previous as i64).into());
+        map.insert(
+            "domain".into(),
+            key.domain.clone().unwrap_or_default().into(),
+        );
+
+        let returned: Dynamic = self
+            .engine
+            .call_fn(
+                &mut Scope::new(),
+                &self.ast,
+                "on_event",
+                (Dynamic::from_map(map), self.state.clone()),
+            )
+            .map_err(|error| anyhow!("{}", error))?;
+
+        let returned = match returned {
+            returned if returned.is_unit() => Array::new(),
+            returned if returned.is_map() => vec![returned],
+            returned => returned.try_cast::<Array>().ok_or(anyhow!(
+                "on_event should return events, one event, or nothing"
+            ))?,
+        };
+
+        returned
+            .into_iter()
+            .map(|returned| self::event(returned, key, event, domains))
+            .collect()
+    }
+}
+
+/// Turns an event the script returned back into one the engine can pass on,
+/// taking whatever it leaves out from `original`, the event it was given,
+/// which `key` describes
+fn event(returned: Dynamic, key: &Key, original: &Event, domains: &[String]) -> Result<Event> {
+    let map = returned
+        .try_cast::<Map>()
+        .ok_or(anyhow!("each event on_event returns should be a map"))?;
+
+    let text = |name: &str| -> Result<Option<String>> {
+        map.get(name)
+            .map(|value| {
+                value
+                    .clone()
+                    .into_string()
+                    .map_err(|_| anyhow!("an event's {} should be a string", name))
+            })
+            .transpose()
+    };
+    let number = |name: &str| -> Result<Option<i32>> {
+        map.get(name)
+            .map(|value| {
+                value
+                    .as_int()
+                    .ok()
+                    .and_then(|value| i32::try_from(value).ok())
+                    .ok_or(anyhow!("an event's {} should be a whole number", name))
+            })
+            .transpose()
+    };
+
+    let key = Key {
+        event_type: match text("type")? {
+            Some(event_type) => Some(event_type.parse()?),
+            None => key.event_type,
+        },
+        code: text("code")?.or(key.code.clone()),
+        value: None,
+        domain: text("domain")?.or(key.domain.clone()),
+    };
+
+    let (Some(event_type), Some(code), Some(domain)) =
+        (key.event_type, key.code_number(), key.domain.as_deref())
+    else {
+        bail!("{} isn't an event we know about", key);
+    };
+
+    let domain = domains
+        .iter()
+        .position(|other| other == domain)
+        .ok_or(anyhow!("nothing reads from @{}", domain))?;
+    Ok(Event {
+        event_type: match event_type {
+            EventType::Key | EventType::Btn => super::engine::EV_KEY,
+            EventType::Abs => super::engine::EV_ABS,
+            EventType::Rel => super::engine::EV_REL,
+        },
+        code,
+        value: number("value")?.unwrap_or(original.value),
+        previous: number("previous")?.unwrap_or(original.previous),
+        domain,
+    })
+}
@@ -0,0 +1,42 @@
+use crate::config::Settings;
+
+/// Shows desktop notifications about the Wiitar, unless they've been turned
+/// off or roadii was built without them
+#[derive(Clone, Copy, Debug)]
+pub struct Notifier {
+    enabled: bool,
+}
+
+impl Notifier {
+    pub fn new(settings: &Settings) -> Self {
+        Notifier {
+            enabled: !settings.no_notify.unwrap_or_default(),
+        }
+    }
+
+    /// A notifier which never shows anything, for when there's nobody to
+    /// tell
+    pub fn silent() -> Self {
+        Notifier { enabled: false }
+    }
+
+    /// Shows a notification saying `summary`. There may well be no desktop
+    /// to show it on, like when started by udev, so failing isn't fatal.
+    pub fn send(&self, summary: &str) {
+        if !self.enabled {
+            return;
+        }
+
+        #[cfg(feature = "notifications")]
+        if let Err(error) = notify_rust::Notification::new()
+            .appname("roadii")
+            .summary(summary)
+            .show()
+        {
+            tracing::warn!("Couldn't show a notification: {}", error);
+        }
+
+        #[cfg(not(feature = "notifications"))]
+        let _ = summary;
+    }
+}
@@ -0,0 +1,94 @@
+use anyhow::{Context, Result};
+use evdev::{EventType, InputEvent};
+use std::io;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use crate::key::Key;
+use crate::mapping;
+use crate::permissions;
+use crate::wiitar::Wiitar;
+
+/// Prints the events coming from the Wiimote, guitar, accelerometer and any
+/// Motion Plus in `parts`, without grabbing them or creating anything, until
+/// one of them goes away
+pub fn run(parts: &Wiitar) -> Result<()> {
+    let mut sources = vec![
+        (parts.wiimote.as_ref(), "wiimote", "wiimote"),
+        (parts.guitar.as_ref(), "wiimote guitar", "guitar"),
+        (parts.accel.as_ref(), "wiimote accelerometer", "accel"),
+    ];
+
+    // The Motion Plus is only there if the Wiimote has one
+    if parts.motion_plus.is_some() {
+        sources.push((parts.motion_plus.as_ref(), "wiimote Motion Plus", "gyro"));
+    }
+
+    let mut paths = Vec::new();
+    for (device, description, _) in &sources {
+        let path = mapping::devnode(*device, description)?;
+        println!("Watching the {} at {}", description, path.display());
+        paths.push(path);
+    }
+
+    for message in read(paths)? {
+        let (index, event) = match message {
+            Ok(message) => message,
+            Err(error) if error.raw_os_error() == Some(libc::ENODEV) => {
+                println!("An input device went away, so we're done here");
+                return Ok(());
+            }
+            Err(error) => return Err(error).context("couldn't read from an input device"),
+        };
+
+        if event.event_type() == EventType::SYNCHRONIZATION {
+            continue;
+        }
+
+        let domain = sources[index].2;
+        match Key::from_event(&event, domain) {
+            Some(key) => println!("{}", key),
+            None => println!(
+                "type {} code {} value {} @{}",
+                event.event_type().0,
+                event.code(),
+                event.value(),
+                domain
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+/// Opens each of the devices at `paths` without grabbing them, and passes
+/// on everything they send along with which of them sent it. An error ends
+/// the stream, like when a device goes away.
+pub fn read(paths: Vec<PathBuf>) -> Result<Receiver<io::Result<(usize, InputEvent)>>> {
+    let (sender, receiver) = mpsc::channel();
+
+    for (index, path) in paths.into_iter().enumerate() {
+        let mut device = permissions::open(&path)?;
+
+        let sender = sender.clone();
+
+        thread::spawn(move || loop {
+            match device.fetch_events() {
+                Ok(events) => {
+                    for event in events {
+                        if sender.send(Ok((index, event))).is_err() {
+                            return;
+                        }
+                    }
+                }
+                Err(error) => {
+                    let _ = sender.send(Err(error));
+                    return;
+                }
+            }
+        });
+    }
+
+    Ok(receiver)
+}
@@ -0,0 +1,88 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use evdev::InputEvent;
+use std::net::{SocketAddr, UdpSocket};
+use tracing::info;
+
+use crate::key::Key;
+use crate::profile::{Control, WhammySource};
+
+/// Sends what the guitar is doing as OSC messages, for music software and
+/// lighting rigs rather than games. Each message has a single integer
+/// argument:
+///
+/// - `/roadii/button/<control>`, 1 when pressed and 0 when let go
+/// - `/roadii/whammy`, from 0 at rest
+/// - `/roadii/stick/x` and `/roadii/stick/y`, from -32 to 31
+/// - `/roadii/accel/rx`, `/roadii/accel/ry` and `/roadii/accel/rz`
+pub struct Osc {
+    socket: UdpSocket,
+    target: SocketAddr,
+}
+
+impl Osc {
+    /// Gets ready to send messages to `target`
+    pub fn start(target: SocketAddr) -> Result<Self> {
+        let local: SocketAddr = match target {
+            SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+            SocketAddr::V6(_) => ([0; 8], 0).into(),
+        };
+        let socket = UdpSocket::bind(local).context("couldn't open a socket for OSC")?;
+
+        info!("Sending OSC messages to {}", target);
+
+        Ok(Osc { socket, target })
+    }
+
+    /// Sends a message for `event` from the input device tagged `domain`, if
+    /// it's from a control there's a message for
+    pub fn record(&self, domain: &str, event: &InputEvent) {
+        let Some(key) = Key::from_event(event, domain) else {
+            return;
+        };
+        let name = Key { value: None, ..key }.to_string();
+
+        let control = Control::ALL
+            .into_iter()
+            .chain([Control::Home])
+            .find(|control| control.button() == Some(name.as_str()));
+
+        let address = if let Some(control) = control.and_then(|c| c.to_possible_value()) {
+            format!("/roadii/button/{}", control.get_name())
+        } else if name == WhammySource::Bar.axis() {
+            "/roadii/whammy".to_owned()
+        } else {
+            match name.as_str() {
+                "abs:x@guitar" => "/roadii/stick/x",
+                "abs:y@guitar" => "/roadii/stick/y",
+                "abs:rx@accel" => "/roadii/accel/rx",
+                "abs:ry@accel" => "/roadii/accel/ry",
+                "abs:rz@accel" => "/roadii/accel/rz",
+                _ => return,
+            }
+            .to_owned()
+        };
+
+        // Nothing listening is no reason to stop remapping, and UDP wouldn't
+        // tell us reliably anyway
+        let _ = self
+            .socket
+            .send_to(&message(&address, event.value()), self.target);
+    }
+}
+
+/// An OSC message to `address` with `value` as its only argument
+fn message(address: &str, value: i32) -> Vec<u8> {
+    let mut message = Vec::new();
+    push_string(&mut message, address);
+    push_string(&mut message, ",i");
+    message.extend_from_slice(&value.to_be_bytes());
+    message
+}
+
+/// OSC strings end with at least one zero, padded out to a multiple of four
+/// bytes
+fn push_string(message: &mut Vec<u8>, string: &str) {
+    message.extend_from_slice(string.as_bytes());
+    message.extend(std::iter::repeat_n(0, 4 - string.len() % 4));
+}
@@ -0,0 +1,211 @@
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::ffi::CString;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use udev::{Enumerator, Udev};
+
+use crate::failure::Failure;
+use crate::grab;
+use crate::pipeline::{Pipeline, Step};
+
+/// Where virtual devices are made, and so what whoever made them has open
+pub const UINPUT: &str = "/dev/uinput";
+
+/// What the phys of roadii's own virtual devices starts with, before the
+/// process ID of the roadii which made them
+const PHYS_PREFIX: &str = "roadii/";
+
+/// How long to wait for virtual devices to go once whoever made them has
+/// been asked to stop
+const REPLACE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What to do about virtual devices which already have the names roadii
+/// would give its own
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Existing {
+    /// Stop, and say what's already there
+    #[default]
+    Refuse,
+
+    /// Stop whichever roadii or evsieve made them, and carry on once
+    /// they've gone
+    Replace,
+}
+
+/// What roadii sets as the phys of its own virtual devices, so another
+/// roadii can tell whose they are
+pub fn phys() -> CString {
+    CString::new(format!("{}{}", PHYS_PREFIX, std::process::id()))
+        .expect("a number has no NUL bytes")
+}
+
+/// A virtual device which already has the name of one of the outputs
+struct Clash {
+    name: String,
+    phys: Option<String>,
+}
+
+/// Makes sure there aren't already virtual devices with the names of
+/// `pipeline`'s outputs, which games could pick instead of ours, dealing
+/// with any there are as `existing` says
+pub fn check(pipeline: &Pipeline, existing: Existing) -> Result<()> {
+    let clashes = clashing(pipeline)?;
+    if clashes.is_empty() {
+        return Ok(());
+    }
+
+    let mut names: Vec<String> = clashes
+        .iter()
+        .map(|clash| format!("{:?}", clash.name))
+        .collect();
+    names.dedup();
+    let names = names.join(", ");
+
+    // A virtual device lasts as long as whoever made it has uinput open
+    let makers: Vec<(u32, String)> = grab::holders(Path::new(UINPUT))
+        .into_iter()
+        .filter(|(_, name)| name == "roadii" || name == "evsieve")
+        .collect();
+
+    if existing == Existing::Refuse {
+        let by = match makers.as_slice() {
+            [] => String::new(),
+            makers => format!(", made by {}", list(makers)),
+        };
+
+        return Err(Failure::Duplicate.error(format!(
+            "there's already a virtual device called {}{}, so roadii is likely already running. Stop it, give this one another name with --output-name, or replace it with --existing-outputs replace.",
+            names, by
+        )));
+    }
+
+    if makers.is_empty() {
+        bail!(
+            "there's already a virtual device called {}, but it wasn't made by roadii or evsieve that we can see, so it can't be replaced. Running as root shows more.",
+            names
+        );
+    }
+
+    // Only whoever made these, not every roadii and evsieve remapping
+    // something else. If we can't tell, there's only a guess to go on when
+    // there's nobody else it could be.
+    let owners = match owners(&clashes, &makers) {
+        Some(owners) => owners,
+        None if makers.len() == 1 => makers,
+        None => {
+            return Err(Failure::Duplicate.error(format!(
+                "there's already a virtual device called {}, but it isn't clear which of {} made it, so none of them were stopped. Stop the right one yourself, or give this one another name with --output-name.",
+                names,
+                list(&makers)
+            )));
+        }
+    };
+
+    for (pid, name) in &owners {
+        warn!("Stopping {} ({}), to replace {}", name, pid, names);
+
+        // SAFETY: this only sends a signal
+        if unsafe { libc::kill(*pid as libc::pid_t, libc::SIGTERM) } != 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("couldn't stop {} ({})", name, pid));
+        }
+    }
+
+    let started = Instant::now();
+    while !clashing(pipeline)?.is_empty() {
+        if started.elapsed() > REPLACE_TIMEOUT {
+            bail!(
+                "asked whatever made {} to stop, but it's still there",
+                names
+            );
+        }
+
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    info!("Replaced {}", names);
+
+    Ok(())
+}
+
+/// Which of `makers` made the virtual devices in `clashes`, if that can be
+/// told for all of them. roadii's own say so in their phys, and evsieve was
+/// given their names on its command line.
+fn owners(clashes: &[Clash], makers: &[(u32, String)]) -> Option<Vec<(u32, String)>> {
+    let mut owners: Vec<(u32, String)> = Vec::new();
+
+    for clash in clashes {
+        let owner = makers.iter().find(|(pid, name)| match name.as_str() {
+            "roadii" => clash
+                .phys
+                .as_deref()
+                .and_then(|phys| phys.strip_prefix(PHYS_PREFIX))
+                .is_some_and(|owner| owner == pid.to_string()),
+            _ => fs::read(format!("/proc/{}/cmdline", pid)).is_ok_and(|cmdline| {
+                let arg = format!("name={}", clash.name);
+                cmdline
+                    .split(|&byte| byte == 0)
+                    .any(|part| part == arg.as_bytes())
+            }),
+        })?;
+
+        if !owners.contains(owner) {
+            owners.push(owner.clone());
+        }
+    }
+
+    Some(owners)
+}
+
+/// Lists processes for error messages, like `roadii (123), evsieve (456)`
+fn list(processes: &[(u32, String)]) -> String {
+    processes
+        .iter()
+        .map(|(pid, name)| format!("{} ({})", name, pid))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The virtual devices which already have the name of one of `pipeline`'s
+/// outputs
+fn clashing(pipeline: &Pipeline) -> Result<Vec<Clash>> {
+    let udev = Udev::new().context("couldn't get access to Udev")?;
+    let mut clashes = Vec::new();
+    for step in &pipeline.steps {
+        let Step::Output { name, .. } = step else {
+            continue;
+        };
+
+        let mut enumerator =
+            Enumerator::with_udev(udev.clone()).context("couldn't start a device enumerator")?;
+        enumerator
+            .match_subsystem("input")
+            .context("couldn't set input as device subsystem matcher")?;
+        enumerator
+            .match_attribute("name", name)
+            .context("couldn't set the output's name as device attribute matcher")?;
+
+        // Only virtual devices, since some identities share their name with
+        // a real guitar, which could be plugged in too
+        clashes.extend(
+            enumerator
+                .scan_devices()
+                .context("couldn't scan devices")?
+                .filter(|device| device.syspath().starts_with("/sys/devices/virtual"))
+                .map(|device| Clash {
+                    name: name.clone(),
+                    phys: device
+                        .attribute_value("phys")
+                        .map(|phys| phys.to_string_lossy().into_owned()),
+                }),
+        );
+    }
+
+    Ok(clashes)
+}
@@ -0,0 +1,162 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use evdev::InputEvent;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use tracing::{info, warn};
+use tungstenite::WebSocket;
+
+use crate::key::Key;
+use crate::profile::{Control, WhammySource};
+
+/// What overlays are sent whenever anything changes, as JSON
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+struct State {
+    /// Whether each button is held, by its name on the command line, like
+    /// `strum-up`
+    buttons: BTreeMap<String, bool>,
+
+    /// Where the whammy bar is, from 0 at rest
+    whammy: i32,
+
+    /// Where the stick is, from -32 to 31 on each axis
+    stick: Stick,
+
+    /// The accelerometer's RX, RY and RZ, for working out tilt
+    accel: [i32; 3],
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+struct Stick {
+    x: i32,
+    y: i32,
+}
+
+/// What the thread talking to overlays has to deal with
+enum Message {
+    /// An overlay has connected
+    Client(Box<WebSocket<TcpStream>>),
+
+    /// The state has changed, and this is it as JSON
+    State(String),
+}
+
+/// Streams what the guitar is doing to any overlays connected over a
+/// WebSocket. Overlays are written to from their own thread, so a slow one
+/// can't hold up remapping.
+pub struct Overlay {
+    state: State,
+    sender: Sender<Message>,
+}
+
+impl Overlay {
+    /// Starts listening for overlays on `address`
+    pub fn start(address: SocketAddr) -> Result<Self> {
+        let listener = TcpListener::bind(address)
+            .with_context(|| format!("couldn't listen for WebSockets on {}", address))?;
+
+        info!("Streaming the guitar's state to ws://{}", address);
+
+        let (sender, receiver) = mpsc::channel();
+
+        let clients = sender.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let client = match stream {
+                    Ok(stream) => tungstenite::accept(stream).map_err(|error| error.to_string()),
+                    Err(error) => Err(error.to_string()),
+                };
+
+                match client {
+                    Ok(client) => {
+                        if clients.send(Message::Client(Box::new(client))).is_err() {
+                            return;
+                        }
+                    }
+                    Err(error) => warn!("An overlay couldn't connect: {}", error),
+                }
+            }
+        });
+
+        // Every button is there from the start, so overlays know what to draw
+        let state = State {
+            buttons: Control::ALL
+                .into_iter()
+                .chain([Control::Home])
+                .filter(|control| control.button().is_some())
+                .filter_map(|control| control.to_possible_value())
+                .map(|control| (control.get_name().to_owned(), false))
+                .collect(),
+            ..Default::default()
+        };
+        let mut latest = json(&state);
+
+        thread::spawn(move || {
+            let mut clients: Vec<Box<WebSocket<TcpStream>>> = Vec::new();
+
+            for message in receiver {
+                match message {
+                    // Overlays need to know where things are from the start
+                    Message::Client(mut client) => {
+                        if client.send(latest.as_str().into()).is_ok() {
+                            clients.push(client);
+                        }
+                    }
+                    Message::State(state) => {
+                        // Overlays which have gone away are forgotten about
+                        clients.retain_mut(|client| client.send(state.as_str().into()).is_ok());
+                        latest = state;
+                    }
+                }
+            }
+        });
+
+        Ok(Overlay { state, sender })
+    }
+
+    /// Takes note of `event` from the input device tagged `domain`, letting
+    /// the overlays know if it changed anything
+    pub fn record(&mut self, domain: &str, event: &InputEvent) {
+        let Some(key) = Key::from_event(event, domain) else {
+            return;
+        };
+        let value = event.value();
+        let name = Key { value: None, ..key }.to_string();
+
+        let previous = self.state.clone();
+
+        let control = Control::ALL
+            .into_iter()
+            .chain([Control::Home])
+            .find(|control| control.button() == Some(name.as_str()));
+
+        if let Some(control) = control.and_then(|control| control.to_possible_value()) {
+            self.state
+                .buttons
+                .insert(control.get_name().to_owned(), value != 0);
+        } else if name == WhammySource::Bar.axis() {
+            self.state.whammy = value;
+        } else {
+            match name.as_str() {
+                "abs:x@guitar" => self.state.stick.x = value,
+                "abs:y@guitar" => self.state.stick.y = value,
+                "abs:rx@accel" => self.state.accel[0] = value,
+                "abs:ry@accel" => self.state.accel[1] = value,
+                "abs:rz@accel" => self.state.accel[2] = value,
+                _ => {}
+            }
+        }
+
+        if self.state != previous {
+            // The thread only goes away along with us
+            let _ = self.sender.send(Message::State(json(&self.state)));
+        }
+    }
+}
+
+fn json(state: &State) -> String {
+    serde_json::to_string(state).expect("the state can always be written as JSON")
+}
@@ -0,0 +1,249 @@
+use anyhow::{bail, Context, Result};
+use bluer::agent::{Agent, ReqError};
+use bluer::{Adapter, AdapterEvent, Address, Session};
+use futures::{pin_mut, StreamExt};
+use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+use tracing::{info, warn};
+use udev::{Enumerator, Udev};
+
+use crate::wiitar::Wiitar;
+
+/// What Wiimotes call themselves over Bluetooth, the original and the one
+/// with Motion Plus built in
+const NAMES: &[&str] = &["Nintendo RVL-CNT-01", "Nintendo RVL-CNT-01-TR"];
+
+/// How long to wait for hid-wiimote to take the Wiimote once it's connected
+const DRIVER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to leave a Wiimote before trying to connect to it again
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Waits up to `timeout` for a Wiimote to show up with its sync button
+/// pressed, pairs with it, trusts it so it can reconnect by itself, and
+/// checks the hid-wiimote driver takes it
+pub fn run(timeout: Duration) -> Result<()> {
+    let address = runtime()?.block_on(pair(timeout))?;
+
+    println!("Waiting for the hid-wiimote driver to take it...");
+
+    let started = Instant::now();
+    while !driven(&address)? {
+        if started.elapsed() > DRIVER_TIMEOUT {
+            bail!(
+                "{} is connected, but the hid-wiimote driver didn't take it, try `modprobe hid-wiimote`",
+                address
+            );
+        }
+
+        thread::sleep(Duration::from_millis(500));
+    }
+
+    println!("{} is paired and hid-wiimote has it", address);
+
+    if guitar_attached(&address)? {
+        println!("It has a guitar attached, so it's ready to be remapped");
+    } else {
+        println!("Plug the guitar into it to have roadii remap it");
+    }
+
+    Ok(())
+}
+
+/// Keeps looking out for the Wiimotes which have been paired and trusted,
+/// and connects to any which turn up after a button is pressed, so
+/// hid-wiimote and then roadii can take it from there. Runs until BlueZ
+/// goes away.
+pub fn reconnect() -> Result<()> {
+    runtime()?.block_on(async {
+        let (_session, adapter) = adapter().await?;
+
+        let events = adapter
+            .discover_devices_with_changes()
+            .await
+            .context("couldn't start looking for Bluetooth devices")?;
+        pin_mut!(events);
+
+        info!("Looking out for trusted Wiimotes");
+
+        let mut attempts: HashMap<Address, Instant> = HashMap::new();
+
+        while let Some(event) = events.next().await {
+            let AdapterEvent::DeviceAdded(address) = event else {
+                continue;
+            };
+
+            let device = adapter.device(address)?;
+            let name = device.name().await?;
+            if !name.is_some_and(|name| NAMES.contains(&name.as_str()))
+                || !device.is_trusted().await?
+                || device.is_connected().await?
+                || device.rssi().await?.is_none()
+            {
+                continue;
+            }
+
+            // Each change to the Wiimote turns up again, so give it a moment
+            // before trying again
+            if attempts
+                .get(&address)
+                .is_some_and(|attempt| attempt.elapsed() < RETRY_INTERVAL)
+            {
+                continue;
+            }
+            attempts.insert(address, Instant::now());
+
+            info!("{} is in range, connecting", address);
+            match device.connect().await {
+                Ok(()) => info!("Connected to {}", address),
+                Err(error) => warn!("Couldn't connect to {}: {}", address, error),
+            }
+        }
+
+        bail!("BlueZ stopped looking for Bluetooth devices")
+    })
+}
+
+fn runtime() -> Result<Runtime> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("couldn't start talking to BlueZ")
+}
+
+/// A session with BlueZ, which has to be kept around, and its default
+/// adapter, turned on
+async fn adapter() -> Result<(Session, Adapter)> {
+    let session = Session::new()
+        .await
+        .context("couldn't connect to BlueZ, is bluetoothd running?")?;
+    let adapter = session
+        .default_adapter()
+        .await
+        .context("couldn't find a Bluetooth adapter")?;
+    adapter
+        .set_powered(true)
+        .await
+        .context("couldn't turn the Bluetooth adapter on")?;
+
+    Ok((session, adapter))
+}
+
+async fn pair(timeout: Duration) -> Result<Address> {
+    let (session, adapter) = adapter().await?;
+
+    // Pressing the sync button means the Wiimote wants the adapter's own
+    // address, backwards and as raw bytes, as its PIN. BlueZ's wiimote
+    // plugin answers with it before any agent is asked, so if we're asked,
+    // the plugin must be missing and there's no PIN we could give instead.
+    let _agent = session
+        .register_agent(Agent {
+            request_pin_code: Some(Box::new(|request| {
+                Box::pin(async move {
+                    println!(
+                        "BlueZ asked for a PIN for {}, which means its wiimote plugin isn't there to answer, so pairing can't work",
+                        request.device
+                    );
+                    Err(ReqError::Rejected)
+                })
+            })),
+            ..Default::default()
+        })
+        .await
+        .context("couldn't register with BlueZ to help with pairing")?;
+
+    println!(
+        "Press the red sync button under the Wiimote's battery cover, looking for {} seconds...",
+        timeout.as_secs()
+    );
+
+    let address = tokio::time::timeout(timeout, async {
+        let events = adapter
+            .discover_devices()
+            .await
+            .context("couldn't start looking for Bluetooth devices")?;
+        pin_mut!(events);
+
+        while let Some(event) = events.next().await {
+            let AdapterEvent::DeviceAdded(address) = event else {
+                continue;
+            };
+
+            // Devices BlueZ already knows about turn up too, but only ones
+            // actually in range have a signal strength
+            let device = adapter.device(address)?;
+            let name = device.name().await?;
+            if name.is_some_and(|name| NAMES.contains(&name.as_str()))
+                && device.rssi().await?.is_some()
+            {
+                return Ok(address);
+            }
+        }
+
+        bail!("BlueZ stopped looking for Bluetooth devices")
+    })
+    .await
+    .context("didn't find a Wiimote in time, make sure its lights are flashing")??;
+
+    let device = adapter.device(address)?;
+    println!("Found a Wiimote at {}", address);
+
+    if !device.is_paired().await? {
+        println!("Pairing...");
+        device
+            .pair()
+            .await
+            .with_context(|| format!("couldn't pair with {}", address))?;
+    }
+
+    device
+        .set_trusted(true)
+        .await
+        .with_context(|| format!("couldn't trust {}", address))?;
+
+    if !device.is_connected().await? {
+        println!("Connecting...");
+        device
+            .connect()
+            .await
+            .with_context(|| format!("couldn't connect to {}", address))?;
+    }
+
+    Ok(address)
+}
+
+/// Whether the hid-wiimote driver has the Wiimote at `address`
+fn driven(address: &Address) -> Result<bool> {
+    let udev = Udev::new().context("couldn't get access to Udev")?;
+    let mut enumerator =
+        Enumerator::with_udev(udev).context("couldn't start a device enumerator")?;
+    enumerator
+        .match_subsystem("hid")
+        .context("couldn't set hid as device subsystem matcher")?;
+
+    let address = address.to_string();
+
+    Ok(enumerator
+        .scan_devices()
+        .context("couldn't scan devices")?
+        .any(|device| {
+            device.driver().is_some_and(|driver| driver == "wiimote")
+                && device
+                    .property_value("HID_UNIQ")
+                    .is_some_and(|uniq| uniq.to_string_lossy().eq_ignore_ascii_case(&address))
+        }))
+}
+
+/// Whether the Wiimote at `address` has a guitar plugged into it
+fn guitar_attached(address: &Address) -> Result<bool> {
+    let udev = Udev::new().context("couldn't get access to Udev")?;
+    let address = address.to_string();
+
+    Ok(Wiitar::guitars(udev)?.iter().any(|guitar| {
+        guitar
+            .attribute_value("uniq")
+            .is_some_and(|uniq| uniq.to_string_lossy().eq_ignore_ascii_case(&address))
+    }))
+}
@@ -0,0 +1,226 @@
+use anyhow::{anyhow, Result};
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+use std::ffi::{CStr, CString};
+use std::io::{self, ErrorKind};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use udev::{Enumerator, Udev};
+
+use crate::failure::Failure;
+use crate::wiitar::Wiitar;
+
+const UINPUT: &str = "/dev/uinput";
+
+/// Opens the input device at `path`, saying how to get permission to if
+/// that's what's stopping it
+pub fn open(path: &Path) -> Result<evdev::Device> {
+    evdev::Device::open(path).map_err(|error| match error.kind() {
+        ErrorKind::PermissionDenied => {
+            Failure::Permission.error(format!("couldn't open {}: {}", path.display(), fix(path)))
+        }
+        _ => anyhow::Error::new(error).context(format!("couldn't open {}", path.display())),
+    })
+}
+
+/// Opens uinput to start making a virtual device, saying how to get it
+/// going or get permission to if that's what's stopping it
+pub fn uinput<'a>() -> Result<VirtualDeviceBuilder<'a>> {
+    VirtualDevice::builder().map_err(|error| {
+        let kind = error.kind();
+        match (uinput_fix(&error), kind) {
+            (Some(fix), ErrorKind::PermissionDenied) => {
+                Failure::Permission.error(format!("couldn't open {}: {}", UINPUT, fix))
+            }
+            (Some(fix), _) => anyhow!("couldn't open {}: {}", UINPUT, fix),
+            (None, _) => anyhow::Error::new(error).context(format!("couldn't open {}", UINPUT)),
+        }
+    })
+}
+
+/// Checks each of the Wiimote's devices and uinput can be opened, printing
+/// how it went for each, and how to fix any which can't. Fails if any of
+/// them can't.
+pub fn check(parts: &Wiitar) -> Result<()> {
+    let devnodes = [
+        &parts.wiimote,
+        &parts.guitar,
+        &parts.accel,
+        &parts.ir,
+        &parts.motion_plus,
+    ]
+    .into_iter()
+    .flatten()
+    .filter_map(|device| device.devnode().map(Path::to_path_buf));
+
+    let mut failed = 0;
+
+    for path in devnodes {
+        match evdev::Device::open(&path) {
+            Ok(_) => println!("{}: can be opened", path.display()),
+            Err(error) if error.kind() == ErrorKind::PermissionDenied => {
+                failed += 1;
+                println!("{}: permission denied", path.display());
+                println!("  {}", fix(&path));
+            }
+            Err(error) => {
+                failed += 1;
+                println!("{}: {}", path.display(), error);
+            }
+        }
+    }
+
+    match VirtualDevice::builder() {
+        Ok(_) => println!("{}: can be opened", UINPUT),
+        Err(error) => {
+            failed += 1;
+            println!("{}: {}", UINPUT, error);
+            if let Some(fix) = uinput_fix(&error) {
+                println!("  {}", fix);
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(Failure::Permission.error(format!(
+            "{} of the devices roadii needs can't be opened",
+            failed
+        )));
+    }
+
+    println!("Everything roadii needs can be opened");
+
+    Ok(())
+}
+
+/// How to get permission to open the input device at `path`
+fn fix(path: &Path) -> String {
+    // SAFETY: this only reads the process's ID
+    if unsafe { libc::geteuid() } == 0 {
+        return "even root isn't allowed to, so something like SELinux or a container is in the way"
+            .to_owned();
+    }
+
+    let group = path
+        .metadata()
+        .ok()
+        .and_then(|metadata| group_name(metadata.gid()));
+
+    if uaccess(path) {
+        return format!(
+            "it's tagged uaccess, so only whoever's sitting at the machine can open it, which isn't this session (over SSH, say). Run roadii as root, or add yourself to the {} group with `sudo usermod -aG {} $USER` and log in again.",
+            group.as_deref().unwrap_or("input"),
+            group.as_deref().unwrap_or("input")
+        );
+    }
+
+    match group {
+        Some(group) if in_group(&group) => format!(
+            "you're in the {} group, but it isn't allowed to open it, so check the udev rules aren't changing its mode",
+            group
+        ),
+        Some(group) => format!(
+            "only root and the {} group can open it. Run roadii as root, as the example systemd service does, or add yourself to the group with `sudo usermod -aG {} $USER` and log in again.",
+            group, group
+        ),
+        None => "only root can open it. Run roadii as root, as the example systemd service does."
+            .to_owned(),
+    }
+}
+
+/// How to get uinput going after `error` opening it, if it's something
+/// that can be fixed
+fn uinput_fix(error: &io::Error) -> Option<String> {
+    match error.kind() {
+        ErrorKind::NotFound => Some(
+            "the uinput module isn't loaded, load it with `sudo modprobe uinput`, and add `uinput` to /etc/modules-load.d/uinput.conf to keep it loaded".to_owned(),
+        ),
+        ErrorKind::PermissionDenied => {
+            let group = Path::new(UINPUT)
+                .metadata()
+                .ok()
+                .and_then(|metadata| group_name(metadata.gid()))
+                .filter(|group| group != "root");
+
+            Some(match group {
+                Some(group) => format!(
+                    "only root and the {} group can write to it. Run roadii as root, as the example systemd service does, or add yourself to the group with `sudo usermod -aG {} $USER` and log in again.",
+                    group, group
+                ),
+                None => "only root can write to it. Run roadii as root, as the example systemd service does, or add a udev rule like `KERNEL==\"uinput\", GROUP=\"input\", MODE=\"0660\"` and join the input group.".to_owned(),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Whether udev tagged the input device at `path` for whoever's sitting at
+/// the machine to open
+fn uaccess(path: &Path) -> bool {
+    let devices = Udev::new()
+        .and_then(Enumerator::with_udev)
+        .and_then(|mut enumerator| {
+            enumerator.match_subsystem("input")?;
+            Ok(enumerator.scan_devices()?.collect::<Vec<_>>())
+        });
+
+    devices
+        .ok()
+        .and_then(|devices| {
+            devices
+                .into_iter()
+                .find(|device| device.devnode() == Some(path))
+        })
+        .is_some_and(|device| {
+            ["CURRENT_TAGS", "TAGS"].iter().any(|property| {
+                device
+                    .property_value(property)
+                    .is_some_and(|tags| tags.to_string_lossy().contains(":uaccess:"))
+            })
+        })
+}
+
+/// The name of the group with `gid`
+fn group_name(gid: u32) -> Option<String> {
+    // SAFETY: the group is only read before anything else could call
+    // getgrgid
+    let group = unsafe { libc::getgrgid(gid) };
+    if group.is_null() {
+        return None;
+    }
+
+    // SAFETY: getgrgid gave a group, whose name is a valid C string
+    let name = unsafe { CStr::from_ptr((*group).gr_name) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+/// Whether the process is in the group called `name`
+pub fn in_group(name: &str) -> bool {
+    let Ok(name) = CString::new(name) else {
+        return false;
+    };
+
+    // SAFETY: the name is a valid C string, and the group is only read
+    // before anything else could call getgrnam
+    let group = unsafe { libc::getgrnam(name.as_ptr()) };
+    if group.is_null() {
+        return false;
+    }
+    let gid = unsafe { (*group).gr_gid };
+
+    // SAFETY: asking for zero groups only returns how many there are, which
+    // is then the size of the buffer
+    let count = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+    if count < 0 {
+        return false;
+    }
+    let mut groups = vec![0; count as usize];
+    let count = unsafe { libc::getgroups(count, groups.as_mut_ptr()) };
+    if count < 0 {
+        return false;
+    }
+
+    // SAFETY: this only reads the process's group
+    let egid = unsafe { libc::getegid() };
+
+    egid == gid || groups[..count as usize].contains(&gid)
+}
@@ -0,0 +1,773 @@
+use anyhow::{anyhow, bail, Context, Error, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::info;
+
+use crate::key::{EventType, Key, Range, Value};
+use crate::permissions;
+
+/// A single step of a remapping session, which events pass through in order
+#[derive(Clone, Debug)]
+pub enum Step {
+    /// Reads events from the device at `path`, tagging them with `domain`
+    Input { path: PathBuf, domain: String },
+
+    /// Replaces events matching `source` with `targets`
+    Map { source: Key, targets: Vec<Key> },
+
+    /// Adds `targets` alongside events matching `source`
+    Copy { source: Key, targets: Vec<Key> },
+
+    /// Drops events matching any of `keys`
+    Block { keys: Vec<Key> },
+
+    /// Smooths out the values of absolute axis events matching `key`, each
+    /// event only moving `1 - amount` of the way towards its actual value.
+    /// Only the native backend can do this.
+    Smooth { key: Key, amount: f64 },
+
+    /// Adds a `target` event alongside any event matching one of the
+    /// absolute axes in `sources`, holding the magnitude of all of their
+    /// latest values together. Only the native backend can do this.
+    Magnitude { sources: Vec<Key>, target: Key },
+
+    /// Presses `target` while every one of `keys` is held down, leaving the
+    /// events for `keys` themselves alone
+    Chord { keys: Vec<Key>, target: Key },
+
+    /// Drops presses of buttons matching `key` which come within `interval`
+    /// of the last press of the same button, along with their releases.
+    /// Only the native backend can do this.
+    Debounce { key: Key, interval: Duration },
+
+    /// Latches buttons matching `key`, so one press presses them and the
+    /// next lets go of them, ignoring when they're actually let go of. Only
+    /// the native backend can do this.
+    Toggle { key: Key },
+
+    /// Moves events between domains according to the first of `choices`,
+    /// moving on to the next choice each time every one of `keys` has been
+    /// held down for `hold`. Only the native backend can do this.
+    Switch {
+        keys: Vec<Key>,
+        hold: Duration,
+        choices: Vec<Choice>,
+    },
+
+    /// Stops remapping once every one of `keys` has been held down for
+    /// `hold`. Only the native backend can do this.
+    Exit { keys: Vec<Key>, hold: Duration },
+
+    /// Pauses remapping, letting go of the inputs and holding back the
+    /// outputs, once every one of `keys` has been held down for `hold`, and
+    /// resumes the next time they're held. Only the native backend can do this.
+    Pause { keys: Vec<Key>, hold: Duration },
+
+    /// While a button matching `key` is held down, lets go of it and presses
+    /// it again every `interval`. Only the native backend can do this.
+    Repeat { key: Key, interval: Duration },
+
+    /// While an absolute axis matching `key` is anywhere but 0, passes on its
+    /// value again every `interval`, so holding it still keeps things like
+    /// the pointer moving. Only the native backend can do this.
+    Sustain { key: Key, interval: Duration },
+
+    /// Holds back presses of buttons matching `key`, passing them on as a
+    /// quick tap when they're let go of, or pressing `target` instead once
+    /// they've been held for `hold`. Only the native backend can do this.
+    LongPress {
+        key: Key,
+        target: Key,
+        hold: Duration,
+    },
+
+    /// Plays `events` when every one of `keys` has been pressed, each one
+    /// coming its delay after that, unless they're still being played from
+    /// last time. Only the native backend can do this.
+    Macro {
+        keys: Vec<Key>,
+        events: Vec<(Duration, Key)>,
+    },
+
+    /// Runs every event through the Rhai script at `path`, passing on
+    /// whatever it returns instead. Only the native backend can do this.
+    Script { path: PathBuf },
+
+    /// Runs every event through the WebAssembly plugin at `path`, passing
+    /// on whatever it emits instead. Only the native backend can do this.
+    Plugin { path: PathBuf },
+
+    /// Writes events matching `keys` to a virtual device
+    Output {
+        keys: Vec<Key>,
+        name: String,
+        device_id: Option<(u16, u16)>,
+    },
+
+    /// Passes force feedback, like rumble, which games send to the output
+    /// for `output` on to the input for `input`. Only the native backend can
+    /// do this.
+    Rumble { output: String, input: String },
+}
+
+/// A line for each step, for showing what a pipeline does
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Step::Input { path, domain } => write!(f, "read {} as @{}", path.display(), domain),
+            Step::Map { source, targets } => write!(f, "map {} to {}", source, list(targets)),
+            Step::Copy { source, targets } => {
+                write!(f, "copy {} to {}", source, list(targets))
+            }
+            Step::Block { keys } => write!(f, "block {}", list(keys)),
+            Step::Smooth { key, amount } => write!(f, "smooth {} by {}", key, amount),
+            Step::Magnitude { sources, target } => {
+                write!(f, "magnitude of {} as {}", list(sources), target)
+            }
+            Step::Chord { keys, target } => write!(f, "chord {} presses {}", list(keys), target),
+            Step::Debounce { key, interval } => {
+                write!(f, "debounce {} within {:?}", key, interval)
+            }
+            Step::Toggle { key } => write!(f, "toggle {}", key),
+            Step::Switch {
+                keys,
+                hold,
+                choices,
+            } => {
+                let choices = choices
+                    .iter()
+                    .map(|choice| choice.name.as_str())
+                    .collect::<Vec<_>>();
+                write!(
+                    f,
+                    "switch between {} holding {} for {:?}",
+                    choices.join(", "),
+                    list(keys),
+                    hold
+                )
+            }
+            Step::Exit { keys, hold } => write!(f, "exit holding {} for {:?}", list(keys), hold),
+            Step::Pause { keys, hold } => {
+                write!(f, "pause holding {} for {:?}", list(keys), hold)
+            }
+            Step::Repeat { key, interval } => write!(f, "repeat {} every {:?}", key, interval),
+            Step::Sustain { key, interval } => {
+                write!(f, "sustain {} every {:?}", key, interval)
+            }
+            Step::LongPress { key, target, hold } => {
+                write!(f, "long press {} for {:?} presses {}", key, hold, target)
+            }
+            Step::Macro { keys, events } => {
+                let events = events
+                    .iter()
+                    .map(|(delay, key)| format!("{} after {:?}", key, delay))
+                    .collect::<Vec<_>>();
+                write!(f, "macro {} plays {}", list(keys), events.join(", "))
+            }
+            Step::Script { path } => write!(f, "script {}", path.display()),
+            Step::Plugin { path } => write!(f, "plugin {}", path.display()),
+            Step::Output {
+                keys,
+                name,
+                device_id,
+            } => {
+                write!(f, "output {} to {:?}", list(keys), name)?;
+                if let Some((vendor, product)) = device_id {
+                    write!(f, " as {:04x}:{:04x}", vendor, product)?;
+                }
+                Ok(())
+            }
+            Step::Rumble { output, input } => write!(f, "rumble from @{} to @{}", output, input),
+        }
+    }
+}
+
+fn list(keys: &[Key]) -> String {
+    keys.iter()
+        .map(|key| key.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// One of the ways a switch can send events on
+#[derive(Clone, Debug)]
+pub struct Choice {
+    pub name: String,
+    /// Pairs of the domain events come from and the domain they're moved to
+    pub routes: Vec<(String, String)>,
+}
+
+/// The minimum and maximum of the input devices' absolute axes, by the
+/// domain of the device and the axis' code
+pub type SourceRanges = HashMap<(String, u16), (i32, i32)>;
+
+/// Everything a backend needs to know to run a remapping session
+#[derive(Clone, Debug, Default)]
+pub struct Pipeline {
+    pub steps: Vec<Step>,
+    /// Whether the input devices are read without grabbing them, so other
+    /// programs see them as well as the virtual devices
+    pub mirror: bool,
+    /// The ranges of the inputs' axes, for when there are no input devices
+    /// to ask, like when replaying a recording
+    pub source_ranges: SourceRanges,
+    /// The flat to give absolute axes on the outputs, by code, rather than
+    /// the one they'd get from whatever is mapped to them
+    pub flats: HashMap<u16, i32>,
+}
+
+impl Pipeline {
+    pub fn input(&mut self, path: PathBuf, domain: &str) -> &mut Self {
+        self.steps.push(Step::Input {
+            path,
+            domain: domain.to_owned(),
+        });
+
+        self
+    }
+
+    pub fn map(&mut self, source: &str, target: &str) -> Result<&mut Self> {
+        self.steps.push(Step::Map {
+            source: source.parse()?,
+            targets: vec![target.parse()?],
+        });
+
+        Ok(self)
+    }
+
+    pub fn copy(&mut self, source: &str, target: &str) -> Result<&mut Self> {
+        self.steps.push(Step::Copy {
+            source: source.parse()?,
+            targets: vec![target.parse()?],
+        });
+
+        Ok(self)
+    }
+
+    pub fn block(&mut self, keys: &[&str]) -> Result<&mut Self> {
+        self.steps.push(Step::Block {
+            keys: keys.iter().map(|key| key.parse()).collect::<Result<_>>()?,
+        });
+
+        Ok(self)
+    }
+
+    pub fn smooth(&mut self, key: &str, amount: f64) -> Result<&mut Self> {
+        self.steps.push(Step::Smooth {
+            key: key.parse()?,
+            amount,
+        });
+
+        Ok(self)
+    }
+
+    pub fn magnitude(&mut self, sources: &[&str], target: &str) -> Result<&mut Self> {
+        self.steps.push(Step::Magnitude {
+            sources: sources
+                .iter()
+                .map(|source| source.parse())
+                .collect::<Result<_>>()?,
+            target: target.parse()?,
+        });
+
+        Ok(self)
+    }
+
+    pub fn chord(&mut self, keys: &[&str], target: &str) -> Result<&mut Self> {
+        self.steps.push(Step::Chord {
+            keys: keys.iter().map(|key| key.parse()).collect::<Result<_>>()?,
+            target: target.parse()?,
+        });
+
+        Ok(self)
+    }
+
+    pub fn debounce(&mut self, key: &str, interval: Duration) -> Result<&mut Self> {
+        self.steps.push(Step::Debounce {
+            key: key.parse()?,
+            interval,
+        });
+
+        Ok(self)
+    }
+
+    pub fn toggle(&mut self, key: &str) -> Result<&mut Self> {
+        self.steps.push(Step::Toggle { key: key.parse()? });
+
+        Ok(self)
+    }
+
+    pub fn repeat(&mut self, key: &str, interval: Duration) -> Result<&mut Self> {
+        self.steps.push(Step::Repeat {
+            key: key.parse()?,
+            interval,
+        });
+
+        Ok(self)
+    }
+
+    pub fn sustain(&mut self, key: &str, interval: Duration) -> Result<&mut Self> {
+        self.steps.push(Step::Sustain {
+            key: key.parse()?,
+            interval,
+        });
+
+        Ok(self)
+    }
+
+    pub fn long_press(&mut self, key: &str, target: &str, hold: Duration) -> Result<&mut Self> {
+        self.steps.push(Step::LongPress {
+            key: key.parse()?,
+            target: target.parse()?,
+            hold,
+        });
+
+        Ok(self)
+    }
+
+    pub fn output(&mut self, domain: &str, name: &str, device_id: Option<(u16, u16)>) -> &mut Self {
+        self.steps.push(Step::Output {
+            keys: vec![Key::domain(domain)],
+            name: name.to_owned(),
+            device_id,
+        });
+
+        self
+    }
+
+    pub fn rumble(&mut self, output: &str, input: &str) -> &mut Self {
+        self.steps.push(Step::Rumble {
+            output: output.to_owned(),
+            input: input.to_owned(),
+        });
+
+        self
+    }
+
+    /// Renames the domains of every key in the steps other than the inputs
+    /// and outputs, wherever `rename` gives a new name
+    pub fn rename_domains(&mut self, rename: impl Fn(&str) -> Option<String>) {
+        for step in &mut self.steps {
+            let keys = match step {
+                Step::Input { .. }
+                | Step::Output { .. }
+                | Step::Switch { .. }
+                | Step::Exit { .. }
+                | Step::Pause { .. }
+                | Step::Script { .. }
+                | Step::Plugin { .. }
+                | Step::Rumble { .. } => continue,
+                Step::Map { source, targets } | Step::Copy { source, targets } => {
+                    std::iter::once(source).chain(targets).collect()
+                }
+                Step::Block { keys } => keys.iter_mut().collect::<Vec<_>>(),
+                Step::Smooth { key, .. }
+                | Step::Debounce { key, .. }
+                | Step::Toggle { key }
+                | Step::Repeat { key, .. }
+                | Step::Sustain { key, .. } => vec![key],
+                Step::Magnitude { sources, target } => {
+                    sources.iter_mut().chain(std::iter::once(target)).collect()
+                }
+                Step::Chord { keys, target } => {
+                    keys.iter_mut().chain(std::iter::once(target)).collect()
+                }
+                Step::LongPress { key, target, .. } => vec![key, target],
+                Step::Macro { keys, events } => keys
+                    .iter_mut()
+                    .chain(events.iter_mut().map(|(_, key)| key))
+                    .collect(),
+            };
+
+            for key in keys {
+                if let Some(domain) = key.domain.as_deref().and_then(&rename) {
+                    key.domain = Some(domain);
+                }
+            }
+        }
+    }
+
+    /// Makes `range.axis` on the outputs span exactly `range`, by rescaling
+    /// whatever is mapped to it from the range it would otherwise span, and
+    /// gives it the range's flat, if it has one
+    pub fn set_axis_range(&mut self, range: &AxisRange) -> Result<()> {
+        if let Some(flat) = range.flat {
+            let code = format!("abs:{}", range.axis)
+                .parse::<Key>()?
+                .code_number()
+                .ok_or_else(|| anyhow!("{:?} isn't an absolute axis", range.axis))?;
+            self.flats.insert(code, flat);
+        }
+
+        let Some((min, max)) = self.axis_span(&range.axis)? else {
+            return Ok(());
+        };
+
+        if min == max {
+            bail!(
+                "everything mapped to {} has the same value, so it can't be rescaled",
+                range.axis
+            );
+        }
+
+        info!(
+            "Rescaling {} from {}..{} to {}..{}",
+            range.axis, min, max, range.min, range.max
+        );
+
+        // Fit that range onto the one we want, after whatever the mapping
+        // already does, so flipped axes stay flipped and so on
+        let factor = (range.max - range.min) as f64 / (max - min);
+        let offset = range.min as f64 - min * factor;
+
+        self.compose_axis(&range.axis, factor, offset);
+
+        Ok(())
+    }
+
+    /// Scales, offsets and inverts `transform.axis` on the outputs, after
+    /// whatever is mapped to it already
+    pub fn transform_axis(&mut self, transform: &AxisTransform) -> Result<()> {
+        let Some((min, max)) = self.axis_span(&transform.axis)? else {
+            return Ok(());
+        };
+
+        // Inverting flips it around the middle of the range it spans
+        let (factor, offset) = match transform.invert {
+            true => (
+                -transform.scale,
+                transform.scale * (min + max) + transform.offset,
+            ),
+            false => (transform.scale, transform.offset),
+        };
+
+        self.compose_axis(&transform.axis, factor, offset);
+
+        Ok(())
+    }
+
+    /// The domains of the outputs
+    fn outputs(&self) -> Vec<String> {
+        self.steps
+            .iter()
+            .filter_map(|step| match step {
+                Step::Output { keys, .. } => Some(keys),
+                _ => None,
+            })
+            .flatten()
+            .filter_map(|key| key.domain.clone())
+            .collect()
+    }
+
+    /// Works out the range `axis` on the outputs spans as things stand, if
+    /// anything is mapped to it. Things like the tilt thresholds only produce
+    /// buttons, so are left alone.
+    fn axis_span(&self, axis: &str) -> Result<Option<(f64, f64)>> {
+        let outputs = self.outputs();
+        let is_target = |target: &Key| outputs.iter().any(|output| target.is_axis(axis, output));
+
+        let mut spans = Vec::new();
+
+        for step in &self.steps {
+            let Step::Map { source, targets } = step else {
+                continue;
+            };
+
+            if source.event_type != Some(EventType::Abs) || !targets.iter().any(is_target) {
+                continue;
+            }
+
+            let (min, max) = self.source_range(source)?;
+
+            for target in targets.iter().filter(|target| is_target(target)) {
+                if let Some(span) = span(target.value, min, max) {
+                    spans.push(span);
+                }
+            }
+        }
+
+        let Some(min) = spans.iter().map(|(min, _)| *min).reduce(f64::min) else {
+            return Ok(None);
+        };
+        let max = spans.iter().map(|(_, max)| *max).fold(min, f64::max);
+
+        Ok(Some((min, max)))
+    }
+
+    /// Applies `value * factor + offset` to whatever is mapped to `axis` on
+    /// the outputs, after what the mapping does already
+    fn compose_axis(&mut self, axis: &str, factor: f64, offset: f64) {
+        let outputs = self.outputs();
+        let is_target = |target: &Key| outputs.iter().any(|output| target.is_axis(axis, output));
+
+        for step in &mut self.steps {
+            let Step::Map { source, targets } = step else {
+                continue;
+            };
+
+            if source.event_type != Some(EventType::Abs) {
+                continue;
+            }
+
+            for target in targets.iter_mut().filter(|target| is_target(target)) {
+                target.value = match target.value {
+                    None => Some(Value::Linear {
+                        factor,
+                        offset,
+                        delta: false,
+                    }),
+                    Some(Value::Linear {
+                        factor: own_factor,
+                        offset: own_offset,
+                        delta: false,
+                    }) => Some(Value::Linear {
+                        factor: own_factor * factor,
+                        offset: own_offset * factor + offset,
+                        delta: false,
+                    }),
+                    Some(Value::Range {
+                        previous: None,
+                        current:
+                            Range {
+                                min: Some(value),
+                                max: Some(own_max),
+                            },
+                    }) if value == own_max => Some(Value::Range {
+                        previous: None,
+                        current: Range::exactly((value as f64 * factor + offset).round() as i32),
+                    }),
+                    value => value,
+                };
+            }
+        }
+    }
+
+    /// Reads the minimum and maximum of an absolute axis from its input
+    /// device, unless it's one of the source ranges we were given
+    fn source_range(&self, source: &Key) -> Result<(i32, i32)> {
+        let code = source
+            .code_number()
+            .ok_or_else(|| anyhow!("{} isn't a specific axis", source))?;
+
+        let (mut min, mut max) = match source
+            .domain
+            .clone()
+            .and_then(|domain| self.source_ranges.get(&(domain, code)))
+        {
+            Some(&range) => range,
+            None => self.device_range(source, code)?,
+        };
+
+        // A mapping only ever sees the values its source matches
+        if let Some(Value::Range { current, .. }) = source.value {
+            min = current.min.map_or(min, |own| own.clamp(min, max));
+            max = current.max.map_or(max, |own| own.clamp(min, max));
+        }
+
+        Ok((min, max))
+    }
+
+    /// Reads the minimum and maximum of the axis `code` from the input
+    /// device `source` comes from
+    fn device_range(&self, source: &Key, code: u16) -> Result<(i32, i32)> {
+        let path = self
+            .steps
+            .iter()
+            .find_map(|step| match step {
+                Step::Input { path, domain } if source.domain.as_ref() == Some(domain) => {
+                    Some(path)
+                }
+                _ => None,
+            })
+            .ok_or_else(|| anyhow!("{} doesn't come from any input device", source))?;
+
+        if path.as_os_str().is_empty() {
+            bail!(
+                "the range of {} isn't known, so it can't be rescaled",
+                source
+            );
+        }
+
+        let device = permissions::open(path)?;
+
+        let (_, info) = device
+            .get_absinfo()
+            .with_context(|| format!("couldn't read the axes of {:?}", path))?
+            .find(|(axis, _)| axis.0 == code)
+            .ok_or_else(|| anyhow!("{:?} doesn't have the axis {}", path, source))?;
+
+        Ok((info.minimum(), info.maximum()))
+    }
+}
+
+/// The range of values `value` produces on an output from a source axis
+/// spanning `min` to `max`, if it produces a range of values at all
+fn span(value: Option<Value>, min: i32, max: i32) -> Option<(f64, f64)> {
+    let (min, max) = (min as f64, max as f64);
+
+    match value {
+        None => Some((min, max)),
+        Some(Value::Linear {
+            factor,
+            offset,
+            delta: false,
+        }) => {
+            let (a, b) = (min * factor + offset, max * factor + offset);
+            Some((a.min(b), a.max(b)))
+        }
+        Some(Value::Range {
+            previous: None,
+            current:
+                Range {
+                    min: Some(value),
+                    max: Some(own_max),
+                },
+        }) if value == own_max => Some((value as f64, value as f64)),
+        _ => None,
+    }
+}
+
+/// Checks `axis` names an absolute axis, like `rx`, and nothing more, since
+/// overrides apply to the axis wherever it's output
+fn axis_name(axis: &str) -> Result<String> {
+    let key: Key = format!("abs:{}", axis)
+        .parse()
+        .with_context(|| format!("{:?} isn't an absolute axis", axis))?;
+
+    if key.domain.is_some() || key.value.is_some() {
+        bail!(
+            "{:?} should only name the axis, like `rx`, without a domain or value",
+            axis
+        );
+    }
+
+    key.code
+        .ok_or_else(|| anyhow!("{:?} isn't an absolute axis", axis))
+}
+
+/// An override for the range an absolute axis on the output spans, given
+/// as the axis name and the range, like `rx=-32768:32767`, optionally
+/// followed by its flat, the deadzone games should ignore around the middle,
+/// like `rx=-32768:32767:4096`
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct AxisRange {
+    pub axis: String,
+    pub min: i32,
+    pub max: i32,
+    pub flat: Option<i32>,
+}
+
+impl FromStr for AxisRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (axis, range) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("{:?} should look like `axis=min:max`", s))?;
+        let (min, max) = range
+            .split_once(':')
+            .ok_or_else(|| anyhow!("{:?} should look like `axis=min:max`", s))?;
+        let (max, flat) = match max.split_once(':') {
+            Some((max, flat)) => (max, Some(flat)),
+            None => (max, None),
+        };
+
+        let range = AxisRange {
+            axis: axis_name(axis)?,
+            min: min
+                .parse()
+                .with_context(|| format!("{:?} is not a valid minimum", min))?,
+            max: max
+                .parse()
+                .with_context(|| format!("{:?} is not a valid maximum", max))?,
+            flat: flat
+                .map(|flat| {
+                    flat.parse()
+                        .with_context(|| format!("{:?} is not a valid flat", flat))
+                })
+                .transpose()?,
+        };
+
+        if range.min >= range.max {
+            bail!("the minimum of {:?} must be less than its maximum", s);
+        }
+        if range.flat.is_some_and(|flat| flat < 0) {
+            bail!("the flat of {:?} can't be negative", s);
+        }
+
+        Ok(range)
+    }
+}
+
+impl TryFrom<String> for AxisRange {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+/// A transform for an absolute axis on the output, given as the axis name and
+/// any of `scale:N`, `offset:N` and `invert` separated by commas, like
+/// `rx=scale:2,offset:-10,invert`
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct AxisTransform {
+    pub axis: String,
+    pub scale: f64,
+    pub offset: f64,
+    pub invert: bool,
+}
+
+impl FromStr for AxisTransform {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (axis, options) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("{:?} should look like `axis=scale:N,offset:N,invert`", s))?;
+
+        let mut transform = AxisTransform {
+            axis: axis_name(axis)?,
+            scale: 1.0,
+            offset: 0.0,
+            invert: false,
+        };
+
+        for option in options.split(',') {
+            let number = |value: &str| -> Result<f64> {
+                value
+                    .parse()
+                    .ok()
+                    .filter(|number: &f64| number.is_finite())
+                    .ok_or_else(|| anyhow!("{:?} is not a valid number in {:?}", value, s))
+            };
+
+            match option.split_once(':') {
+                Some(("scale", value)) => transform.scale = number(value)?,
+                Some(("offset", value)) => transform.offset = number(value)?,
+                None if option == "invert" => transform.invert = true,
+                _ => bail!(
+                    "{:?} should be `scale:N`, `offset:N` or `invert` in {:?}",
+                    option,
+                    s
+                ),
+            }
+        }
+
+        Ok(transform)
+    }
+}
+
+impl TryFrom<String> for AxisTransform {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
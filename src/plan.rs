@@ -0,0 +1,164 @@
+use anyhow::{Context, Result};
+use std::ffi::{OsStr, OsString};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::backend::Backend;
+use crate::evsieve;
+use crate::pipeline::{Pipeline, Step};
+
+/// Prints what remapping with `pipeline` through `backend` would do: which
+/// devices would be grabbed, and the exact `evsieve` command, with `extra`
+/// arguments on the end, or each step the native backend would take
+pub fn print(
+    pipeline: &Pipeline,
+    backend: Backend,
+    evsieve_path: Option<PathBuf>,
+    extra: &[OsString],
+) -> Result<()> {
+    match pipeline.mirror {
+        true => println!("Would read without grabbing:"),
+        false => println!("Would grab:"),
+    }
+    for step in &pipeline.steps {
+        if let Step::Input { path, domain } = step {
+            println!("  {} as @{}", path.display(), domain);
+        }
+    }
+
+    match backend {
+        Backend::Evsieve => {
+            println!("Would run:");
+            println!("  {}", command_line(pipeline, evsieve_path, extra)?);
+        }
+        Backend::Native => {
+            println!("Would remap natively:");
+            for step in &pipeline.steps {
+                println!("  {}", step);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The `evsieve` command which runs `pipeline` followed by `extra`, quoted
+/// for a shell, with each option on its own line
+fn command_line(
+    pipeline: &Pipeline,
+    evsieve_path: Option<PathBuf>,
+    extra: &[OsString],
+) -> Result<String> {
+    let mut line = quote(evsieve_path.unwrap_or("evsieve".into()).as_os_str());
+
+    for argument in evsieve::arguments(pipeline)?.iter().chain(extra) {
+        match argument.to_string_lossy().starts_with("--") {
+            true => line.push_str(" \\\n    "),
+            false => line.push(' '),
+        }
+        line.push_str(&quote(argument));
+    }
+
+    Ok(line)
+}
+
+/// Writes a shell script to `path` which runs `evsieve` the same way roadii
+/// would for `pipeline`, with `extra` arguments on the end, so it can be
+/// tuned by hand or run without roadii. Each input device is a variable,
+/// which can be given in the environment when they've moved since.
+pub fn script(
+    pipeline: &Pipeline,
+    evsieve_path: Option<PathBuf>,
+    extra: &[OsString],
+    path: &Path,
+) -> Result<()> {
+    let mut script = String::from("#!/bin/sh\n");
+    script.push_str(&format!(
+        "# Written by roadii {}. The Wiimote's devices are wherever they were\n",
+        env!("CARGO_PKG_VERSION")
+    ));
+    script.push_str("# at the time, and can move each time it connects, so they can be\n");
+    script.push_str(
+        "# given in the environment instead, like ROADII_WIIMOTE=/dev/input/event16.\n\n",
+    );
+
+    // Each input path becomes a variable named after its domain, prefixed so
+    // it can't clash with anything else in the environment, like PATH
+    let mut variables: Vec<(OsString, String)> = Vec::new();
+    for step in &pipeline.steps {
+        if let Step::Input { path, domain } = step {
+            let variable = "ROADII_"
+                .chars()
+                .chain(domain.chars().map(|c| match c.is_ascii_alphanumeric() {
+                    true => c.to_ascii_uppercase(),
+                    false => '_',
+                }))
+                .collect::<String>();
+
+            // The default is quoted on its own, since a path could have
+            // anything in it, including what would end a ${...}
+            script.push_str(&format!(
+                "[ -n \"${}\" ] || {}={}\n",
+                variable,
+                variable,
+                quote(path.as_os_str())
+            ));
+            variables.push((path.clone().into_os_string(), variable));
+        }
+    }
+
+    script.push_str("\nexec ");
+    script.push_str(&quote(evsieve_path.unwrap_or("evsieve".into()).as_os_str()));
+
+    let mut previous = OsString::new();
+    for argument in evsieve::arguments(pipeline)?
+        .into_iter()
+        .chain(extra.iter().cloned())
+    {
+        match argument.to_string_lossy().starts_with("--") {
+            true => script.push_str(" \\\n    "),
+            false => script.push(' '),
+        }
+
+        let variable = variables
+            .iter()
+            .find(|(path, _)| previous == "--input" && *path == argument);
+        match variable {
+            Some((_, variable)) => script.push_str(&format!("\"${}\"", variable)),
+            None => script.push_str(&quote(&argument)),
+        }
+
+        previous = argument;
+    }
+    script.push('\n');
+
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o755)
+        .open(path)
+        .and_then(|mut file| file.write_all(script.as_bytes()))
+        .with_context(|| format!("couldn't write {:?}", path))?;
+
+    println!("Wrote a script which runs evsieve to {}", path.display());
+
+    Ok(())
+}
+
+/// `argument`, quoted if a shell would otherwise take it apart
+fn quote(argument: &OsStr) -> String {
+    let argument = argument.to_string_lossy();
+
+    let safe = !argument.is_empty()
+        && argument
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "@%+=:,./_-".contains(c));
+
+    match safe {
+        true => argument.into_owned(),
+        false => format!("'{}'", argument.replace('\'', "'\\''")),
+    }
+}
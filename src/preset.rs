@@ -0,0 +1,68 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::config::Settings;
+use crate::identity::Identity;
+use crate::pipeline::AxisRange;
+use crate::profile::{Profile, TiltMode};
+
+/// Settings which suit a particular game, for anything which isn't set
+/// some other way
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    /// A Santroller guitar, which Clone Hero sets up by itself, with tilt
+    /// on an axis and the whammy spanning the whole of its range
+    CloneHero,
+
+    /// The same as Clone Hero, which YARG also sets up by itself
+    Yarg,
+
+    /// A PlayStation 3 guitar, for RPCS3's Guitar Hero and Rock Band
+    /// handlers, with tilt pressing Select and the whammy spanning a byte,
+    /// as the real guitar's does
+    Rpcs3,
+
+    /// Only a keyboard, with the keys Frets on Fire uses by default
+    FretsOnFire,
+}
+
+impl Preset {
+    /// The settings this preset picks
+    pub fn settings(&self) -> Settings {
+        match self {
+            Preset::CloneHero | Preset::Yarg => Settings {
+                profile: Some(Profile::Gamepad),
+                identity: Some(Identity::Santroller),
+                tilt_mode: Some(TiltMode::Axis),
+                axis_ranges: vec![
+                    axis_range("rx", i16::MIN.into(), i16::MAX.into()),
+                    axis_range("ry", i16::MIN.into(), i16::MAX.into()),
+                ],
+                ..Default::default()
+            },
+            Preset::Rpcs3 => Settings {
+                profile: Some(Profile::Gamepad),
+                identity: Some(Identity::Ps3Guitar),
+                tilt_mode: Some(TiltMode::Button),
+                axis_ranges: vec![axis_range("rx", 0, 255)],
+                ..Default::default()
+            },
+            // The keys bound by default are Frets on Fire's own
+            Preset::FretsOnFire => Settings {
+                profile: Some(Profile::Keyboard),
+                tilt_mode: Some(TiltMode::Button),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+fn axis_range(axis: &str, min: i32, max: i32) -> AxisRange {
+    AxisRange {
+        axis: axis.to_owned(),
+        min,
+        max,
+        flat: None,
+    }
+}
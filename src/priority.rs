@@ -0,0 +1,55 @@
+use anyhow::{bail, Result};
+use std::io;
+use tracing::{info, warn};
+
+/// The priority the event loop gets with `--realtime`, in the middle of
+/// SCHED_FIFO's range, so it goes ahead of anything ordinary without getting
+/// in the way of the kernel's own realtime threads, or audio servers' which
+/// sit above it
+const REALTIME_PRIORITY: libc::c_int = 50;
+
+/// Gives the calling thread, and any threads it starts from here on, a
+/// realtime priority if `realtime`, or else the `nice` value if given, so
+/// the event loop still gets to run right away when something else is busy.
+/// If we aren't allowed to, it carries on as it was, with a warning.
+pub fn raise(realtime: bool, nice: Option<i32>) -> Result<()> {
+    if let Some(nice) = nice {
+        if !(-20..=19).contains(&nice) {
+            bail!("the nice value should be from -20 to 19");
+        }
+    }
+
+    if realtime {
+        let param = libc::sched_param {
+            sched_priority: REALTIME_PRIORITY,
+        };
+
+        // SAFETY: the parameters are valid for the whole call, and 0 means
+        // the calling thread
+        match unsafe { libc::sched_setscheduler(0, libc::SCHED_FIFO, &param) } {
+            0 => {
+                info!("Running with realtime priority {}", REALTIME_PRIORITY);
+                return Ok(());
+            }
+            _ => warn!(
+                "Couldn't get realtime priority, which needs root or an rtprio limit in /etc/security/limits.conf: {}",
+                io::Error::last_os_error()
+            ),
+        }
+    }
+
+    if let Some(nice) = nice {
+        // SAFETY: this only changes the calling thread's niceness, as Linux
+        // takes the process ID 0 to mean the calling thread
+        match unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } {
+            0 => info!("Running with a nice value of {}", nice),
+            _ => warn!(
+                "Couldn't set the nice value to {}, which needs root or a nice limit in /etc/security/limits.conf to go below 0: {}",
+                nice,
+                io::Error::last_os_error()
+            ),
+        }
+    }
+
+    Ok(())
+}
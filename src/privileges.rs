@@ -0,0 +1,87 @@
+use anyhow::{bail, Context, Result};
+use std::ffi::CString;
+use std::io;
+use tracing::{info, warn};
+
+/// Who to carry on as once everything that needs root has been opened
+#[derive(Debug)]
+pub struct User {
+    name: String,
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+}
+
+impl User {
+    /// Looks up the user called `name`, to carry on in `group`, or their
+    /// own group if that's not given
+    pub fn lookup(name: &str, group: Option<&str>) -> Result<Self> {
+        let c_name = CString::new(name)?;
+
+        // SAFETY: the name is a valid C string, and the entry is only read
+        // before anything else could call getpwnam
+        let entry = unsafe { libc::getpwnam(c_name.as_ptr()) };
+        if entry.is_null() {
+            bail!("there's no user called {}", name);
+        }
+        let (uid, own_gid) = unsafe { ((*entry).pw_uid, (*entry).pw_gid) };
+
+        let gid = match group {
+            Some(group) => {
+                let c_group = CString::new(group)?;
+
+                // SAFETY: as above, for getgrnam
+                let entry = unsafe { libc::getgrnam(c_group.as_ptr()) };
+                if entry.is_null() {
+                    bail!("there's no group called {}", group);
+                }
+                unsafe { (*entry).gr_gid }
+            }
+            None => own_gid,
+        };
+
+        Ok(User {
+            name: name.to_owned(),
+            uid,
+            gid,
+        })
+    }
+
+    /// Gives up root for good, carrying on as this user. Whatever's already
+    /// open stays open. Does nothing if we aren't root to begin with.
+    pub fn switch(&self) -> Result<()> {
+        // SAFETY: these only read and change the process's IDs, which glibc
+        // changes for every thread at once
+        unsafe {
+            if libc::geteuid() != 0 {
+                warn!(
+                    "Not running as root, so there's nothing to give up by carrying on as {}",
+                    self.name
+                );
+                return Ok(());
+            }
+
+            let name = CString::new(self.name.as_str())?;
+            if libc::initgroups(name.as_ptr(), self.gid) != 0 {
+                return Err(io::Error::last_os_error())
+                    .with_context(|| format!("couldn't join {}'s groups", self.name));
+            }
+            if libc::setgid(self.gid) != 0 {
+                return Err(io::Error::last_os_error())
+                    .with_context(|| format!("couldn't switch to group {}", self.gid));
+            }
+            if libc::setuid(self.uid) != 0 {
+                return Err(io::Error::last_os_error())
+                    .with_context(|| format!("couldn't switch to {}", self.name));
+            }
+
+            // There shouldn't be any way back
+            if self.uid != 0 && libc::setuid(0) == 0 {
+                bail!("switched to {}, but could still get root back", self.name);
+            }
+        }
+
+        info!("Carrying on as {}", self.name);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,866 @@
+use anyhow::{anyhow, bail, Context, Error, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::key::{EventType, Key};
+
+/// A built-in set of mappings, which decides what virtual devices are created
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Profile {
+    /// A gamepad, laid out like a PlayStation 3 guitar
+    #[default]
+    Gamepad,
+
+    /// The gamepad, plus a keyboard where the frets also press their keys
+    /// (F1 to F5 by default), for games which only read the keyboard
+    GamepadKeyboard,
+
+    /// Only a keyboard, where every control presses a key, for games like
+    /// Frets on Fire which have no gamepad support at all
+    Keyboard,
+
+    /// Only a pointer, moved by pointing the Wiimote at the sensor bar, with
+    /// A and B as the left and right mouse buttons
+    Pointer,
+
+    /// Only a keyboard for getting around menus, where strumming presses the
+    /// up and down arrows, yellow and blue left and right, green Enter and
+    /// red Escape
+    Menu,
+}
+
+impl Profile {
+    /// Whether this profile creates a gamepad
+    pub fn has_gamepad(&self) -> bool {
+        matches!(self, Profile::Gamepad | Profile::GamepadKeyboard)
+    }
+
+    /// Whether this profile creates a keyboard
+    pub fn has_keyboard(&self) -> bool {
+        matches!(
+            self,
+            Profile::GamepadKeyboard | Profile::Keyboard | Profile::Menu
+        )
+    }
+}
+
+/// How the Wiimote's IR camera moves the pointer
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum PointerMode {
+    /// Like a mouse, moving relative to where the pointer was
+    Relative,
+
+    /// Like a touchscreen, pointing straight at a position on the screen
+    Absolute,
+}
+
+/// How the Wiimote is being held, which decides which way its d-pad points
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum WiimoteOrientation {
+    /// Plugged into the guitar, where the d-pad is on its side
+    #[default]
+    Guitar,
+
+    /// Held upright, pointing away from the player
+    Upright,
+
+    /// Held sideways with the d-pad on the left, like a NES controller
+    Sideways,
+}
+
+impl WiimoteOrientation {
+    /// Pairs each of the Wiimote's d-pad keys with the direction it points
+    pub fn dpad(&self) -> [(&'static str, &'static str); 4] {
+        match self {
+            WiimoteOrientation::Guitar => [
+                ("key:left@wiimote", "up"),
+                ("key:right@wiimote", "down"),
+                ("key:up@wiimote", "left"),
+                ("key:down@wiimote", "right"),
+            ],
+            WiimoteOrientation::Upright => [
+                ("key:up@wiimote", "up"),
+                ("key:down@wiimote", "down"),
+                ("key:left@wiimote", "left"),
+                ("key:right@wiimote", "right"),
+            ],
+            WiimoteOrientation::Sideways => [
+                ("key:right@wiimote", "up"),
+                ("key:left@wiimote", "down"),
+                ("key:up@wiimote", "left"),
+                ("key:down@wiimote", "right"),
+            ],
+        }
+    }
+}
+
+/// What raising the guitar's neck does on the gamepad
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum TiltMode {
+    /// Presses Select once the neck is raised past the threshold
+    #[default]
+    Button,
+
+    /// Moves the RY axis along with the neck, for games which can bind star
+    /// power to an axis, like Clone Hero and YARG
+    Axis,
+
+    /// Does nothing, for guitars without a reliable accelerometer
+    Off,
+}
+
+/// Which of the accelerometer's axes tilt is read from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum TiltSource {
+    Rx,
+    #[default]
+    Ry,
+    Rz,
+
+    /// The magnitude of RX and RY together, for guitars which aren't held
+    /// quite straight. Unlike the others, this rises as the neck is raised.
+    /// Needs the native backend.
+    Magnitude,
+}
+
+impl TiltSource {
+    /// The axis tilt is read from once this source has been set up
+    pub fn axis(&self) -> &'static str {
+        match self {
+            TiltSource::Rx => "abs:rx@accel",
+            TiltSource::Ry => "abs:ry@accel",
+            TiltSource::Rz => "abs:rz@accel",
+            TiltSource::Magnitude => "abs:ry@tilt",
+        }
+    }
+
+    /// What this source reads, given the accelerometer's RX, RY and RZ
+    pub fn value(&self, [rx, ry, rz]: [i32; 3]) -> i32 {
+        match self {
+            TiltSource::Rx => rx,
+            TiltSource::Ry => ry,
+            TiltSource::Rz => rz,
+            TiltSource::Magnitude => (rx as f64).hypot(ry as f64).round() as i32,
+        }
+    }
+
+    /// Whether the axis rises as the neck is raised, rather than falling
+    pub fn rises(&self) -> bool {
+        matches!(self, TiltSource::Magnitude)
+    }
+
+    /// The threshold used if none is given
+    pub fn default_threshold(&self) -> i32 {
+        match self {
+            TiltSource::Magnitude => 60,
+            _ => -60,
+        }
+    }
+}
+
+/// Where the whammy comes from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum WhammySource {
+    /// The whammy bar, of course
+    #[default]
+    Bar,
+
+    /// One of the accelerometer's axes, for guitars with a worn out
+    /// whammy bar
+    Rx,
+    Ry,
+    Rz,
+}
+
+impl WhammySource {
+    /// The axis the whammy is read from on its input device
+    pub fn axis(&self) -> &'static str {
+        match self {
+            WhammySource::Bar => "abs:hat1x@guitar",
+            WhammySource::Rx => "abs:rx@accel",
+            WhammySource::Ry => "abs:ry@accel",
+            WhammySource::Rz => "abs:rz@accel",
+        }
+    }
+}
+
+/// A setting for each of the stick's axes, given as `x:y`, or just one
+/// value for both
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(
+    try_from = "String",
+    bound(deserialize = "T: FromStr + Copy, T::Err: std::error::Error + Send + Sync + 'static")
+)]
+pub struct Pair<T> {
+    pub x: T,
+    pub y: T,
+}
+
+impl<T> FromStr for Pair<T>
+where
+    T: FromStr + Copy,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = |value: &str| -> Result<T> {
+            value
+                .parse()
+                .with_context(|| format!("{:?} is not a valid value in {:?}", value, s))
+        };
+
+        match s.split_once(':') {
+            Some((x, y)) => Ok(Pair {
+                x: parse(x)?,
+                y: parse(y)?,
+            }),
+            None => {
+                let both = parse(s)?;
+                Ok(Pair { x: both, y: both })
+            }
+        }
+    }
+}
+
+impl<T> TryFrom<String> for Pair<T>
+where
+    T: FromStr + Copy,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+/// How an axis responds as it travels from rest to fully pressed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Curve {
+    /// Evenly all the way along
+    #[default]
+    Linear,
+
+    /// Gently at first, then more quickly towards the end
+    Expo,
+}
+
+impl Curve {
+    /// Reshapes `travel`, from 0 at rest to 1 fully pressed
+    pub fn apply(&self, travel: f64) -> f64 {
+        match self {
+            Curve::Linear => travel,
+            Curve::Expo => travel * travel,
+        }
+    }
+}
+
+/// The controls on the guitar which can be bound to keys
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Control {
+    Green,
+    Red,
+    Yellow,
+    Blue,
+    Orange,
+    StrumUp,
+    StrumDown,
+    Start,
+    Select,
+    /// Pressing the whammy bar past halfway
+    Whammy,
+    /// The Wiimote's Home button, which can only be part of a chord
+    Home,
+}
+
+impl Control {
+    pub const ALL: [Control; 10] = [
+        Control::Green,
+        Control::Red,
+        Control::Yellow,
+        Control::Blue,
+        Control::Orange,
+        Control::StrumUp,
+        Control::StrumDown,
+        Control::Start,
+        Control::Select,
+        Control::Whammy,
+    ];
+
+    pub const FRETS: [Control; 5] = [
+        Control::Green,
+        Control::Red,
+        Control::Yellow,
+        Control::Blue,
+        Control::Orange,
+    ];
+
+    /// The key this control presses if it hasn't been bound to anything
+    pub fn default_key(&self) -> &'static str {
+        // The frets and strum keys match the defaults in Frets on Fire
+        match self {
+            Control::Green => "f1",
+            Control::Red => "f2",
+            Control::Yellow => "f3",
+            Control::Blue => "f4",
+            Control::Orange => "f5",
+            Control::StrumUp => "enter",
+            Control::StrumDown => "rightshift",
+            Control::Start => "esc",
+            Control::Select => "space",
+            Control::Whammy => "rightctrl",
+            Control::Home => "home",
+        }
+    }
+
+    /// The button on the guitar for this control, if it is a simple button
+    pub fn button(&self) -> Option<&'static str> {
+        match self {
+            Control::Green => Some("btn:1@guitar"),
+            Control::Red => Some("btn:2@guitar"),
+            Control::Yellow => Some("btn:3@guitar"),
+            Control::Blue => Some("btn:4@guitar"),
+            Control::Orange => Some("btn:5@guitar"),
+            Control::StrumUp => Some("btn:dpad_up@guitar"),
+            Control::StrumDown => Some("btn:dpad_down@guitar"),
+            Control::Start => Some("btn:start@guitar"),
+            Control::Select => Some("btn:select@guitar"),
+            Control::Whammy => None,
+            Control::Home => Some("btn:mode@wiimote"),
+        }
+    }
+}
+
+/// Binds a control to a key on the keyboard output, given as the control
+/// name and the key name, like `green=f1`
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct KeyBinding {
+    pub control: Control,
+    pub key: String,
+}
+
+impl KeyBinding {
+    /// The key bound to `control` by the last matching binding, if any
+    pub fn key_for(bindings: &[KeyBinding], control: Control) -> &str {
+        bindings
+            .iter()
+            .rev()
+            .find(|binding| binding.control == control)
+            .map(|binding| binding.key.as_str())
+            .unwrap_or(control.default_key())
+    }
+}
+
+impl FromStr for KeyBinding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (control, key) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("{:?} should look like `control=key`", s))?;
+
+        let control = Control::from_str(control, true)
+            .ok()
+            .filter(|control| Control::ALL.contains(control))
+            .ok_or_else(|| anyhow!("{:?} is not a control which can press a key", control))?;
+
+        // Make sure it's a real key now, rather than when we build the mapping
+        format!("key:{}", key)
+            .parse::<Key>()
+            .with_context(|| format!("{:?} is not a key we know about", key))?;
+
+        Ok(KeyBinding {
+            control,
+            key: key.to_owned(),
+        })
+    }
+}
+
+impl TryFrom<String> for KeyBinding {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+/// Controls on the guitar held down together, given as their names joined
+/// by `+`, like `select+green`
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Chord(pub Vec<Control>);
+
+impl Chord {
+    /// The buttons on the guitar which make up this chord
+    pub fn buttons(&self) -> Vec<&'static str> {
+        self.0
+            .iter()
+            .filter_map(|control| control.button())
+            .collect()
+    }
+}
+
+impl FromStr for Chord {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let controls = s
+            .split('+')
+            .map(|control| {
+                let control = Control::from_str(control, true)
+                    .map_err(|_| anyhow!("{:?} is not a control on the guitar", control))?;
+
+                if control.button().is_none() {
+                    bail!("{:?} can't be held down as part of a chord", s);
+                }
+
+                Ok(control)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Chord(controls))
+    }
+}
+
+impl TryFrom<String> for Chord {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+/// Presses an event on the main output while a chord is held down, given as
+/// the chord and the event, like `green+red=key:f6`
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ChordBinding {
+    pub chord: Chord,
+    pub target: Key,
+}
+
+impl FromStr for ChordBinding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (chord, target) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("{:?} should look like `control+control=event`", s))?;
+
+        Ok(ChordBinding {
+            chord: chord.parse()?,
+            target: button_target(target)?,
+        })
+    }
+}
+
+impl TryFrom<String> for ChordBinding {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+/// Presses an event on the main output when a control is held down for a
+/// while, leaving quick taps to do what the control usually does, given as
+/// the control and the event, like `select=btn:mode`
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct LongPressBinding {
+    pub control: Control,
+    pub target: Key,
+}
+
+impl FromStr for LongPressBinding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (control, target) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("{:?} should look like `control=event`", s))?;
+
+        let control = Control::from_str(control, true)
+            .ok()
+            .filter(|control| control.button().is_some())
+            .ok_or_else(|| anyhow!("{:?} is not a button on the guitar", control))?;
+
+        Ok(LongPressBinding {
+            control,
+            target: button_target(target)?,
+        })
+    }
+}
+
+impl TryFrom<String> for LongPressBinding {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+/// Plays a short sequence of events on the main output when some controls
+/// are held down together, given as the controls and the steps separated by
+/// commas, like `select+orange=btn:start,500,btn:dpad_down,btn:south`. Each
+/// step is a key or button to tap, or press or let go of when given a value
+/// of 1 or 0, or a number of milliseconds to wait.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct MacroBinding {
+    pub chord: Chord,
+    pub steps: Vec<MacroStep>,
+}
+
+/// One step of a macro
+#[derive(Clone, Debug, PartialEq)]
+pub enum MacroStep {
+    /// Presses a key or button and lets go of it again
+    Tap(Key),
+    /// Presses or lets go of a key or button, depending on its value
+    Set(Key),
+    /// Waits before carrying on
+    Wait(Duration),
+}
+
+impl FromStr for MacroBinding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (chord, steps) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("{:?} should look like `control+control=step,step`", s))?;
+
+        let steps = steps
+            .split(',')
+            .map(|step| {
+                if let Ok(milliseconds) = step.parse() {
+                    return Ok(MacroStep::Wait(Duration::from_millis(milliseconds)));
+                }
+
+                // The value is checked here, so it's left out to check the rest
+                let (event, value) = match step.rsplit_once(':') {
+                    Some((event, value @ ("0" | "1"))) => (event, Some(value)),
+                    _ => (step, None),
+                };
+
+                let mut target = button_target(event)?;
+
+                match value {
+                    Some(value) => {
+                        target.value = Some(value.parse()?);
+                        Ok(MacroStep::Set(target))
+                    }
+                    None => Ok(MacroStep::Tap(target)),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(MacroBinding {
+            chord: chord.parse()?,
+            steps,
+        })
+    }
+}
+
+impl TryFrom<String> for MacroBinding {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+/// Presses a key or button while an axis is past a threshold, given as the
+/// axis, the key or button, the value it's pressed at and optionally the
+/// value it's let go of at, like `abs:hat1x@guitar=btn:tl2,8,6`. The axis
+/// still does its usual job.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct AxisButton {
+    pub source: Key,
+    pub target: Key,
+    pub press: i32,
+    /// If this is below `press`, it's pressed as the axis rises, otherwise
+    /// as it falls
+    pub release: i32,
+}
+
+impl FromStr for AxisButton {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (source, rest) = s.split_once('=').ok_or_else(|| {
+            anyhow!(
+                "{:?} should look like `abs:axis@domain=event,press,release`",
+                s
+            )
+        })?;
+
+        let source = source_key(source, &[EventType::Abs], "abs:hat1x@guitar")?;
+        let mut parts = rest.split(',');
+        let target = target_key(
+            parts.next().unwrap_or_default(),
+            &[EventType::Key, EventType::Btn],
+            "btn:tl2",
+        )?;
+
+        let value = |part: Option<&str>| -> Result<Option<i32>> {
+            part.map(|part| {
+                part.parse()
+                    .with_context(|| format!("{:?} is not a valid value in {:?}", part, s))
+            })
+            .transpose()
+        };
+
+        let press = value(parts.next())?
+            .ok_or_else(|| anyhow!("{:?} needs a value to press the button at", s))?;
+        let release = value(parts.next())?.unwrap_or(press - 1);
+
+        if parts.next().is_some() {
+            bail!("{:?} has too many values", s);
+        }
+
+        if release == press {
+            bail!("{:?} needs to let go of the button at a different value", s);
+        }
+
+        Ok(AxisButton {
+            source,
+            target,
+            press,
+            release,
+        })
+    }
+}
+
+impl TryFrom<String> for AxisButton {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+/// Sets an axis to one value while a key or button is pressed and another
+/// once it's let go of, given as the key or button, the axis and the two
+/// values, like `btn:5@guitar=abs:z,255,0`. The button still does its usual
+/// job.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ButtonAxis {
+    pub source: Key,
+    pub target: Key,
+    pub pressed: i32,
+    pub released: i32,
+}
+
+impl FromStr for ButtonAxis {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (source, rest) = s.split_once('=').ok_or_else(|| {
+            anyhow!(
+                "{:?} should look like `btn:button@domain=abs:axis,pressed,released`",
+                s
+            )
+        })?;
+
+        let source = source_key(source, &[EventType::Key, EventType::Btn], "btn:5@guitar")?;
+        let parts: Vec<&str> = rest.split(',').collect();
+
+        let [target, pressed, released] = parts[..] else {
+            bail!("{:?} needs an axis and two values for it", s);
+        };
+
+        let value = |part: &str| -> Result<i32> {
+            part.parse()
+                .with_context(|| format!("{:?} is not a valid value in {:?}", part, s))
+        };
+
+        Ok(ButtonAxis {
+            source,
+            target: target_key(target, &[EventType::Abs], "abs:z")?,
+            pressed: value(pressed)?,
+            released: value(released)?,
+        })
+    }
+}
+
+impl TryFrom<String> for ButtonAxis {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+/// Replaces one event with another, given as the two events, like
+/// `key:b@pedal=btn:select`. The event replaced has to say where it comes
+/// from, and the one replacing it goes to the main output unless it says
+/// otherwise.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct EventMap {
+    pub source: Key,
+    pub target: Key,
+}
+
+impl FromStr for EventMap {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (source, target) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("{:?} should look like `type:code@domain=type:code`", s))?;
+
+        let types = [
+            EventType::Key,
+            EventType::Btn,
+            EventType::Abs,
+            EventType::Rel,
+        ];
+
+        Ok(EventMap {
+            source: source_key(source, &types, "key:b@pedal")?,
+            target: target_key(target, &types, "btn:select")?,
+        })
+    }
+}
+
+impl TryFrom<String> for EventMap {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+/// Another input device to read from alongside the Wiimote, like a foot
+/// pedal, given as the domain its events are tagged with and its path, like
+/// `pedal=/dev/input/by-id/usb-footswitch-event-kbd`
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Merge {
+    pub domain: String,
+    pub path: PathBuf,
+}
+
+impl Merge {
+    /// The domains roadii uses itself, which are taken
+    const RESERVED: [&'static str; 17] = [
+        "wiimote", "guitar", "accel", "ir", "gyro", "tilt", "whammy", "swapped", "reversed",
+        "stick", "mouse", "wiitar", "keyboard", "pointer", "lightgun", "motion", "rotation",
+    ];
+}
+
+impl FromStr for Merge {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (domain, path) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("{:?} should look like `domain=/dev/input/...`", s))?;
+
+        if domain.is_empty()
+            || !domain
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            bail!(
+                "{:?} should be a name made of letters, numbers, `-` and `_`",
+                domain
+            );
+        }
+
+        if Merge::RESERVED.contains(&domain) {
+            bail!("{:?} is already used by roadii, pick another name", domain);
+        }
+
+        Ok(Merge {
+            domain: domain.to_owned(),
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+impl TryFrom<String> for Merge {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+/// Parses an event for a binding to watch, which has to be one of `types`
+/// and say where it comes from, like `example`
+fn source_key(s: &str, types: &[EventType], example: &str) -> Result<Key> {
+    let source: Key = s
+        .parse()
+        .with_context(|| format!("{:?} is not an event we know about", s))?;
+
+    if !source
+        .event_type
+        .is_some_and(|event_type| types.contains(&event_type))
+        || source.code.is_none()
+        || source.value.is_some()
+        || source.domain.is_none()
+    {
+        bail!("{:?} should look like `{}`", s, example);
+    }
+
+    Ok(source)
+}
+
+/// Parses an event for a binding to produce, which has to be one of `types`,
+/// and goes to the main output unless it says otherwise, like `example`
+fn target_key(s: &str, types: &[EventType], example: &str) -> Result<Key> {
+    let target: Key = s
+        .parse()
+        .with_context(|| format!("{:?} is not an event we know about", s))?;
+
+    if !target
+        .event_type
+        .is_some_and(|event_type| types.contains(&event_type))
+        || target.code.is_none()
+        || target.value.is_some()
+    {
+        bail!("{:?} should look like `{}`", s, example);
+    }
+
+    Ok(target)
+}
+
+/// Parses a key or button for a binding to press, which goes wherever the
+/// binding decides
+fn button_target(s: &str) -> Result<Key> {
+    let target: Key = s
+        .parse()
+        .with_context(|| format!("{:?} is not an event we know about", s))?;
+
+    if !matches!(target.event_type, Some(EventType::Key | EventType::Btn))
+        || target.code.is_none()
+        || target.value.is_some()
+        || target.domain.is_some()
+    {
+        bail!(
+            "{:?} should be a key or button on its own, like `key:f6` or `btn:tr2`",
+            s
+        );
+    }
+
+    Ok(target)
+}
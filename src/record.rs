@@ -0,0 +1,290 @@
+use anyhow::{bail, Context, Result};
+use evdev::{EventType, InputEvent};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::mapping;
+use crate::monitor;
+use crate::observe;
+use crate::permissions;
+use crate::pipeline::{Pipeline, SourceRanges};
+use crate::wiitar::Wiitar;
+
+/// What every recording starts with, followed by the format's version
+const MAGIC: &[u8; 4] = b"RDII";
+const VERSION: u8 = 2;
+
+/// A device in a recording, by the domain its events had
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordedDevice {
+    pub domain: String,
+
+    /// Whether this is one of roadii's virtual devices, rather than part of
+    /// the Wiimote
+    pub output: bool,
+
+    /// The code, minimum and maximum of each of its absolute axes, which
+    /// recordings from before version 2 of the format don't have
+    pub axes: Vec<(u16, i32, i32)>,
+}
+
+/// One event from a recording
+#[derive(Clone, Copy, Debug)]
+pub struct Recorded {
+    /// Which of the recording's devices sent it
+    pub device: usize,
+
+    /// How long after the recording started it was sent
+    pub time: Duration,
+
+    pub event: InputEvent,
+}
+
+/// Everything read back from a recording
+#[derive(Clone, Debug)]
+pub struct Recording {
+    pub devices: Vec<RecordedDevice>,
+    pub events: Vec<Recorded>,
+}
+
+impl Recording {
+    /// Reads back the recording at `path`
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("couldn't open {:?}", path))?;
+
+        read(&mut BufReader::new(file))
+            .with_context(|| format!("{:?} isn't a recording we can read", path))
+    }
+
+    /// The ranges of the axes of the parts of the Wiimote it was recorded
+    /// from, for replaying it through a pipeline which rescales them
+    pub fn source_ranges(&self) -> SourceRanges {
+        self.devices
+            .iter()
+            .filter(|device| !device.output)
+            .flat_map(|device| {
+                device
+                    .axes
+                    .iter()
+                    .map(|&(code, min, max)| ((device.domain.clone(), code), (min, max)))
+            })
+            .collect()
+    }
+}
+
+/// Records the events from the Wiimote, guitar, accelerometer and any Motion
+/// Plus in `parts` to `path`, without grabbing them, until one of them goes
+/// away or roadii is stopped. If given the `pipeline` roadii is running
+/// with, what its virtual devices send is recorded too.
+pub fn run(parts: &Wiitar, pipeline: Option<&Pipeline>, path: &Path) -> Result<()> {
+    let mut sources = vec![
+        (parts.wiimote.as_ref(), "wiimote", "wiimote"),
+        (parts.guitar.as_ref(), "wiimote guitar", "guitar"),
+        (parts.accel.as_ref(), "wiimote accelerometer", "accel"),
+    ];
+
+    if parts.motion_plus.is_some() {
+        sources.push((parts.motion_plus.as_ref(), "wiimote Motion Plus", "gyro"));
+    }
+
+    let mut devices = Vec::new();
+    for (device, description, domain) in sources {
+        let path = mapping::devnode(device, description)?;
+        let axes = axes(&path)?;
+
+        devices.push((
+            path,
+            RecordedDevice {
+                domain: domain.to_owned(),
+                output: false,
+                axes,
+            },
+        ));
+    }
+
+    if let Some(pipeline) = pipeline {
+        for (path, domain) in monitor::outputs(pipeline)? {
+            devices.push((
+                path,
+                RecordedDevice {
+                    domain,
+                    output: true,
+                    axes: Vec::new(),
+                },
+            ));
+        }
+    }
+
+    let (paths, devices): (Vec<PathBuf>, Vec<RecordedDevice>) = devices.into_iter().unzip();
+
+    let file = File::create(path).with_context(|| format!("couldn't create {:?}", path))?;
+    let mut writer = BufWriter::new(file);
+    write_header(&mut writer, &devices).with_context(|| format!("couldn't write to {:?}", path))?;
+
+    let start = SystemTime::now();
+    let mut count = 0;
+
+    println!("Recording to {}, press Ctrl+C to stop", path.display());
+
+    for message in observe::read(paths)? {
+        let (device, event) = match message {
+            Ok(message) => message,
+            Err(error) if error.raw_os_error() == Some(libc::ENODEV) => {
+                println!("A device went away, so we're done here");
+                break;
+            }
+            Err(error) => return Err(error).context("couldn't read from a device"),
+        };
+
+        // Anything from before we started was already waiting
+        let time = event.timestamp().duration_since(start).unwrap_or_default();
+
+        write_event(&mut writer, device, time, &event)
+            .with_context(|| format!("couldn't write to {:?}", path))?;
+        count += 1;
+
+        // Each report is a complete moment, so being stopped part way
+        // through the next one loses nothing important
+        if event.event_type() == EventType::SYNCHRONIZATION {
+            writer
+                .flush()
+                .with_context(|| format!("couldn't write to {:?}", path))?;
+        }
+    }
+
+    writer
+        .flush()
+        .with_context(|| format!("couldn't write to {:?}", path))?;
+    println!("Recorded {} events", count);
+
+    Ok(())
+}
+
+/// The code, minimum and maximum of each of the absolute axes of the device
+/// at `path`
+fn axes(path: &Path) -> Result<Vec<(u16, i32, i32)>> {
+    let device = permissions::open(path)?;
+
+    let axes = device
+        .get_absinfo()
+        .with_context(|| format!("couldn't read the axes of {:?}", path))?
+        .map(|(axis, info)| (axis.0, info.minimum(), info.maximum()))
+        .collect();
+
+    Ok(axes)
+}
+
+/// Writes out what a recording starts with: the magic, the version and how
+/// many devices there are, then whether each is an output, its domain, and
+/// how many axes it has followed by each one's code, minimum and maximum
+fn write_header(writer: &mut impl Write, devices: &[RecordedDevice]) -> Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[VERSION, devices.len().try_into()?])?;
+
+    for device in devices {
+        writer.write_all(&[device.output as u8, device.domain.len().try_into()?])?;
+        writer.write_all(device.domain.as_bytes())?;
+
+        writer.write_all(&[device.axes.len().try_into()?])?;
+        for (code, min, max) in &device.axes {
+            writer.write_all(&code.to_le_bytes())?;
+            writer.write_all(&min.to_le_bytes())?;
+            writer.write_all(&max.to_le_bytes())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes out `event`, which is 17 bytes: the device, microseconds since the
+/// recording started, and the event's type, code and value
+fn write_event(
+    writer: &mut impl Write,
+    device: usize,
+    time: Duration,
+    event: &InputEvent,
+) -> Result<()> {
+    writer.write_all(&[device as u8])?;
+    writer.write_all(&(time.as_micros() as u64).to_le_bytes())?;
+    writer.write_all(&event.event_type().0.to_le_bytes())?;
+    writer.write_all(&event.code().to_le_bytes())?;
+    writer.write_all(&event.value().to_le_bytes())?;
+
+    Ok(())
+}
+
+fn read(reader: &mut impl Read) -> Result<Recording> {
+    let mut header = [0; 6];
+    reader.read_exact(&mut header)?;
+
+    if &header[..4] != MAGIC {
+        bail!("it doesn't start like a recording");
+    }
+    // Version 1 is the same, only without the axes
+    let version = header[4];
+    if !(1..=VERSION).contains(&version) {
+        bail!("it's version {} of the format, not {}", version, VERSION);
+    }
+
+    let mut devices = Vec::new();
+    for _ in 0..header[5] {
+        let mut device = [0; 2];
+        reader.read_exact(&mut device)?;
+
+        let mut domain = vec![0; device[1] as usize];
+        reader.read_exact(&mut domain)?;
+
+        let mut axes = Vec::new();
+        if version >= 2 {
+            let mut count = [0; 1];
+            reader.read_exact(&mut count)?;
+
+            for _ in 0..count[0] {
+                let mut axis = [0; 10];
+                reader.read_exact(&mut axis)?;
+
+                axes.push((
+                    u16::from_le_bytes(axis[0..2].try_into()?),
+                    i32::from_le_bytes(axis[2..6].try_into()?),
+                    i32::from_le_bytes(axis[6..10].try_into()?),
+                ));
+            }
+        }
+
+        devices.push(RecordedDevice {
+            domain: String::from_utf8(domain).context("a device's domain isn't UTF-8")?,
+            output: device[0] != 0,
+            axes,
+        });
+    }
+
+    let mut events = Vec::new();
+    loop {
+        let mut event = [0; 17];
+        match reader.read_exact(&mut event) {
+            Ok(()) => {}
+            // Being stopped part way through writing an event only loses that
+            Err(error) if error.kind() == ErrorKind::UnexpectedEof => break,
+            Err(error) => return Err(error.into()),
+        }
+
+        let device = event[0] as usize;
+        if device >= devices.len() {
+            bail!("an event came from device {}, which isn't there", device);
+        }
+
+        events.push(Recorded {
+            device,
+            time: Duration::from_micros(u64::from_le_bytes(event[1..9].try_into()?)),
+            event: InputEvent::new(
+                u16::from_le_bytes(event[9..11].try_into()?),
+                u16::from_le_bytes(event[11..13].try_into()?),
+                i32::from_le_bytes(event[13..17].try_into()?),
+            ),
+        });
+    }
+
+    Ok(Recording { devices, events })
+}
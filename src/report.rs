@@ -0,0 +1,318 @@
+use anyhow::{Context, Result};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+use udev::{Device, Enumerator, Udev};
+
+use crate::calibration::Calibration;
+use crate::config::{Config, Settings};
+use crate::doctor;
+use crate::evsieve;
+use crate::mapping;
+use crate::wiitar::Wiitar;
+
+/// How many lines of roadii's logs to include
+const JOURNAL_LINES: &str = "1000";
+
+/// What a report is built from
+pub struct Sources<'a> {
+    pub evsieve_path: Option<PathBuf>,
+    pub config_path: &'a Path,
+    pub config: &'a Config,
+    pub calibration: &'a Calibration,
+
+    /// The settings given on the command line
+    pub settings: &'a Settings,
+}
+
+/// Writes everything useful for working out what's wrong to a gzipped
+/// tarball at `path`, to attach to a bug report: versions, what `roadii
+/// doctor` says, the configuration, the settings and mapping each connected
+/// guitar would get, the udev properties of their devices and roadii's
+/// recent logs. Bluetooth addresses and the hostname are swapped out along
+/// the way.
+pub fn run(path: &Path, sources: Sources) -> Result<()> {
+    let mut redactor = Redactor::new();
+
+    let files = [
+        ("versions.txt", versions(&sources)),
+        ("doctor.txt", doctor::summary(sources.evsieve_path.clone())),
+        ("config.toml", config(sources.config_path)),
+        ("guitars.txt", guitars(&sources)),
+        ("udev.txt", udev()),
+        ("journal.txt", journal()),
+    ];
+
+    let file = File::create(path).with_context(|| format!("couldn't create {:?}", path))?;
+    let mut archive = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    for (name, contents) in files {
+        let contents = redactor.redact(&contents);
+
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(now);
+        header.set_cksum();
+
+        archive
+            .append_data(
+                &mut header,
+                format!("roadii-report/{}", name),
+                contents.as_bytes(),
+            )
+            .with_context(|| format!("couldn't write to {:?}", path))?;
+    }
+
+    archive
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .with_context(|| format!("couldn't write to {:?}", path))?;
+
+    println!(
+        "Wrote a report to {}, it's ready to attach to a bug report",
+        path.display()
+    );
+    if !redactor.addresses.is_empty() {
+        println!(
+            "{} Bluetooth addresses were swapped out, as wiimote-1 and so on",
+            redactor.addresses.len()
+        );
+    }
+
+    Ok(())
+}
+
+fn versions(sources: &Sources) -> String {
+    let evsieve = match evsieve::find(sources.evsieve_path.clone()) {
+        Ok((path, version)) => format!("{} at {}", version, path.display()),
+        Err(error) => format!("{:#}", error),
+    };
+
+    format!(
+        "roadii {}\nevsieve {}\n{}",
+        env!("CARGO_PKG_VERSION"),
+        evsieve,
+        fs::read_to_string("/proc/version").unwrap_or_else(|error| error.to_string())
+    )
+}
+
+fn config(path: &Path) -> String {
+    fs::read_to_string(path).unwrap_or_else(|error| format!("# {}: {}\n", path.display(), error))
+}
+
+/// The settings and pipeline each connected guitar would get
+fn guitars(sources: &Sources) -> String {
+    let guitars = match Udev::new().map_err(Into::into).and_then(Wiitar::guitars) {
+        Ok(guitars) => guitars,
+        Err(error) => return format!("{:#}\n", error),
+    };
+
+    if guitars.is_empty() {
+        return format!(
+            "There are no Wiimotes with a guitar attached, so these are the settings any would get:\n{:#?}\n",
+            sources
+                .settings
+                .clone()
+                .resolve(sources.config, sources.calibration, None)
+        );
+    }
+
+    let mut text = String::new();
+
+    for guitar in guitars {
+        let kernel_name = guitar.sysname().to_owned();
+        text.push_str(&format!("{}:\n", kernel_name.to_string_lossy()));
+
+        let parts = match Wiitar::from_kernel_name(kernel_name) {
+            Ok(parts) => parts,
+            Err(error) => {
+                text.push_str(&format!("{:#}\n\n", error));
+                continue;
+            }
+        };
+
+        let settings = sources.settings.clone().resolve(
+            sources.config,
+            sources.calibration,
+            parts.address.as_deref(),
+        );
+        text.push_str(&format!("{:#?}\n", settings));
+
+        match mapping::pipeline(&parts, &settings) {
+            Ok(pipeline) => text.push_str(&format!("{:#?}\n\n", pipeline)),
+            Err(error) => text.push_str(&format!("{:#}\n\n", error)),
+        }
+    }
+
+    text
+}
+
+/// The properties of every device belonging to a Wiimote with a guitar
+/// attached, and the Wiimote itself
+fn udev() -> String {
+    let udev = match Udev::new() {
+        Ok(udev) => udev,
+        Err(error) => return format!("{}\n", error),
+    };
+    let guitars = match Wiitar::guitars(udev.clone()) {
+        Ok(guitars) => guitars,
+        Err(error) => return format!("{:#}\n", error),
+    };
+
+    let mut text = String::new();
+
+    for wiimote in guitars.iter().filter_map(|guitar| guitar.parent()) {
+        text.push_str(&describe(&wiimote));
+
+        let devices = Enumerator::with_udev(udev.clone()).and_then(|mut enumerator| {
+            enumerator.match_parent(&wiimote)?;
+            Ok(enumerator.scan_devices()?.collect::<Vec<_>>())
+        });
+
+        match devices {
+            Ok(devices) => {
+                for device in devices
+                    .iter()
+                    .filter(|device| device.syspath() != wiimote.syspath())
+                {
+                    text.push_str(&describe(device));
+                }
+            }
+            Err(error) => text.push_str(&format!("{}\n", error)),
+        }
+    }
+
+    text
+}
+
+fn describe(device: &Device) -> String {
+    let mut text = format!("{}\n", device.syspath().display());
+
+    for property in device.properties() {
+        text.push_str(&format!(
+            "  {}={}\n",
+            property.name().to_string_lossy(),
+            property.value().to_string_lossy()
+        ));
+    }
+
+    text.push('\n');
+    text
+}
+
+/// roadii's recent logs, from every time the systemd service ran
+fn journal() -> String {
+    let output = Command::new("journalctl")
+        .args(["--no-pager", "--output=short-iso", "--lines", JOURNAL_LINES])
+        .args(["--unit", "roadii@*"])
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        }
+        Ok(output) => String::from_utf8_lossy(&output.stderr).into_owned(),
+        Err(error) => format!("couldn't run journalctl: {}\n", error),
+    }
+}
+
+/// Swaps out anything identifying in what goes into a report, consistently
+/// across the whole report so it still makes sense
+struct Redactor {
+    /// The Bluetooth addresses seen so far, lowercased, in the order they
+    /// were seen
+    addresses: Vec<String>,
+
+    hostname: Option<String>,
+}
+
+impl Redactor {
+    fn new() -> Self {
+        Redactor {
+            addresses: Vec::new(),
+            hostname: fs::read_to_string("/proc/sys/kernel/hostname")
+                .ok()
+                .map(|hostname| hostname.trim().to_owned())
+                .filter(|hostname| !hostname.is_empty()),
+        }
+    }
+
+    fn redact(&mut self, text: &str) -> String {
+        let mut redacted = String::with_capacity(text.len());
+        let mut rest = text;
+
+        // An address is six pairs of hex digits split by colons, 17
+        // characters in all
+        while !rest.is_empty() {
+            let candidate = rest.get(..17).filter(|candidate| is_address(candidate));
+
+            match candidate {
+                Some(address) => {
+                    let address = address.to_ascii_lowercase();
+                    let index = match self.addresses.iter().position(|seen| *seen == address) {
+                        Some(index) => index,
+                        None => {
+                            self.addresses.push(address);
+                            self.addresses.len() - 1
+                        }
+                    };
+
+                    redacted.push_str(&format!("wiimote-{}", index + 1));
+                    rest = &rest[17..];
+                }
+                None => {
+                    let next = rest.chars().next().expect("rest isn't empty");
+                    redacted.push(next);
+                    rest = &rest[next.len_utf8()..];
+                }
+            }
+        }
+
+        match &self.hostname {
+            Some(hostname) => replace_word(&redacted, hostname, "hostname"),
+            None => redacted,
+        }
+    }
+}
+
+/// `text` with `word` replaced by `with` wherever it's a word of its own,
+/// rather than part of a longer one, so a host called `deck` doesn't take
+/// `steamdeck` or `deck-2` with it
+fn replace_word(text: &str, word: &str, with: &str) -> String {
+    let is_word = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+
+    let mut replaced = String::with_capacity(text.len());
+    let mut last = 0;
+
+    for (start, _) in text.match_indices(word) {
+        let end = start + word.len();
+
+        let before = text[..start].chars().next_back();
+        let after = text[end..].chars().next();
+        if before.is_some_and(is_word) || after.is_some_and(is_word) {
+            continue;
+        }
+
+        replaced.push_str(&text[last..start]);
+        replaced.push_str(with);
+        last = end;
+    }
+
+    replaced.push_str(&text[last..]);
+    replaced
+}
+
+fn is_address(candidate: &str) -> bool {
+    candidate.split(':').count() == 6
+        && candidate
+            .split(':')
+            .all(|pair| pair.len() == 2 && pair.chars().all(|c| c.is_ascii_hexdigit()))
+}
@@ -0,0 +1,137 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+use udev::{Device, Udev};
+
+use crate::failure::Failure;
+use crate::wiitar::Wiitar;
+
+/// Where a Wiimote's devices are, for scripts driving them some other way
+#[derive(Debug, Serialize)]
+struct Resolved {
+    /// The kernel name of the guitar, to remap it by
+    kernel_name: String,
+
+    /// The Wiimote's Bluetooth address, if it has one
+    address: Option<String>,
+
+    wiimote: Option<PathBuf>,
+    guitar: Option<PathBuf>,
+    accel: Option<PathBuf>,
+    ir: Option<PathBuf>,
+    motion_plus: Option<PathBuf>,
+}
+
+/// Finds the Wiimote with a guitar attached which `selector` picks out,
+/// and prints the device nodes of its parts, one `part path` a line, or as
+/// a JSON object if `json`. The selector can be the kernel name of any of
+/// its input or event devices, or of the Wiimote itself, its Bluetooth
+/// address, or a path to one of its device nodes, like a
+/// `/dev/input/by-id` link.
+pub fn run(selector: &str, json: bool) -> Result<()> {
+    let udev = Udev::new().context("couldn't get access to Udev")?;
+
+    // Anything which doesn't exist as a path can still be a name or address
+    let path = Path::new(selector).canonicalize().ok();
+
+    let mut resolved = None;
+
+    for guitar in Wiitar::guitars(udev)? {
+        let parts = match Wiitar::from_kernel_name(guitar.sysname().to_owned()) {
+            Ok(parts) => parts,
+            Err(error) => {
+                debug!(
+                    "Skipping {}: {:#}",
+                    guitar.sysname().to_string_lossy(),
+                    error
+                );
+                continue;
+            }
+        };
+
+        if picks(&parts, selector, path.as_deref()) {
+            resolved = Some(Resolved {
+                kernel_name: guitar.sysname().to_string_lossy().into_owned(),
+                address: parts.address.clone(),
+                wiimote: devnode(parts.wiimote.as_ref()),
+                guitar: devnode(parts.guitar.as_ref()),
+                accel: devnode(parts.accel.as_ref()),
+                ir: devnode(parts.ir.as_ref()),
+                motion_plus: devnode(parts.motion_plus.as_ref()),
+            });
+            break;
+        }
+    }
+
+    let resolved = resolved.ok_or_else(|| {
+        Failure::NotFound.error(format!(
+            "couldn't find a Wiimote with a guitar attached which {:?} picks out",
+            selector
+        ))
+    })?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&resolved)?);
+        return Ok(());
+    }
+
+    for (part, devnode) in [
+        ("wiimote", &resolved.wiimote),
+        ("guitar", &resolved.guitar),
+        ("accel", &resolved.accel),
+        ("ir", &resolved.ir),
+        ("motion_plus", &resolved.motion_plus),
+    ] {
+        if let Some(devnode) = devnode {
+            println!("{} {}", part, devnode.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether `selector`, or `path` if it's a path which exists, picks out the
+/// Wiimote `parts` makes up
+fn picks(parts: &Wiitar, selector: &str, path: Option<&Path>) -> bool {
+    if parts
+        .address
+        .as_deref()
+        .is_some_and(|address| address.eq_ignore_ascii_case(selector))
+    {
+        return true;
+    }
+
+    [
+        &parts.wiimote,
+        &parts.guitar,
+        &parts.accel,
+        &parts.ir,
+        &parts.motion_plus,
+    ]
+    .into_iter()
+    .flatten()
+    .any(|event| {
+        // The event device, the input device it's under, and the Wiimote
+        let input = event.parent();
+        let wiimote = input.as_ref().and_then(Device::parent);
+
+        [Some(event.clone()), input, wiimote]
+            .into_iter()
+            .flatten()
+            .any(|device| {
+                device.sysname() == selector
+                    || path.is_some_and(|path| {
+                        device.syspath() == path
+                            || device
+                                .devnode()
+                                .and_then(|devnode| devnode.canonicalize().ok())
+                                .is_some_and(|devnode| devnode == path)
+                    })
+            })
+    })
+}
+
+fn devnode(device: Option<&Device>) -> Option<PathBuf> {
+    device.and_then(Device::devnode).map(Path::to_path_buf)
+}
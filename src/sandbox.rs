@@ -0,0 +1,90 @@
+use anyhow::{Context, Result};
+use seccompiler::{
+    BpfProgram, SeccompAction, SeccompCmpArgLen, SeccompCmpOp, SeccompCondition, SeccompFilter,
+    SeccompRule, TargetArch,
+};
+use std::collections::BTreeMap;
+use std::env::consts::ARCH;
+use tracing::info;
+
+/// The system calls remapping needs once everything's open: reading and
+/// writing devices and sockets which are already open, pausing with ioctl,
+/// waiting, keeping time, memory, and what threads need to run and finish,
+/// including ones which were started just before and are still starting up
+const ALLOWED: &[libc::c_long] = &[
+    libc::SYS_read,
+    libc::SYS_readv,
+    libc::SYS_write,
+    libc::SYS_writev,
+    libc::SYS_sendto,
+    libc::SYS_sendmsg,
+    libc::SYS_ioctl,
+    libc::SYS_ppoll,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_poll,
+    libc::SYS_futex,
+    libc::SYS_clock_gettime,
+    libc::SYS_clock_nanosleep,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_nanosleep,
+    libc::SYS_sched_yield,
+    libc::SYS_mmap,
+    libc::SYS_munmap,
+    libc::SYS_mremap,
+    libc::SYS_mprotect,
+    libc::SYS_madvise,
+    libc::SYS_brk,
+    libc::SYS_getrandom,
+    libc::SYS_close,
+    libc::SYS_lseek,
+    libc::SYS_fstat,
+    libc::SYS_newfstatat,
+    libc::SYS_statx,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_sigaltstack,
+    libc::SYS_rseq,
+    libc::SYS_set_robust_list,
+    libc::SYS_sched_getaffinity,
+    libc::SYS_getpid,
+    libc::SYS_gettid,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+];
+
+/// Stops roadii, and every thread it's started, from making any system
+/// call remapping doesn't need from here on, and from gaining privileges by
+/// running anything. Files can still be opened to read, for checking the
+/// battery, but not to write. Anything else fails as if it wasn't allowed.
+pub fn enter() -> Result<()> {
+    let mut rules: BTreeMap<i64, Vec<SeccompRule>> =
+        ALLOWED.iter().map(|&call| (call, Vec::new())).collect();
+
+    // Only opening to read, without creating or truncating anything
+    let read_only = libc::O_ACCMODE | libc::O_CREAT | libc::O_TRUNC;
+    rules.insert(
+        libc::SYS_openat,
+        vec![SeccompRule::new(vec![SeccompCondition::new(
+            2,
+            SeccompCmpArgLen::Dword,
+            SeccompCmpOp::MaskedEq(read_only as u64),
+            libc::O_RDONLY as u64,
+        )?])?],
+    );
+
+    let arch = TargetArch::try_from(ARCH)
+        .with_context(|| format!("the sandbox doesn't know about {} computers", ARCH))?;
+    let filter = SeccompFilter::new(
+        rules,
+        SeccompAction::Errno(libc::EPERM as u32),
+        SeccompAction::Allow,
+        arch,
+    )?;
+    let program: BpfProgram = filter.try_into()?;
+
+    seccompiler::apply_filter_all_threads(&program).context("couldn't enter the sandbox")?;
+
+    info!("Entered the sandbox");
+
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use anyhow::{bail, Result};
+use std::collections::BTreeSet;
+
+use crate::config::Settings;
+use crate::key::{EventType, Key};
+use crate::mapping;
+use crate::pipeline::{Pipeline, Step};
+
+/// The first of the codes SDL numbers before all the others
+const BTN_JOYSTICK: u16 = 0x120;
+
+/// The hat switches, which SDL numbers separately from the other axes
+const ABS_HATS: std::ops::RangeInclusive<u16> = 0x10..=0x17;
+
+/// What each button and axis on the gamepad is, in SDL's terms
+const ELEMENTS: [(&str, &str); 20] = [
+    ("a", "btn:south"),
+    ("b", "btn:east"),
+    ("x", "btn:west"),
+    ("y", "btn:north"),
+    ("back", "btn:select"),
+    ("guide", "btn:mode"),
+    ("start", "btn:start"),
+    ("leftstick", "btn:thumbl"),
+    ("rightstick", "btn:thumbr"),
+    ("leftshoulder", "btn:tl"),
+    ("rightshoulder", "btn:tr"),
+    ("dpup", "btn:dpad_up"),
+    ("dpdown", "btn:dpad_down"),
+    ("dpleft", "btn:dpad_left"),
+    ("dpright", "btn:dpad_right"),
+    ("misc1", "btn:z"),
+    ("leftx", "abs:x"),
+    ("lefty", "abs:y"),
+    ("rightx", "abs:rx"),
+    ("righty", "abs:ry"),
+];
+
+/// The line for `SDL_GAMECONTROLLERCONFIG` which describes the gamepad
+/// `settings` would create, so SDL games know what each control is
+pub fn mapping(settings: &Settings) -> Result<String> {
+    let profile = settings.profile.unwrap_or_default();
+    if !profile.has_gamepad() {
+        bail!("there's no gamepad to describe, as the profile doesn't create one");
+    }
+
+    let mut pipeline = Pipeline::default();
+    mapping::gamepad(&mut pipeline, settings)?;
+
+    // SDL numbers buttons and axes by their order in the kernel's codes,
+    // with the joystick and gamepad buttons ahead of everything else
+    let mut buttons = BTreeSet::new();
+    let mut axes = BTreeSet::new();
+
+    for key in targets(&pipeline, "wiitar") {
+        match (key.event_type, key.code_number()) {
+            (Some(EventType::Btn | EventType::Key), Some(code)) => {
+                buttons.insert((code < BTN_JOYSTICK, code));
+            }
+            (Some(EventType::Abs), Some(code)) if !ABS_HATS.contains(&code) => {
+                axes.insert(code);
+            }
+            _ => {}
+        }
+    }
+
+    let buttons: Vec<u16> = buttons.into_iter().map(|(_, code)| code).collect();
+    let axes: Vec<u16> = axes.into_iter().collect();
+
+    let identity = settings.identity.unwrap_or_default();
+    let name = settings.output_name.as_deref().unwrap_or(identity.name());
+
+    let mut line = format!(
+        "{},{},",
+        guid(name, identity.device_id()),
+        name.replace(',', " ")
+    );
+
+    for (element, key) in ELEMENTS {
+        let key: Key = key.parse()?;
+        let Some(code) = key.code_number() else {
+            continue;
+        };
+
+        let (list, prefix) = match key.event_type {
+            Some(EventType::Abs) => (&axes, 'a'),
+            _ => (&buttons, 'b'),
+        };
+
+        if let Some(index) = list.iter().position(|&own| own == code) {
+            line.push_str(&format!("{}:{}{},", element, prefix, index));
+        }
+    }
+
+    line.push_str("platform:Linux,");
+
+    Ok(line)
+}
+
+/// Every key which `pipeline` maps or copies into `domain`
+fn targets<'a>(pipeline: &'a Pipeline, domain: &'a str) -> impl Iterator<Item = &'a Key> {
+    pipeline
+        .steps
+        .iter()
+        .flat_map(|step| match step {
+            Step::Map { targets, .. } | Step::Copy { targets, .. } => targets.as_slice(),
+            _ => &[],
+        })
+        .filter(move |key| key.domain.as_deref() == Some(domain))
+}
+
+/// The GUID SDL gives a device on Linux, with its name's checksum and
+/// version left out so SDL doesn't hold either against us
+fn guid(name: &str, device_id: Option<(u16, u16)>) -> String {
+    let mut guid = [0u8; 16];
+
+    match device_id {
+        Some((vendor, product)) => {
+            guid[0..2].copy_from_slice(&0x03u16.to_le_bytes());
+            guid[4..6].copy_from_slice(&vendor.to_le_bytes());
+            guid[8..10].copy_from_slice(&product.to_le_bytes());
+        }
+        // Without IDs, SDL falls back on as much of the name as will fit
+        None => {
+            guid[0..2].copy_from_slice(&0x06u16.to_le_bytes());
+            let name = &name.as_bytes()[..name.len().min(12)];
+            guid[4..4 + name.len()].copy_from_slice(name);
+        }
+    }
+
+    guid.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
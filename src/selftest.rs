@@ -0,0 +1,112 @@
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use evdev::EventType;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
+
+use crate::color;
+use crate::key::Key;
+use crate::monitor;
+use crate::native;
+use crate::observe;
+use crate::pipeline::Pipeline;
+use crate::profile::Control;
+
+/// How long to wait for each control to be pressed
+const PRESS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Asks for each of the guitar's buttons to be pressed in turn, and checks
+/// the virtual devices of the roadii already running `pipeline` send what
+/// `pipeline` says pressing it should. Fails if any of them don't.
+pub fn run(pipeline: &Pipeline) -> Result<()> {
+    let (paths, domains): (Vec<PathBuf>, Vec<String>) =
+        monitor::outputs(pipeline)?.into_iter().unzip();
+    let receiver = observe::read(paths)?;
+
+    let mut failed = Vec::new();
+    let mut checked = 0;
+
+    for control in Control::ALL {
+        let name = control
+            .to_possible_value()
+            .map(|value| value.get_name().to_owned())
+            .unwrap_or_default();
+
+        // The whammy isn't a button, so there's nothing simple to expect
+        let Some(button) = control.button() else {
+            continue;
+        };
+
+        let mut expected: HashSet<String> = native::expected(pipeline, &button.parse::<Key>()?)?
+            .into_iter()
+            .collect();
+
+        if expected.is_empty() {
+            println!("{} doesn't do anything on its own, skipping it", name);
+            continue;
+        }
+
+        // Anything from before asking, like letting go of the last one,
+        // would only confuse things
+        while receiver.try_recv().is_ok() {}
+
+        println!("Press {}", name);
+        checked += 1;
+
+        let deadline = Instant::now() + PRESS_TIMEOUT;
+        while !expected.is_empty() {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+
+            let (index, event) = match receiver.recv_timeout(timeout) {
+                Ok(Ok(message)) => message,
+                Ok(Err(error)) if error.raw_os_error() == Some(libc::ENODEV) => {
+                    bail!("one of roadii's virtual devices went away")
+                }
+                Ok(Err(error)) => return Err(error).context("couldn't read from a virtual device"),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    bail!("stopped hearing from roadii's virtual devices")
+                }
+            };
+
+            if event.event_type() == EventType::SYNCHRONIZATION {
+                continue;
+            }
+
+            if let Some(key) = Key::from_event(&event, &domains[index]) {
+                expected.remove(&key.to_string());
+            }
+        }
+
+        match expected.is_empty() {
+            true => println!("  {} {}", name, color::good("works")),
+            false => {
+                let mut missing = expected.into_iter().collect::<Vec<_>>();
+                missing.sort();
+
+                println!(
+                    "  {} {} {}",
+                    name,
+                    color::bad("should have sent"),
+                    missing.join(", ")
+                );
+                failed.push(name);
+            }
+        }
+    }
+
+    match failed.as_slice() {
+        [] => {
+            println!("{}", color::good(format!("All {} controls work", checked)));
+            Ok(())
+        }
+        failed => bail!(
+            "{} of {} controls didn't do what they should: {}",
+            failed.len(),
+            checked,
+            failed.join(", ")
+        ),
+    }
+}
@@ -0,0 +1,186 @@
+use clap::ValueEnum;
+use evdev::InputEvent;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+use crate::config::Settings;
+use crate::key::Key;
+use crate::mapping;
+use crate::profile::{Control, TiltMode, TiltSource, WhammySource};
+
+/// What the guitar got up to while it was being remapped
+#[derive(Debug)]
+pub struct Stats {
+    started: Instant,
+
+    /// How many times each button was pressed, in the order of `Control::ALL`
+    presses: Vec<(Control, usize)>,
+
+    /// The lowest and highest the whammy bar went
+    whammy: Option<(i32, i32)>,
+
+    /// The latest RX, RY and RZ from the accelerometer
+    accel: [i32; 3],
+
+    /// How tilting is worked out, if it's on
+    tilt: Option<Tilt>,
+
+    tilted: bool,
+    tilts: usize,
+}
+
+/// Enough of the tilt settings to tell when the neck has been raised
+#[derive(Clone, Copy, Debug)]
+struct Tilt {
+    source: TiltSource,
+    threshold: i32,
+    release: i32,
+    rises: bool,
+}
+
+impl Stats {
+    pub fn new(settings: &Settings) -> Self {
+        let source = settings.tilt_source.unwrap_or_default();
+        let rises = source.rises() != mapping::tilt_inverted(settings);
+        let threshold = mapping::tilt_threshold(settings);
+        let hysteresis = settings.tilt_hysteresis.unwrap_or(1);
+
+        let tilt = (settings.tilt_mode != Some(TiltMode::Off)).then_some(Tilt {
+            source,
+            threshold,
+            release: match rises {
+                true => threshold - hysteresis,
+                false => threshold + hysteresis,
+            },
+            rises,
+        });
+
+        Stats {
+            started: Instant::now(),
+            presses: Control::ALL
+                .into_iter()
+                .filter(|control| control.button().is_some())
+                .map(|control| (control, 0))
+                .collect(),
+            whammy: None,
+            accel: [0; 3],
+            tilt,
+            tilted: false,
+            tilts: 0,
+        }
+    }
+
+    /// Takes note of `event` from the input device tagged `domain`
+    pub fn record(&mut self, domain: &str, event: &InputEvent) {
+        let Some(key) = Key::from_event(event, domain) else {
+            return;
+        };
+        let value = event.value();
+        let name = Key { value: None, ..key }.to_string();
+
+        if value == 1 {
+            if let Some((_, count)) = self
+                .presses
+                .iter_mut()
+                .find(|(control, _)| control.button() == Some(name.as_str()))
+            {
+                *count += 1;
+                return;
+            }
+        }
+
+        if name == WhammySource::Bar.axis() {
+            self.whammy = Some(match self.whammy {
+                Some((min, max)) => (min.min(value), max.max(value)),
+                None => (value, value),
+            });
+            return;
+        }
+
+        let axis = ["abs:rx@accel", "abs:ry@accel", "abs:rz@accel"]
+            .iter()
+            .position(|&axis| axis == name);
+        if let (Some(axis), Some(tilt)) = (axis, self.tilt) {
+            self.accel[axis] = value;
+            let value = tilt.source.value(self.accel);
+
+            // The same hysteresis as the mapping, so a wobble at the
+            // threshold isn't counted over and over
+            let (raised, lowered) = match tilt.rises {
+                true => (value >= tilt.threshold, value <= tilt.release),
+                false => (value <= tilt.threshold, value >= tilt.release),
+            };
+
+            if raised && !self.tilted {
+                self.tilted = true;
+                self.tilts += 1;
+            } else if lowered {
+                self.tilted = false;
+            }
+        }
+    }
+
+    /// Logs a summary of everything so far
+    pub fn print(&self) {
+        let elapsed = self.started.elapsed();
+        info!("Played for {}", duration(elapsed));
+
+        let presses = self
+            .presses
+            .iter()
+            .map(|(control, count)| format!("{} {}", name(*control), count))
+            .collect::<Vec<_>>();
+        info!("  Presses: {}", presses.join(", "));
+
+        let strums: usize = self
+            .presses
+            .iter()
+            .filter(|(control, _)| matches!(control, Control::StrumUp | Control::StrumDown))
+            .map(|(_, count)| count)
+            .sum();
+        let minutes = elapsed.as_secs_f64() / 60.0;
+        if minutes > 0.0 {
+            info!("  {:.1} strums a minute", strums as f64 / minutes);
+        }
+
+        match self.whammy {
+            Some((min, max)) => info!("  The whammy bar went from {} to {}", min, max),
+            None => info!("  The whammy bar never moved"),
+        }
+
+        if self.tilt.is_some() {
+            info!("  The neck was raised {} times", self.tilts);
+        }
+
+        // A fret which never registers is more likely broken than unloved
+        let unused = self
+            .presses
+            .iter()
+            .filter(|(control, count)| *count == 0 && Control::FRETS.contains(control))
+            .map(|(control, _)| name(*control))
+            .collect::<Vec<_>>();
+        if !unused.is_empty() {
+            info!("  Never pressed: {}", unused.join(", "));
+        }
+    }
+}
+
+/// What a control is called on the command line, like `strum-up`
+fn name(control: Control) -> String {
+    control
+        .to_possible_value()
+        .map(|value| value.get_name().to_owned())
+        .unwrap_or_default()
+}
+
+/// `elapsed` in hours, minutes and seconds, leaving off what's zero at the
+/// front
+fn duration(elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs();
+
+    match (seconds / 3600, seconds / 60 % 60, seconds % 60) {
+        (0, 0, seconds) => format!("{}s", seconds),
+        (0, minutes, seconds) => format!("{}m {}s", minutes, seconds),
+        (hours, minutes, seconds) => format!("{}h {}m {}s", hours, minutes, seconds),
+    }
+}
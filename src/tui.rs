@@ -0,0 +1,350 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use evdev::EventType;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Gauge, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use crate::config::Settings;
+use crate::key::{Key, Value};
+use crate::mapping;
+use crate::observe;
+use crate::permissions;
+use crate::profile::{Control, TiltSource, WhammySource};
+use crate::wiitar::Wiitar;
+
+/// How long to wait for the guitar before looking at the keyboard again
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How big the picture of the stick is, in characters
+const STICK_WIDTH: u16 = 21;
+const STICK_HEIGHT: u16 = 9;
+
+/// What the guitar is doing right now, as far as we've been told
+#[derive(Debug, Default)]
+struct State {
+    /// The controls being held down
+    held: Vec<Control>,
+
+    /// The latest value of each axis, like `abs:hat1x@guitar`
+    axes: HashMap<String, i32>,
+
+    /// The lowest and highest value each axis can have
+    ranges: HashMap<String, (i32, i32)>,
+}
+
+impl State {
+    /// Takes note of what `key` says has changed
+    fn update(&mut self, key: Key) {
+        let value = match key.value {
+            Some(Value::Range { current, .. }) => current.min.unwrap_or_default(),
+            _ => return,
+        };
+        let name = Key { value: None, ..key }.to_string();
+
+        let control = Control::ALL
+            .into_iter()
+            .chain([Control::Home])
+            .find(|control| control.button() == Some(name.as_str()));
+
+        match control {
+            Some(control) => {
+                self.held.retain(|&held| held != control);
+                if value != 0 {
+                    self.held.push(control);
+                }
+            }
+            None => {
+                self.axes.insert(name, value);
+            }
+        }
+    }
+
+    fn axis(&self, name: &str) -> i32 {
+        self.axes.get(name).copied().unwrap_or_default()
+    }
+
+    /// How far along its range `name` is, from 0 to 1
+    fn travel(&self, name: &str) -> f64 {
+        let (min, max) = self.ranges.get(name).copied().unwrap_or((0, 1));
+
+        ((self.axis(name) - min) as f64 / (max - min).max(1) as f64).clamp(0.0, 1.0)
+    }
+
+    /// The value of the tilt source, roughly how far the neck is raised in
+    /// degrees going by which way gravity is pulling the accelerometer, and
+    /// whether that's past the tilt threshold
+    fn tilt(&self, settings: &Settings) -> (i32, f64, bool) {
+        let [rx, ry, rz] =
+            ["abs:rx@accel", "abs:ry@accel", "abs:rz@accel"].map(|axis| self.axis(axis) as f64);
+
+        let source = settings.tilt_source.unwrap_or_default();
+        let (value, rest) = match source {
+            TiltSource::Rx => (rx, ry.hypot(rz)),
+            TiltSource::Ry => (ry, rx.hypot(rz)),
+            TiltSource::Rz => (rz, rx.hypot(ry)),
+            TiltSource::Magnitude => (rx.hypot(ry), rz.abs()),
+        };
+
+        let angle = value.atan2(rest).to_degrees();
+        let value = value.round() as i32;
+        let threshold = mapping::tilt_threshold(settings);
+
+        match source.rises() != mapping::tilt_inverted(settings) {
+            true => (value, angle, value >= threshold),
+            false => (value, -angle, value <= threshold),
+        }
+    }
+}
+
+/// Shows what each of the guitar's controls is doing in the terminal, and
+/// whether star power would be triggered, until `q` is pressed or the guitar
+/// goes away. Nothing is grabbed, so it can be used alongside remapping.
+pub fn run(parts: &Wiitar, settings: &Settings) -> Result<()> {
+    let sources = [
+        (
+            mapping::devnode(parts.wiimote.as_ref(), "wiimote")?,
+            "wiimote",
+        ),
+        (
+            mapping::devnode(parts.guitar.as_ref(), "wiimote guitar")?,
+            "guitar",
+        ),
+        (
+            mapping::devnode(parts.accel.as_ref(), "wiimote accelerometer")?,
+            "accel",
+        ),
+    ];
+
+    let mut state = State::default();
+
+    // Start off with where the axes are and how far they go, rather than
+    // waiting for them to move
+    for (path, domain) in &sources {
+        let device = permissions::open(path)?;
+
+        for (code, info) in device
+            .get_absinfo()
+            .with_context(|| format!("couldn't read the axes of {:?}", path))?
+        {
+            let name = format!("{:?}", code).to_lowercase().replacen('_', ":", 1);
+            let name = format!("{}@{}", name, domain);
+
+            state.axes.insert(name.clone(), info.value());
+            state.ranges.insert(name, (info.minimum(), info.maximum()));
+        }
+    }
+
+    // A calibrated whammy bar doesn't go as far as the kernel says it can
+    if let Some(range) = settings
+        .whammy_range
+        .filter(|_| settings.whammy_source.unwrap_or_default() == WhammySource::Bar)
+    {
+        state.ranges.insert(
+            WhammySource::Bar.axis().to_owned(),
+            (range.rest.min(range.full), range.rest.max(range.full)),
+        );
+    }
+
+    let (paths, domains): (Vec<PathBuf>, Vec<&str>) = sources.into_iter().unzip();
+    let receiver = observe::read(paths)?;
+
+    let gone = ratatui::run(|terminal| watch(terminal, &receiver, &domains, state, settings))?;
+    if gone {
+        println!("An input device went away, so we're done here");
+    }
+
+    Ok(())
+}
+
+/// Keeps the terminal up to date with what comes out of `receiver`, and
+/// returns whether it stopped because a device went away
+fn watch(
+    terminal: &mut DefaultTerminal,
+    receiver: &Receiver<io::Result<(usize, evdev::InputEvent)>>,
+    domains: &[&str],
+    mut state: State,
+    settings: &Settings,
+) -> Result<bool> {
+    loop {
+        terminal
+            .draw(|frame| draw(frame, &state, settings))
+            .context("couldn't draw to the terminal")?;
+
+        let mut message = receiver.recv_timeout(POLL_INTERVAL);
+        loop {
+            let (index, event) = match message {
+                Ok(Ok(message)) => message,
+                Ok(Err(error)) if error.raw_os_error() == Some(libc::ENODEV) => return Ok(true),
+                Ok(Err(error)) => return Err(error).context("couldn't read from an input device"),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return Ok(true),
+            };
+
+            if event.event_type() != EventType::SYNCHRONIZATION {
+                if let Some(key) = Key::from_event(&event, domains[index]) {
+                    state.update(key);
+                }
+            }
+
+            // Catch up on everything that's waiting before drawing again
+            message = receiver.try_recv().map_err(|_| RecvTimeoutError::Timeout);
+        }
+
+        if event::poll(Duration::ZERO).context("couldn't read the keyboard")? {
+            if let Event::Key(key) = event::read().context("couldn't read the keyboard")? {
+                let quit = matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+                    || (key.code == KeyCode::Char('c')
+                        && key.modifiers.contains(KeyModifiers::CONTROL));
+
+                if quit && key.kind == KeyEventKind::Press {
+                    return Ok(false);
+                }
+            }
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, state: &State, settings: &Settings) {
+    let [frets, buttons, whammy, bottom, help] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Length(3),
+        Constraint::Length(3),
+        Constraint::Length(STICK_HEIGHT + 2),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let colours = [
+        Color::Green,
+        Color::Red,
+        Color::Yellow,
+        Color::Blue,
+        Color::Rgb(255, 140, 0),
+    ];
+    let fret_areas = Layout::horizontal([Constraint::Ratio(1, 5); 5]).split(frets);
+    for ((fret, colour), area) in Control::FRETS.iter().zip(colours).zip(fret_areas.iter()) {
+        let style = match state.held.contains(fret) {
+            true => Style::new().bg(colour).fg(Color::Black),
+            false => Style::new().fg(colour),
+        };
+
+        frame.render_widget(
+            Paragraph::new(name(*fret))
+                .centered()
+                .style(style)
+                .block(Block::bordered().border_style(Style::new().fg(colour))),
+            *area,
+        );
+    }
+
+    let spans = [
+        Control::StrumUp,
+        Control::StrumDown,
+        Control::Start,
+        Control::Select,
+        Control::Home,
+    ]
+    .into_iter()
+    .flat_map(|control| {
+        let label = format!(" {} ", name(control));
+        [
+            match state.held.contains(&control) {
+                true => Span::from(label).reversed(),
+                false => Span::from(label).dim(),
+            },
+            Span::from(" "),
+        ]
+    })
+    .collect::<Vec<_>>();
+    frame.render_widget(
+        Paragraph::new(Line::from(spans)).block(Block::bordered().title("Buttons")),
+        buttons,
+    );
+
+    let axis = WhammySource::Bar.axis();
+    frame.render_widget(
+        Gauge::default()
+            .block(Block::bordered().title("Whammy"))
+            .ratio(state.travel(axis))
+            .label(state.axis(axis).to_string()),
+        whammy,
+    );
+
+    let [stick, tilt] =
+        Layout::horizontal([Constraint::Length(STICK_WIDTH + 2), Constraint::Min(0)]).areas(bottom);
+    draw_stick(frame, state, stick);
+
+    let (value, angle, tilted) = state.tilt(settings);
+
+    let star_power = match tilted || state.held.contains(&Control::Select) {
+        true => Span::from(" STAR POWER ").bold().black().on_light_cyan(),
+        false => Span::from(" no star power ").dim(),
+    };
+    let lines = vec![
+        Line::from(format!("Neck raised about {:.0}°", angle)),
+        Line::from(format!(
+            "Tilt source at {}, star power at {}",
+            value,
+            mapping::tilt_threshold(settings)
+        )),
+        Line::from(format!(
+            "Accelerometer {} {} {}",
+            state.axis("abs:rx@accel"),
+            state.axis("abs:ry@accel"),
+            state.axis("abs:rz@accel"),
+        )),
+        Line::default(),
+        Line::from(star_power),
+    ];
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title("Tilt")),
+        tilt,
+    );
+
+    frame.render_widget(Line::from("Press q to quit").dim(), help);
+}
+
+/// Draws a dot where the stick is pointing
+fn draw_stick(frame: &mut Frame, state: &State, area: Rect) {
+    let x = (state.travel("abs:x@guitar") * (STICK_WIDTH - 1) as f64).round() as u16;
+    let y = (state.travel("abs:y@guitar") * (STICK_HEIGHT - 1) as f64).round() as u16;
+
+    let lines = (0..STICK_HEIGHT)
+        .map(|row| {
+            (0..STICK_WIDTH)
+                .map(|column| {
+                    if (column, row) == (x, y) {
+                        '●'
+                    } else if (column, row) == (STICK_WIDTH / 2, STICK_HEIGHT / 2) {
+                        '+'
+                    } else {
+                        '·'
+                    }
+                })
+                .collect::<String>()
+        })
+        .map(Line::from)
+        .collect::<Vec<_>>();
+
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title("Stick")),
+        area,
+    );
+}
+
+/// What a control is called on the command line, like `strum-up`
+fn name(control: Control) -> String {
+    control
+        .to_possible_value()
+        .map(|value| value.get_name().to_owned())
+        .unwrap_or_default()
+}
@@ -0,0 +1,354 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use tracing::{debug, info, trace};
+use udev::{Device, Enumerator, Udev};
+
+use crate::failure::Failure;
+
+/// The input devices which make up a Wiimote with a guitar attached
+#[derive(Debug, Default)]
+pub struct Wiitar {
+    pub address: Option<String>,
+    pub wiimote: Option<Device>,
+    pub guitar: Option<Device>,
+    pub accel: Option<Device>,
+    pub ir: Option<Device>,
+    /// The Motion Plus gyroscope, plugged in or built into the Wiimote
+    pub motion_plus: Option<Device>,
+    /// The Wiimote's player LEDs, from left to right
+    pub leds: Vec<Device>,
+    /// The sysfs path of the Wiimote's battery
+    pub battery: Option<PathBuf>,
+}
+
+/// The parts of a Wiimote hid-wiimote creates an input device for, which
+/// we use
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Part {
+    Wiimote,
+    Guitar,
+    Accelerometer,
+    Ir,
+    MotionPlus,
+}
+
+impl Part {
+    /// Which part the input device called `name` is. These names are
+    /// constants in the Linux kernel, and unlikely to change much, if at
+    /// all.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Nintendo Wii Remote" => Some(Part::Wiimote),
+            "Nintendo Wii Remote Guitar" => Some(Part::Guitar),
+            "Nintendo Wii Remote Accelerometer" => Some(Part::Accelerometer),
+            "Nintendo Wii Remote IR" => Some(Part::Ir),
+            "Nintendo Wii Remote Motion Plus" => Some(Part::MotionPlus),
+            _ => None,
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Part::Wiimote => "Wiimote",
+            Part::Guitar => "guitar",
+            Part::Accelerometer => "accelerometer",
+            Part::Ir => "IR camera",
+            Part::MotionPlus => "Motion Plus",
+        }
+    }
+}
+
+impl Wiitar {
+    pub fn from_kernel_name(kernel_name: OsString) -> Result<Self> {
+        let udev = Udev::new().context("couldn't get access to Udev")?;
+
+        Self::from_kernel_name_with_udev(kernel_name, udev)
+    }
+
+    /// Finds the only Wiimote with a guitar attached, for when we haven't
+    /// been told which one to use
+    pub fn find() -> Result<Self> {
+        let udev = Udev::new().context("couldn't get access to Udev")?;
+
+        let guitars = Self::guitars(udev.clone())?;
+
+        match guitars.as_slice() {
+            [guitar] => Self::from_kernel_name_with_udev(guitar.sysname().to_owned(), udev),
+            [] => Err(Failure::NotFound.error("couldn't find a Wiimote with a guitar attached")),
+            _ => Err(Failure::Usage.error(
+                "there's more than one Wiimote with a guitar attached, pick one with --kernel-name",
+            )),
+        }
+    }
+
+    /// The guitar input devices of every connected Wiimote with one attached
+    pub fn guitars(udev: Udev) -> Result<Vec<Device>> {
+        let mut enumerator =
+            Enumerator::with_udev(udev).context("couldn't start a device enumerator")?;
+        enumerator
+            .match_subsystem("input")
+            .context("couldn't set input as device subsystem matcher")?;
+        enumerator
+            .match_attribute("name", "Nintendo Wii Remote Guitar")
+            .context("couldn't set the guitar's name as device attribute matcher")?;
+
+        Ok(enumerator
+            .scan_devices()
+            .context("couldn't scan devices")?
+            .collect())
+    }
+
+    fn from_kernel_name_with_udev(kernel_name: OsString, udev: Udev) -> Result<Self> {
+        let guitar = kernel_name
+            .to_str()
+            .and_then(|sysname| {
+                Device::from_subsystem_sysname_with_context(
+                    udev.clone(),
+                    "input".to_owned(),
+                    sysname.to_owned(),
+                )
+                .ok()
+            })
+            .ok_or_else(|| {
+                Failure::NotFound.error(format!(
+                    "couldn't find an input device called {:?}",
+                    kernel_name
+                ))
+            })?;
+
+        {
+            // First up, we want to bail if this device doesn't pass our basic
+            // sniff test. Theoretically the udev rule should guard against
+            // this too but better to make sure than not!
+            let name = guitar
+                .attribute_value("name")
+                .ok_or_else(|| {
+                    Failure::NotAGuitar.error("This device has no name? That's very strange.")
+                })?
+                .to_string_lossy();
+
+            // Unfortunately, despite an `extension` attribute on the hid-wiimote
+            // driver, it isn't accessible after mount, so we may need to rely on
+            // the display name, which is kind of strange, but if it works?
+            if !name.contains("Wii") || !name.ends_with("Guitar") {
+                return Err(Failure::NotAGuitar
+                    .error("That's a weird looking Wii Guitar (are the udev rules set right?)"));
+            }
+        }
+
+        // Next, we need to look at the parent device. Ultimately we want to
+        // operate on the guitar device's siblings, but to get those we first
+        // need to look at the parent, so, here we go...
+        let wiimote = guitar
+            .parent()
+            .ok_or_else(|| Failure::NotAGuitar.error("guitar didn't have a parent device"))?;
+
+        {
+            // Sanity checks; the parent should be a hid-wiimote device
+            if wiimote
+                .subsystem()
+                .context("The parent of the wiitar didn't have a subsystem")?
+                != "hid"
+            {
+                return Err(
+                    Failure::NotAGuitar.error("The parent of the Wiitar is not a HID device?")
+                );
+            }
+
+            if wiimote
+                .driver()
+                .context("The parent of the wiitar didn't have a driver")?
+                != "wiimote"
+            {
+                return Err(Failure::NotAGuitar
+                    .error("The parent of the Wiitar is an HID device but not a Wiimote?"));
+            }
+        }
+
+        info!(
+            "Looks like {} is a Wiimote, with a guitar attached at {}!",
+            wiimote.sysname().to_string_lossy(),
+            guitar.sysname().to_string_lossy()
+        );
+
+        // Cool, let's get the party started, now we initialise our struct.
+        // The Bluetooth address lets us find any Wiimote-specific settings.
+        let mut inputs = Self {
+            address: guitar
+                .attribute_value("uniq")
+                .map(|uniq| uniq.to_string_lossy().into_owned())
+                .filter(|uniq| !uniq.is_empty()),
+            ..Default::default()
+        };
+
+        // Everything else we need is somewhere under the Wiimote, so one
+        // look at all of that is enough
+        let mut enumerator =
+            Enumerator::with_udev(udev).context("couldn't start a device enumerator")?;
+        enumerator
+            .match_parent(&wiimote)
+            .context("couldn't set wiimote as parent device matcher")?;
+        let descendants: Vec<Device> = enumerator
+            .scan_devices()
+            .context("couldn't scan the Wiimote's devices")?
+            .filter(|device| device.syspath() != wiimote.syspath())
+            .collect();
+
+        for device in
+            children(&descendants, &wiimote).filter(|device| in_subsystem(device, "input"))
+        {
+            let sysname = device.sysname().to_string_lossy().into_owned();
+
+            // Like mentioned above, the name is the best we can match these
+            // on
+            let name = match device.attribute_value("name") {
+                Some(os_name) => os_name.to_string_lossy().into_owned(),
+                None => {
+                    debug!("Skipping {}, which has no name", sysname);
+                    continue;
+                }
+            };
+
+            let found = match Part::from_name(&name) {
+                Some(Part::Wiimote) => &mut inputs.wiimote,
+                Some(Part::Guitar) => &mut inputs.guitar,
+                Some(Part::Accelerometer) => &mut inputs.accel,
+                Some(Part::Ir) => &mut inputs.ir,
+                Some(Part::MotionPlus) => &mut inputs.motion_plus,
+                None => {
+                    debug!("Skipping {}, {:?} isn't part of a Wiitar", sysname, name);
+                    continue;
+                }
+            };
+
+            if found.is_some() {
+                debug!("Skipping {}, we already have a {:?}", sysname, name);
+                continue;
+            }
+
+            let event_device = children(&descendants, device)
+                .find(|child| {
+                    trace!(
+                        "Considering {} as the event device for {}",
+                        child.sysname().to_string_lossy(),
+                        sysname
+                    );
+
+                    child.sysname().to_string_lossy().starts_with("event")
+                })
+                .ok_or_else(|| Failure::MissingPart.error("didn't find a child event device"))?;
+            debug!(
+                "Using {} as the {:?}, at {}",
+                sysname,
+                name,
+                event_device.sysname().to_string_lossy()
+            );
+            *found = Some(event_device.clone());
+        }
+
+        if !inputs.is_complete() {
+            return Err(Failure::MissingPart
+                .error("Failed to find wiimote, guitar and accelerometer input devices"));
+        }
+
+        if inputs.ir.is_none() {
+            debug!("The Wiimote has no IR camera, so there's no pointer");
+        }
+        if inputs.motion_plus.is_none() {
+            debug!("The Wiimote has no Motion Plus, so there's no gyroscope");
+        }
+
+        inputs.battery = descendants
+            .iter()
+            .find(|device| in_subsystem(device, "power_supply"))
+            .map(|battery| battery.syspath().to_path_buf());
+        if inputs.battery.is_none() {
+            debug!("Couldn't find the Wiimote's battery");
+        }
+
+        // hid-wiimote names its LEDs after the Wiimote, ending `p0` to `p3`
+        inputs.leds = descendants
+            .iter()
+            .filter(|device| {
+                in_subsystem(device, "leds")
+                    && device.sysname().to_string_lossy().contains(":blue:p")
+            })
+            .cloned()
+            .collect();
+        inputs.leds.sort_by_key(|led| led.sysname().to_owned());
+
+        Ok(inputs)
+    }
+
+    /// Asks BlueZ to disconnect the Wiimote, so it turns itself off
+    pub fn disconnect(&self) -> Result<()> {
+        let address = self
+            .address
+            .as_deref()
+            .ok_or(anyhow!("couldn't tell which Wiimote this is"))?;
+
+        let status = Command::new("bluetoothctl")
+            .args(["disconnect", address])
+            .stdout(Stdio::null())
+            .status()
+            .context("couldn't run bluetoothctl")?;
+
+        if !status.success() {
+            bail!("bluetoothctl couldn't disconnect {}", address);
+        }
+
+        Ok(())
+    }
+
+    /// Lights the Wiimote's LED for `player`, from 1 to 4, and turns the
+    /// others off
+    pub fn set_player(&self, player: u8) -> Result<()> {
+        if !(1..=4).contains(&player) {
+            bail!("the player should be from 1 to 4");
+        }
+
+        if self.leds.is_empty() {
+            bail!("couldn't find the Wiimote's player LEDs");
+        }
+
+        for (index, led) in self.leds.iter().enumerate() {
+            let brightness = match index + 1 == player as usize {
+                true => "1",
+                false => "0",
+            };
+
+            led.clone()
+                .set_attribute_value("brightness", brightness)
+                .with_context(|| {
+                    format!(
+                        "couldn't set the brightness of {}",
+                        led.sysname().to_string_lossy()
+                    )
+                })?;
+        }
+
+        Ok(())
+    }
+
+    fn is_complete(&self) -> bool {
+        self.wiimote.is_some() && self.guitar.is_some() && self.accel.is_some()
+    }
+}
+
+/// Those of `devices` whose parent is `parent`
+fn children<'a>(devices: &'a [Device], parent: &'a Device) -> impl Iterator<Item = &'a Device> {
+    devices.iter().filter(move |device| {
+        device
+            .parent()
+            .is_some_and(|device_parent| device_parent.syspath() == parent.syspath())
+    })
+}
+
+/// Whether `device` is in `subsystem`
+fn in_subsystem(device: &Device, subsystem: &str) -> bool {
+    device
+        .subsystem()
+        .is_some_and(|device_subsystem| device_subsystem == subsystem)
+}
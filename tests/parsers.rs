@@ -0,0 +1,259 @@
+//! Property tests for everything roadii parses from the command line and
+//! its configuration and calibration files: nothing should make it panic,
+//! and whatever it writes back out should parse to the same thing
+
+use proptest::prelude::*;
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Display};
+use std::str::FromStr;
+
+use roadii::calibration::{Area, Calibration, DeviceCalibration, Span};
+use roadii::config::Config;
+use roadii::key::{EventType, Key, Range, Value};
+use roadii::pipeline::{AxisRange, AxisTransform};
+use roadii::profile::{
+    AxisButton, ButtonAxis, Chord, ChordBinding, EventMap, KeyBinding, LongPressBinding,
+    MacroBinding, Merge,
+};
+
+/// Strings made of what the mapping syntax is made of, which get further
+/// into the parsers than any old string would
+fn mapping_text() -> impl Strategy<Value = String> {
+    "[a-zA-Z0-9:@~.+=,x\\-_ ]{0,40}"
+}
+
+/// Tries `s` as everything roadii parses from a string, for any of them to
+/// panic
+fn parse_everything(s: &str) {
+    let _ = Key::from_str(s);
+    let _ = Value::from_str(s);
+    let _ = Range::from_str(s);
+    let _ = Span::from_str(s);
+    let _ = Area::from_str(s);
+    let _ = AxisRange::from_str(s);
+    let _ = AxisTransform::from_str(s);
+    let _ = KeyBinding::from_str(s);
+    let _ = Chord::from_str(s);
+    let _ = ChordBinding::from_str(s);
+    let _ = LongPressBinding::from_str(s);
+    let _ = MacroBinding::from_str(s);
+    let _ = AxisButton::from_str(s);
+    let _ = ButtonAxis::from_str(s);
+    let _ = EventMap::from_str(s);
+    let _ = Merge::from_str(s);
+}
+
+/// Checks that if `s` parses as a `T`, writing it back out parses to the
+/// same `T`
+fn round_trip<T>(s: &str) -> Result<(), TestCaseError>
+where
+    T: FromStr + Display + PartialEq + Debug,
+    T::Err: Display,
+{
+    let Ok(parsed) = s.parse::<T>() else {
+        return Ok(());
+    };
+
+    let written = parsed.to_string();
+    match written.parse::<T>() {
+        Ok(again) => prop_assert_eq!(again, parsed, "{:?} was written out as {:?}", s, written),
+        Err(error) => prop_assert!(false, "{:?} was written out as {:?}: {}", s, written, error),
+    }
+
+    Ok(())
+}
+
+fn range() -> impl Strategy<Value = Range> {
+    (any::<Option<i32>>(), any::<Option<i32>>()).prop_map(|(min, max)| Range { min, max })
+}
+
+fn value() -> impl Strategy<Value = Value> {
+    let finite = -1e9..1e9f64;
+
+    prop_oneof![
+        (proptest::option::of(range()), range())
+            .prop_map(|(previous, current)| Value::Range { previous, current }),
+        (finite.clone(), finite, any::<bool>()).prop_map(|(factor, offset, delta)| {
+            Value::Linear {
+                factor,
+                offset,
+                delta,
+            }
+        }),
+    ]
+}
+
+/// Keys with an event type and code roadii knows about, and anything for
+/// the rest
+fn key() -> impl Strategy<Value = Key> {
+    let code = prop_oneof![
+        (
+            Just(EventType::Key),
+            prop::sample::select(vec!["a", "f1", "enter", "space"])
+        ),
+        (
+            Just(EventType::Btn),
+            prop::sample::select(vec!["south", "east", "mode", "tl2"])
+        ),
+        (
+            Just(EventType::Abs),
+            prop::sample::select(vec!["x", "ry", "hat0x", "hat1y"])
+        ),
+        (
+            Just(EventType::Rel),
+            prop::sample::select(vec!["x", "wheel"])
+        ),
+    ];
+
+    (
+        code,
+        proptest::option::of(value()),
+        proptest::option::of("[a-z][a-z0-9-]{0,10}"),
+    )
+        .prop_map(|((event_type, code), value, domain)| Key {
+            event_type: Some(event_type),
+            code: Some(code.to_owned()),
+            value,
+            domain,
+        })
+}
+
+fn span() -> impl Strategy<Value = Span> {
+    (any::<i32>(), any::<i32>())
+        .prop_filter("a span has to go somewhere", |(rest, full)| rest != full)
+        .prop_map(|(rest, full)| Span { rest, full })
+}
+
+fn area() -> impl Strategy<Value = Area> {
+    (any::<i32>(), any::<i32>(), any::<i32>(), any::<i32>())
+        .prop_filter(
+            "an area has to cover something",
+            |(left, top, right, bottom)| left != right && top != bottom,
+        )
+        .prop_map(|(left, top, right, bottom)| Area {
+            left,
+            top,
+            right,
+            bottom,
+        })
+}
+
+fn calibration() -> impl Strategy<Value = Calibration> {
+    let device = (proptest::option::of(span()), proptest::option::of(area()))
+        .prop_map(|(whammy, pointer)| DeviceCalibration { whammy, pointer });
+    let address = "([0-9A-F]{2}:){5}[0-9A-F]{2}";
+
+    prop::collection::btree_map(address, device, 0..4)
+        .prop_map(|devices: BTreeMap<String, DeviceCalibration>| Calibration { devices })
+}
+
+#[test]
+fn numbers_which_dont_round_trip_are_refused() {
+    for s in ["NaNx", "x+NaN", "infd", "-infx", "x-inf"] {
+        assert!(s.parse::<Value>().is_err(), "{:?} parsed", s);
+    }
+    for s in ["rx=scale:NaN", "rx=offset:inf"] {
+        assert!(s.parse::<AxisTransform>().is_err(), "{:?} parsed", s);
+    }
+}
+
+#[test]
+fn axis_overrides_with_a_domain_or_value_are_refused() {
+    for s in ["x@guitar=0:10", "x:5=0:10", "=0:10"] {
+        assert!(s.parse::<AxisRange>().is_err(), "{:?} parsed", s);
+    }
+    for s in ["x@guitar=invert", "x:5=scale:2"] {
+        assert!(s.parse::<AxisTransform>().is_err(), "{:?} parsed", s);
+    }
+    assert!("x=0:10".parse::<AxisRange>().is_ok());
+}
+
+#[test]
+fn misspelled_settings_are_refused() {
+    for s in [
+        "whamy-range = \"0:100\"",
+        "[devices.\"00:11:22:33:44:55\"]\nwhamy-range = \"0:100\"",
+    ] {
+        assert!(Config::parse(s).is_err(), "{:?} parsed", s);
+    }
+    assert!(Config::parse("[devices.\"00:11:22:33:44:55\"]\nwhammy-range = \"0:100\"").is_ok());
+}
+
+proptest! {
+    #[test]
+    fn nothing_panics_on_any_string(s in any::<String>()) {
+        parse_everything(&s);
+    }
+
+    #[test]
+    fn nothing_panics_on_mapping_like_strings(s in mapping_text()) {
+        parse_everything(&s);
+    }
+
+    #[test]
+    fn any_config_file_parses_without_panicking(s in any::<String>()) {
+        let _ = Config::parse(&s);
+    }
+
+    #[test]
+    fn any_setting_parses_without_panicking(
+        name in prop::sample::select(vec![
+            "profile", "preset", "exit-chord", "pause-chord", "keys", "chords", "long-presses",
+            "macros", "axis-buttons", "button-axes", "maps", "merges", "axis-ranges",
+            "axis-transforms", "whammy-range", "pointer-area", "stick-deadzone",
+            "stick-center", "player", "nice",
+        ]),
+        text in mapping_text(),
+        list in any::<bool>(),
+    ) {
+        let value = match list {
+            true => format!("[{:?}]", text),
+            false => format!("{:?}", text),
+        };
+
+        let _ = Config::parse(&format!("{} = {}", name, value));
+        let _ = Config::parse(&format!("[devices.\"00:11:22:33:44:55\"]\n{} = {}", name, value));
+    }
+
+    #[test]
+    fn whatever_parses_round_trips(s in mapping_text()) {
+        round_trip::<Key>(&s)?;
+        round_trip::<Value>(&s)?;
+        round_trip::<Range>(&s)?;
+        round_trip::<Span>(&s)?;
+        round_trip::<Area>(&s)?;
+    }
+
+    #[test]
+    fn ranges_round_trip(range in range()) {
+        prop_assert_eq!(range.to_string().parse::<Range>().unwrap(), range);
+    }
+
+    #[test]
+    fn values_round_trip(value in value()) {
+        prop_assert_eq!(value.to_string().parse::<Value>().unwrap(), value);
+    }
+
+    #[test]
+    fn keys_round_trip(key in key()) {
+        prop_assert_eq!(key.to_string().parse::<Key>().unwrap(), key);
+    }
+
+    #[test]
+    fn spans_round_trip(span in span()) {
+        prop_assert_eq!(span.to_string().parse::<Span>().unwrap(), span);
+    }
+
+    #[test]
+    fn areas_round_trip(area in area()) {
+        prop_assert_eq!(area.to_string().parse::<Area>().unwrap(), area);
+    }
+
+    #[test]
+    fn calibrations_round_trip(calibration in calibration()) {
+        let written = toml::to_string_pretty(&calibration).unwrap();
+        let read: Calibration = toml::from_str(&written).unwrap();
+
+        prop_assert_eq!(toml::to_string_pretty(&read).unwrap(), written);
+    }
+}